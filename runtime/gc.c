@@ -0,0 +1,175 @@
+// A conservative mark and sweep collector, linked into programs compiled
+// with managed memory. `alloc`, `alloc_array` and the String runtime
+// allocate through `lang_gc_alloc`. The roots are the stack, the registers
+// and the globals registered by `lang.init`. Any word that points into an
+// allocation keeps it alive, so pointers don't need to be tracked by the
+// compiler and cycles are collected like everything else. The stack is
+// scanned up to glibc's `__libc_stack_end`, so managed programs need glibc
+// and run the collector on the main thread only.
+
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef struct {
+    size_t size;
+    int marked;
+} Block;
+
+// Keeps the payload as aligned as `malloc` would.
+#define HEADER_SIZE ((sizeof(Block) + 15) & ~(size_t)15)
+
+typedef struct {
+    const char *start;
+    size_t size;
+} Root;
+
+#ifndef __GLIBC__
+#error "the collector finds the bottom of the stack through glibc's __libc_stack_end"
+#endif
+
+// The bottom of the main thread's stack, provided by glibc.
+extern void *__libc_stack_end;
+
+// Appended as they are allocated and sorted by address when a collection
+// starts, so the block a word points into is a binary search.
+static Block **blocks;
+static size_t block_count, block_capacity;
+static Root *roots;
+static size_t root_count, root_capacity;
+static Block **worklist;
+static size_t worklist_count, worklist_capacity;
+// Bytes in the blocks alive after the last collection plus the ones
+// allocated since, a collection runs once they exceed the threshold.
+static size_t allocated;
+static size_t threshold = 1 << 20;
+
+static void *grow(void *array, size_t *capacity, size_t item_size) {
+    *capacity = *capacity ? *capacity * 2 : 64;
+    array = realloc(array, *capacity * item_size);
+    if (!array) {
+        fprintf(stderr, "Runtime error: Out of memory\n");
+        exit(1);
+    }
+    return array;
+}
+
+static char *payload(Block *block) {
+    return (char *)block + HEADER_SIZE;
+}
+
+// The block `address` points into, a pointer one past the end counts.
+static Block *find_block(uintptr_t address) {
+    size_t low = 0, high = block_count;
+    while (low < high) {
+        size_t mid = low + (high - low) / 2;
+        if ((uintptr_t)payload(blocks[mid]) <= address) {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    if (low == 0) {
+        return NULL;
+    }
+    Block *block = blocks[low - 1];
+    return address <= (uintptr_t)payload(block) + block->size ? block : NULL;
+}
+
+static void mark_range(const char *start, const char *end) {
+    uintptr_t word_size = sizeof(uintptr_t);
+    const char *p = (const char *)(((uintptr_t)start + word_size - 1) & ~(word_size - 1));
+    for (; p + word_size <= end; p += word_size) {
+        uintptr_t word;
+        memcpy(&word, p, word_size);
+        Block *block = find_block(word);
+        if (block && !block->marked) {
+            block->marked = 1;
+            if (worklist_count == worklist_capacity) {
+                worklist = grow(worklist, &worklist_capacity, sizeof(Block *));
+            }
+            worklist[worklist_count++] = block;
+        }
+    }
+}
+
+// Not inlined so its frame is below the registers spilled by the caller.
+static __attribute__((noinline)) void mark_stack(void) {
+    void *top = NULL;
+    mark_range((const char *)&top, __libc_stack_end);
+}
+
+static int compare_blocks(const void *a, const void *b) {
+    uintptr_t x = (uintptr_t)*(Block *const *)a, y = (uintptr_t)*(Block *const *)b;
+    return (x > y) - (x < y);
+}
+
+void lang_gc_collect(void) {
+    // the sweep keeps the order, only the blocks allocated since the last
+    // collection are out of place
+    qsort(blocks, block_count, sizeof(Block *), compare_blocks);
+    // spills the callee-saved registers onto the stack
+    __builtin_unwind_init();
+    mark_stack();
+    for (size_t i = 0; i < root_count; i++) {
+        mark_range(roots[i].start, roots[i].start + roots[i].size);
+    }
+    while (worklist_count > 0) {
+        Block *block = worklist[--worklist_count];
+        mark_range(payload(block), payload(block) + block->size);
+    }
+
+    size_t live = 0;
+    allocated = 0;
+    for (size_t i = 0; i < block_count; i++) {
+        Block *block = blocks[i];
+        if (block->marked) {
+            block->marked = 0;
+            allocated += block->size;
+            blocks[live++] = block;
+        } else {
+            free(block);
+        }
+    }
+    block_count = live;
+    threshold = allocated * 2 > threshold ? allocated * 2 : threshold;
+}
+
+void *lang_gc_alloc(int64_t size) {
+    if (size < 0) {
+        fprintf(stderr, "Runtime error: Cannot allocate %lld bytes\n", (long long)size);
+        exit(1);
+    }
+    if (allocated + size > threshold) {
+        lang_gc_collect();
+    }
+    // zeroed, so stale data can't keep other blocks alive
+    Block *block = calloc(1, HEADER_SIZE + size);
+    if (!block) {
+        fprintf(stderr, "Runtime error: Out of memory\n");
+        exit(1);
+    }
+    block->size = size;
+    allocated += size;
+
+    if (block_count == block_capacity) {
+        blocks = grow(blocks, &block_capacity, sizeof(Block *));
+    }
+    blocks[block_count++] = block;
+    return payload(block);
+}
+
+// Scans `size` bytes at `start` for pointers in every collection.
+void lang_gc_root(const void *start, int64_t size) {
+    if (root_count == root_capacity) {
+        roots = grow(roots, &root_capacity, sizeof(Root));
+    }
+    roots[root_count++] = (Root){start, size};
+}
+
+// The bytes in allocations that survived the last collection or were made
+// after it.
+int64_t lang_gc_allocated(void) {
+    return allocated;
+}
@@ -0,0 +1,16 @@
+
+// Called by programs compiled with overflow checks when a check fails.
+// Reports where the program was and aborts, so the failure isn't mistaken
+// for a regular exit code.
+
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+void lang_panic(const char *message, const char *function, const char *file, int32_t line,
+                int32_t column) {
+    fflush(stdout);
+    fprintf(stderr, "Runtime error: %s\n    at %s (%s:%d:%d)\n", message, function, file,
+            line, column);
+    abort();
+}
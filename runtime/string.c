@@ -0,0 +1,231 @@
+// Runtime support for the `String` type, compiled and linked into every
+// program by the compiler.
+
+#include <ctype.h>
+#include <errno.h>
+#include <stdarg.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <strings.h>
+
+// With managed memory the data of Strings is owned by the collector in
+// `gc.c`, which frees it once it is unreachable.
+#ifdef LANG_MANAGED
+void *lang_gc_alloc(int64_t size);
+#define lang_malloc(size) lang_gc_alloc(size)
+#else
+#define lang_malloc(size) malloc(size)
+#endif
+
+// Mirrors the `{ ptr, i64, i64 }` struct the compiler emits. A capacity of 0
+// marks a string literal, which is never written to. Strings are UTF-8 and
+// their length is in bytes. The data is NUL terminated, unless a push grew
+// another copy of the String in place, see `lang_string_c_str`.
+typedef struct {
+    char *data;
+    int64_t len;
+    int64_t cap;
+} LangString;
+
+static void lang_string_error(const char *message, const LangString *s) {
+    fprintf(stderr, "Runtime error: %s '%.*s'\n", message, (int)s->len, s->data);
+    exit(1);
+}
+
+static void lang_string_alloc(LangString *out, int64_t len) {
+    out->data = lang_malloc(len + 1);
+    out->data[len] = 0;
+    out->len = len;
+    out->cap = len;
+}
+
+void lang_string_concat(LangString *out, const LangString *a, const LangString *b) {
+    LangString result;
+    lang_string_alloc(&result, a->len + b->len);
+    memcpy(result.data, a->data, a->len);
+    memcpy(result.data + a->len, b->data, b->len);
+    *out = result;
+}
+
+int32_t lang_string_eq(const LangString *a, const LangString *b) {
+    return a->len == b->len && memcmp(a->data, b->data, a->len) == 0;
+}
+
+// Whether `index` doesn't split the UTF-8 encoding of a character.
+static int lang_string_is_boundary(const LangString *s, int64_t index) {
+    return index == s->len || ((unsigned char)s->data[index] & 0xC0) != 0x80;
+}
+
+void lang_string_slice(LangString *out, const LangString *s, int64_t start, int64_t end) {
+    if (start < 0 || start > end || end > s->len) {
+        fprintf(stderr, "Runtime error: Slice %lld..%lld out of range for String of length %lld\n",
+                (long long)start, (long long)end, (long long)s->len);
+        exit(1);
+    }
+    if (!lang_string_is_boundary(s, start) || !lang_string_is_boundary(s, end)) {
+        fprintf(stderr, "Runtime error: Slice %lld..%lld is not on a character boundary\n",
+                (long long)start, (long long)end);
+        exit(1);
+    }
+    LangString result;
+    lang_string_alloc(&result, end - start);
+    memcpy(result.data, s->data + start, end - start);
+    *out = result;
+}
+
+// Buffers grown by `lang_string_push` have spare capacity, and start with
+// how many bytes of it are used. Other buffers are exactly as long as their
+// String.
+static int64_t *lang_string_used(const LangString *s) {
+    return (int64_t *)(s->data - sizeof(int64_t));
+}
+
+// Copies of a String share its data, so a push only writes into the buffer
+// when the String ends where the used bytes do, otherwise it would overwrite
+// what another copy pushed. The capacity doubles when it runs out. The old
+// buffer is left to the copies that may still point at it, like the result
+// of any other String operation.
+void lang_string_push(LangString *s, const LangString *tail) {
+    if (tail->len == 0) {
+        return;
+    }
+    int64_t len = s->len + tail->len;
+    if (s->cap > s->len && len <= s->cap && *lang_string_used(s) == s->len) {
+        memcpy(s->data + s->len, tail->data, tail->len);
+        s->data[len] = 0;
+        *lang_string_used(s) = len;
+        s->len = len;
+        return;
+    }
+
+    int64_t cap = s->cap * 2 > len ? s->cap * 2 : len;
+    cap = cap < 16 ? 16 : cap;
+    char *data = (char *)lang_malloc(sizeof(int64_t) + cap + 1) + sizeof(int64_t);
+    memcpy(data, s->data, s->len);
+    memcpy(data + s->len, tail->data, tail->len);
+    data[len] = 0;
+    s->data = data;
+    s->len = len;
+    s->cap = cap;
+    *lang_string_used(s) = len;
+}
+
+// The data of `s` as a C string. A copy that another one grew past in place
+// isn't terminated at its length anymore, only its data is copied.
+const char *lang_string_c_str(const LangString *s) {
+    if (s->data[s->len] == 0) {
+        return s->data;
+    }
+    LangString result;
+    lang_string_alloc(&result, s->len);
+    memcpy(result.data, s->data, s->len);
+    return result.data;
+}
+
+static void lang_string_format(LangString *out, const char *format, ...) {
+    va_list args;
+    va_start(args, format);
+    int len = vsnprintf(NULL, 0, format, args);
+    va_end(args);
+
+    lang_string_alloc(out, len);
+    va_start(args, format);
+    vsnprintf(out->data, len + 1, format, args);
+    va_end(args);
+}
+
+void lang_string_from_i64(LangString *out, int64_t value) {
+    lang_string_format(out, "%lld", (long long)value);
+}
+
+void lang_string_from_u64(LangString *out, uint64_t value) {
+    lang_string_format(out, "%llu", (unsigned long long)value);
+}
+
+void lang_string_from_f64(LangString *out, double value) {
+    lang_string_format(out, "%g", value);
+}
+
+void lang_string_from_bool(LangString *out, int32_t value) {
+    lang_string_format(out, "%s", value ? "true" : "false");
+}
+
+void lang_string_from_char(LangString *out, int32_t value) {
+    lang_string_format(out, "%c", value);
+}
+
+// A NUL terminated copy of the data for the C parsers, freed by the caller.
+static char *lang_string_dup(const LangString *s) {
+    char *data = malloc(s->len + 1);
+    memcpy(data, s->data, s->len);
+    data[s->len] = 0;
+    return data;
+}
+
+int64_t lang_string_parse_int(const LangString *s) {
+    char *data = lang_string_dup(s), *end;
+    errno = 0;
+    long long value = strtoll(data, &end, 10);
+    int valid = s->len > 0 && !isspace((unsigned char)data[0]) && end == data + s->len &&
+                errno == 0;
+    free(data);
+    if (!valid) {
+        lang_string_error("Invalid integer", s);
+    }
+    return value;
+}
+
+// What the interpreter parses too: decimal digits with an optional sign,
+// fraction and exponent, or `inf`, `infinity` and `nan` in any case. `strtod`
+// alone also takes hexadecimal and `nan(...)`.
+static int lang_string_is_float(const LangString *s) {
+    const char *data = s->data;
+    int64_t i = 0, digits = 0;
+    if (i < s->len && (data[i] == '+' || data[i] == '-')) {
+        i++;
+    }
+    const char *words[] = {"inf", "infinity", "nan"};
+    for (size_t w = 0; w < sizeof(words) / sizeof(words[0]); w++) {
+        int64_t n = strlen(words[w]);
+        if (s->len - i == n && strncasecmp(data + i, words[w], n) == 0) {
+            return 1;
+        }
+    }
+    for (; i < s->len && isdigit((unsigned char)data[i]); i++) {
+        digits++;
+    }
+    if (i < s->len && data[i] == '.') {
+        for (i++; i < s->len && isdigit((unsigned char)data[i]); i++) {
+            digits++;
+        }
+    }
+    if (digits == 0) {
+        return 0;
+    }
+    if (i < s->len && (data[i] == 'e' || data[i] == 'E')) {
+        i++;
+        if (i < s->len && (data[i] == '+' || data[i] == '-')) {
+            i++;
+        }
+        int64_t exponent = 0;
+        for (; i < s->len && isdigit((unsigned char)data[i]); i++) {
+            exponent++;
+        }
+        if (exponent == 0) {
+            return 0;
+        }
+    }
+    return i == s->len;
+}
+
+double lang_string_parse_float(const LangString *s) {
+    if (!lang_string_is_float(s)) {
+        lang_string_error("Invalid float", s);
+    }
+    char *data = lang_string_dup(s);
+    double value = strtod(data, NULL);
+    free(data);
+    return value;
+}
@@ -1,18 +1,23 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use inkwell::{
     AddressSpace, FloatPredicate, IntPredicate,
     basic_block::BasicBlock,
     builder::Builder,
     context::Context,
-    module::Module,
-    types::BasicTypeEnum,
-    values::{BasicValueEnum, FunctionValue, InstructionOpcode, PointerValue},
+    intrinsics::Intrinsic,
+    module::{Linkage, Module},
+    types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum, FunctionType, StructType},
+    values::{
+        BasicMetadataValueEnum, BasicValue, BasicValueEnum, FunctionValue, InstructionOpcode,
+        IntValue, PointerValue, StructValue,
+    },
 };
 
 use crate::{
-    parser::{Expr, Statement, Stmt, Type},
-    token::{Literal, NumericType, Operator},
+    heap::Memory,
+    parser::{Expr, FormatPart, MatchArm, Param, Pattern, Statement, Stmt, Type, Variant},
+    token::{FormatKind, FormatSpec, Literal, Location, NumericType, NumericValue, Operator},
 };
 
 pub struct CodeGen<'ctx> {
@@ -24,15 +29,66 @@ pub struct CodeGen<'ctx> {
     current_block: BasicBlock<'ctx>,
 
     variables: HashMap<String, (PointerValue<'ctx>, BasicTypeEnum<'ctx>)>,
+    variable_types: HashMap<String, Type>,
+    globals: HashMap<String, (PointerValue<'ctx>, BasicTypeEnum<'ctx>)>,
+    global_types: HashMap<String, Type>,
+    /// The `Type::Function` of every declared function.
+    function_types: HashMap<String, Type>,
+    externs: HashSet<String>,
+    enums: HashMap<String, (StructType<'ctx>, Vec<Variant>)>,
+    /// Generic functions, compiled once for every list of type arguments
+    /// they are called with.
+    generic_functions: HashMap<String, Stmt>,
+    /// The type arguments of the generic instance being compiled.
+    type_bindings: HashMap<String, Type>,
+    memory: Memory,
+    /// Whether integer `+`, `-` and `*` panic when they overflow instead of
+    /// wrapping around.
+    overflow_checks: bool,
+    source_name: String,
+    /// Where the statement being compiled is, reported by panics.
+    location: Location,
 }
 
+/// The C runtime linked into every compiled program.
+pub const RUNTIME: &str = concat!(
+    include_str!("../runtime/string.c"),
+    include_str!("../runtime/panic.c")
+);
+
+/// The collector linked into programs compiled with [`Memory::Managed`],
+/// `RUNTIME` has to be compiled with `LANG_MANAGED` defined next to it.
+pub const GC_RUNTIME: &str = include_str!("../runtime/gc.c");
+
+/// The largest range pattern lowered to individual `switch` cases.
+const MAX_SWITCH_RANGE: i128 = 256;
+
+/// Functions implemented by the runtime instead of being declared in the
+/// program.
+const BUILTINS: &[&str] = &[
+    "len",
+    "slice",
+    "push",
+    "to_string",
+    "parse_int",
+    "parse_float",
+    "c_str",
+    "wrapping_add",
+    "wrapping_sub",
+    "wrapping_mul",
+    "saturating_add",
+    "saturating_sub",
+    "saturating_mul",
+];
+
 impl<'ctx> CodeGen<'ctx> {
     pub fn new(context: &'ctx Context) -> Self {
         let builder = context.create_builder();
         let module = context.create_module("main");
         let i32_type = context.i32_type();
         let main_function_type = i32_type.fn_type(&[], false);
-        let main_function = module.add_function("", main_function_type, None);
+        // runs the file scope statements, called at the start of `main`
+        let main_function = module.add_function("lang.init", main_function_type, None);
         let entry_block = context.append_basic_block(main_function, "entry");
 
         Self {
@@ -44,9 +100,39 @@ impl<'ctx> CodeGen<'ctx> {
             current_block: entry_block,
 
             variables: HashMap::new(),
+            variable_types: HashMap::new(),
+            globals: HashMap::new(),
+            global_types: HashMap::new(),
+            function_types: HashMap::new(),
+            externs: HashSet::new(),
+            enums: HashMap::new(),
+            generic_functions: HashMap::new(),
+            type_bindings: HashMap::new(),
+            memory: Memory::Manual,
+            overflow_checks: false,
+            source_name: "<script>".to_string(),
+            location: Location::default(),
         }
     }
 
+    /// Makes integer `+`, `-`, `*` and negation call `lang_panic` when they
+    /// overflow, they wrap around otherwise. Division and remainder by zero
+    /// or of `MIN` by -1, and shifts by the bit width or more panic as well.
+    pub fn set_overflow_checks(&mut self, overflow_checks: bool) {
+        self.overflow_checks = overflow_checks;
+    }
+
+    /// The file name panics report their location in.
+    pub fn set_source_name(&mut self, name: &str) {
+        self.source_name = name.to_string();
+    }
+
+    /// With managed memory allocations go through the collector in
+    /// [`GC_RUNTIME`] and `free` isn't available.
+    pub fn set_memory(&mut self, memory: Memory) {
+        self.memory = memory;
+    }
+
     fn switch_block(&mut self, block: BasicBlock<'ctx>) {
         self.current_block = block;
         self.builder.position_at_end(block);
@@ -67,15 +153,1432 @@ impl<'ctx> CodeGen<'ctx> {
                 NumericType::F64 => self.context.f64_type().into(),
             },
             Type::Named(name) => match name.as_str() {
-                "String" => self.context.ptr_type(AddressSpace::default()).into(),
+                "String" => self.string_type().into(),
                 "char" => self.context.i8_type().into(),
-                _ => anyhow::bail!("Unknown named type: {}", name),
+                _ => match self.enums.get(name) {
+                    Some((enum_type, _)) => (*enum_type).into(),
+                    None => anyhow::bail!("Unknown named type: {}", name),
+                },
+            },
+            Type::Pointer(_) | Type::Function { .. } => {
+                self.context.ptr_type(AddressSpace::default()).into()
+            }
+            Type::Closure { .. } => self.closure_type().into(),
+            Type::Param(name) => match self.type_bindings.get(name) {
+                Some(ty) => self.get_basic_type(&ty.clone())?,
+                None => anyhow::bail!("Unbound type parameter: {}", name),
             },
-            Type::Pointer(_) => self.context.ptr_type(AddressSpace::default()).into(),
             _ => anyhow::bail!("Unsupported type: {:?}", ty),
         })
     }
 
+    /// A closure is the function compiled from the lambda and a pointer to
+    /// the values it captured, which the function gets as its first argument.
+    fn closure_type(&self) -> StructType<'ctx> {
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        self.context
+            .struct_type(&[ptr_type.into(), ptr_type.into()], false)
+    }
+
+    /// The LLVM type of a function taking `params` and returning `ty`.
+    fn function_type(&self, params: &[Type], ty: &Type) -> anyhow::Result<FunctionType<'ctx>> {
+        let variadic = params.last() == Some(&Type::Variadic);
+        let params = params
+            .iter()
+            .filter(|x| **x != Type::Variadic)
+            .map(|x| self.get_basic_type(x).map(Into::into))
+            .collect::<anyhow::Result<Vec<BasicMetadataTypeEnum>>>()?;
+        Ok(match ty {
+            Type::Void => self.context.void_type().fn_type(&params, variadic),
+            ty => self.get_basic_type(ty)?.fn_type(&params, variadic),
+        })
+    }
+
+    /// Compiles the body of a lambda to a function of its own and copies the
+    /// captured variables to the stack of the current one.
+    fn compile_lambda(
+        &mut self,
+        params: &[Param],
+        ty: &Type,
+        body: &[Statement],
+        value: Option<&Expr>,
+        captures: &[Param],
+    ) -> anyhow::Result<Option<BasicValueEnum<'ctx>>> {
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        let capture_types = captures
+            .iter()
+            .map(|x| self.get_basic_type(&x.ty))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let captures_type = self.context.struct_type(&capture_types, false);
+        let captures_ptr = if captures.is_empty() {
+            ptr_type.const_null()
+        } else {
+            let ptr = self.builder.build_alloca(captures_type, "captures")?;
+            for (index, capture) in captures.iter().enumerate() {
+                let value = self
+                    .compile_expr(&Expr::Variable(capture.name.clone()))?
+                    .ok_or_else(|| anyhow::anyhow!("Cannot capture a void value"))?;
+                let field = self.builder.build_struct_gep(
+                    captures_type,
+                    ptr,
+                    index as u32,
+                    &capture.name,
+                )?;
+                self.builder.build_store(field, value)?;
+            }
+            ptr
+        };
+
+        let params: Vec<Param> = params
+            .iter()
+            .map(|x| Param {
+                name: x.name.clone(),
+                ty: self.resolve(&x.ty),
+            })
+            .collect();
+        let ty = self.resolve(ty);
+        let param_types: Vec<Type> = std::iter::once(Type::Pointer(Box::new(Type::Void)))
+            .chain(params.iter().map(|x| x.ty.clone()))
+            .collect();
+        let function_type = self.function_type(&param_types, &ty)?;
+        let parent = self.current_block.get_parent().unwrap();
+        let name = format!("{}.lambda", parent.get_name().to_str()?);
+        let function = self
+            .module
+            .add_function(&name, function_type, Some(Linkage::Private));
+
+        let saved_vars = self.variables.clone();
+        let saved_types = self.variable_types.clone();
+        let capture_types: Vec<(String, Type)> = captures
+            .iter()
+            .map(|x| (x.name.clone(), self.resolve(&x.ty)))
+            .collect();
+        self.variables = self.globals.clone();
+        self.variable_types = self.global_types.clone();
+        let last_block = self.current_block;
+        let location = self.location;
+        let block = self.context.append_basic_block(function, "entry");
+        self.switch_block(block);
+
+        let result = self.compile_lambda_body(
+            function,
+            captures_type,
+            &capture_types,
+            &params,
+            &ty,
+            body,
+            value,
+        );
+
+        self.variables = saved_vars;
+        self.variable_types = saved_types;
+        self.switch_block(last_block);
+        self.location = location;
+        result?;
+
+        let closure = self.closure_type().get_undef();
+        let closure = self.builder.build_insert_value(
+            closure,
+            function.as_global_value().as_pointer_value(),
+            0,
+            "closure",
+        )?;
+        let closure = self
+            .builder
+            .build_insert_value(closure, captures_ptr, 1, "closure")?;
+        Ok(Some(closure.as_basic_value_enum()))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn compile_lambda_body(
+        &mut self,
+        function: FunctionValue<'ctx>,
+        captures_type: StructType<'ctx>,
+        captures: &[(String, Type)],
+        params: &[Param],
+        ty: &Type,
+        body: &[Statement],
+        value: Option<&Expr>,
+    ) -> anyhow::Result<()> {
+        // the captured variables are read where the closure keeps them
+        let captures_ptr = function.get_nth_param(0).unwrap().into_pointer_value();
+        for (index, (name, ty)) in captures.iter().enumerate() {
+            let field =
+                self.builder
+                    .build_struct_gep(captures_type, captures_ptr, index as u32, name)?;
+            self.variables
+                .insert(name.clone(), (field, self.get_basic_type(ty)?));
+            self.variable_types.insert(name.clone(), ty.clone());
+        }
+        for (index, param) in params.iter().enumerate() {
+            let basic_type = self.get_basic_type(&param.ty)?;
+            let ptr = self.builder.build_alloca(basic_type, &param.name)?;
+            let func_param = function.get_nth_param(index as u32 + 1).unwrap();
+            self.builder.build_store(ptr, func_param)?;
+            self.variables.insert(param.name.clone(), (ptr, basic_type));
+            self.variable_types
+                .insert(param.name.clone(), param.ty.clone());
+        }
+
+        for statement in body {
+            self.compile_statement(statement, function)?;
+        }
+        if self.current_block.get_terminator().is_none() {
+            match value {
+                Some(value) => {
+                    let value = self.compile_expr(value)?;
+                    self.builder
+                        .build_return(value.as_ref().map(|x| x as &dyn BasicValue))?;
+                }
+                None if *ty == Type::Void => {
+                    self.builder.build_return(None)?;
+                }
+                None => {
+                    self.builder.build_unreachable()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces the type parameters of the generic instance being compiled.
+    fn resolve(&self, ty: &Type) -> Type {
+        ty.substitute(&self.type_bindings)
+    }
+
+    /// The type parameters of the generic function `name` bound to
+    /// `type_arguments`.
+    fn generic_bindings(&self, name: &str, type_arguments: &[Type]) -> HashMap<String, Type> {
+        match self.generic_functions.get(name) {
+            Some(Stmt::Func { type_params, .. }) => type_params
+                .iter()
+                .cloned()
+                .zip(type_arguments.iter().map(|x| self.resolve(x)))
+                .collect(),
+            _ => HashMap::new(),
+        }
+    }
+
+    /// Compiles the generic function `name` for `type_arguments` unless that
+    /// was done before, and returns the mangled name of the instance, like
+    /// `max<f64>`.
+    fn instantiate(&mut self, name: &str, type_arguments: &[Type]) -> anyhow::Result<String> {
+        let type_arguments: Vec<Type> = type_arguments.iter().map(|x| self.resolve(x)).collect();
+        let mangled = format!(
+            "{name}<{}>",
+            type_arguments
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        if self.module.get_function(&mangled).is_some() {
+            return Ok(mangled);
+        }
+
+        let bindings = self.generic_bindings(name, &type_arguments);
+        let Some(Stmt::Func {
+            params, ty, body, ..
+        }) = self.generic_functions.get(name).cloned()
+        else {
+            anyhow::bail!("Generic function not found: {}", name);
+        };
+        let instance = Stmt::Func {
+            name: mangled.clone(),
+            type_params: Vec::new(),
+            params: params
+                .into_iter()
+                .map(|x| Param {
+                    ty: x.ty.substitute(&bindings),
+                    ..x
+                })
+                .collect(),
+            ty: ty.substitute(&bindings),
+            body,
+        };
+        // the statement calling it goes on after the instance is compiled
+        let location = self.location;
+        let outer_bindings = std::mem::replace(&mut self.type_bindings, bindings);
+        let result = self.compile_stmt(&instance, self.main_function);
+        self.type_bindings = outer_bindings;
+        self.location = location;
+        result?;
+        Ok(mangled)
+    }
+
+    /// An upper bound of the size of `ty` in bytes, rounded up to 8.
+    fn payload_size(&self, ty: &Type) -> u32 {
+        match ty {
+            Type::Named(name) if name == "String" => 24,
+            Type::Named(name) => match self.enums.get(name) {
+                Some((enum_type, _)) => {
+                    8 + 8 * enum_type
+                        .get_field_type_at_index(1)
+                        .map_or(0, |x| x.into_array_type().len())
+                }
+                None => 8,
+            },
+            _ => 8,
+        }
+    }
+
+    /// Lowers an enum to `{ tag: i32, payload: [N x i64] }`, with the payload
+    /// large enough for the fields of every variant.
+    fn declare_enum(&mut self, name: &str, variants: &[Variant]) -> anyhow::Result<()> {
+        let words = variants
+            .iter()
+            .map(|x| x.fields.iter().map(|x| self.payload_size(x)).sum::<u32>() / 8)
+            .max()
+            .unwrap_or(0);
+        let enum_type = self.context.opaque_struct_type(name);
+        enum_type.set_body(
+            &[
+                self.context.i32_type().into(),
+                self.context.i64_type().array_type(words).into(),
+            ],
+            false,
+        );
+        self.enums
+            .insert(name.to_string(), (enum_type, variants.to_vec()));
+        Ok(())
+    }
+
+    /// The tag and payload layout of `enum_name::variant`.
+    fn variant_layout(
+        &self,
+        enum_name: &str,
+        variant: &str,
+    ) -> anyhow::Result<(StructType<'ctx>, u64, StructType<'ctx>, Vec<Type>)> {
+        let (enum_type, variants) = self
+            .enums
+            .get(enum_name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown enum: {}", enum_name))?;
+        let index = variants
+            .iter()
+            .position(|x| x.name == variant)
+            .ok_or_else(|| anyhow::anyhow!("Unknown variant: {}::{}", enum_name, variant))?;
+        let fields = variants[index].fields.clone();
+        let field_types = fields
+            .iter()
+            .map(|x| self.get_basic_type(x))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let payload_type = self.context.struct_type(&field_types, false);
+        Ok((*enum_type, index as u64, payload_type, fields))
+    }
+
+    /// Continues in a new block if `condition` holds, jumping to `fail`
+    /// otherwise.
+    fn branch_or_fail(
+        &mut self,
+        condition: IntValue<'ctx>,
+        fail: BasicBlock<'ctx>,
+    ) -> anyhow::Result<()> {
+        let function = self.current_block.get_parent().unwrap();
+        let next = self.context.append_basic_block(function, "match_test");
+        self.builder
+            .build_conditional_branch(condition, next, fail)?;
+        self.switch_block(next);
+        Ok(())
+    }
+
+    /// Tests the value of type `ty` at `ptr` against `pattern`, jumping to
+    /// `fail` if it doesn't match. Variables bound by the pattern point into
+    /// the tested value.
+    fn compile_pattern(
+        &mut self,
+        pattern: &Pattern,
+        ptr: PointerValue<'ctx>,
+        ty: &Type,
+        fail: BasicBlock<'ctx>,
+        bindings: &mut Vec<(String, PointerValue<'ctx>, Type)>,
+    ) -> anyhow::Result<()> {
+        match pattern {
+            Pattern::Wildcard => {}
+            Pattern::Binding(name) => bindings.push((name.clone(), ptr, ty.clone())),
+            Pattern::Literal(literal) => {
+                let value =
+                    self.builder
+                        .build_load(self.get_basic_type(ty)?, ptr, "match_value")?;
+                let expected = self.compile_expr(&Expr::Literal(literal.clone()))?.unwrap();
+                let condition = match (value, expected) {
+                    (BasicValueEnum::IntValue(value), BasicValueEnum::IntValue(expected)) => self
+                        .builder
+                        .build_int_compare(IntPredicate::EQ, value, expected, "match_eq")?,
+                    (BasicValueEnum::FloatValue(value), BasicValueEnum::FloatValue(expected)) => {
+                        self.builder.build_float_compare(
+                            FloatPredicate::OEQ,
+                            value,
+                            expected,
+                            "match_eq",
+                        )?
+                    }
+                    (value @ BasicValueEnum::StructValue(_), expected) => {
+                        let expected = self.spill(expected)?;
+                        let i32_type = self.context.i32_type();
+                        let eq = self
+                            .call_runtime(
+                                "lang_string_eq",
+                                Some(i32_type.into()),
+                                &[self.spill(value)?.into(), expected.into()],
+                            )?
+                            .unwrap()
+                            .into_int_value();
+                        self.builder.build_int_compare(
+                            IntPredicate::NE,
+                            eq,
+                            i32_type.const_zero(),
+                            "match_eq",
+                        )?
+                    }
+                    _ => anyhow::bail!("Unsupported literal pattern {:?}", literal),
+                };
+                self.branch_or_fail(condition, fail)?;
+            }
+            Pattern::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                let value = self
+                    .builder
+                    .build_load(self.get_basic_type(ty)?, ptr, "match_value")?
+                    .into_int_value();
+                let start = self.compile_expr(&Expr::Literal(start.clone()))?.unwrap();
+                let end = self.compile_expr(&Expr::Literal(end.clone()))?.unwrap();
+                let signed = matches!(ty, Type::Numeric(numeric) if numeric.is_signed());
+                let (lower, upper) = match (signed, inclusive) {
+                    (true, true) => (IntPredicate::SGE, IntPredicate::SLE),
+                    (true, false) => (IntPredicate::SGE, IntPredicate::SLT),
+                    (false, true) => (IntPredicate::UGE, IntPredicate::ULE),
+                    (false, false) => (IntPredicate::UGE, IntPredicate::ULT),
+                };
+                let above = self.builder.build_int_compare(
+                    lower,
+                    value,
+                    start.into_int_value(),
+                    "match_start",
+                )?;
+                let below = self.builder.build_int_compare(
+                    upper,
+                    value,
+                    end.into_int_value(),
+                    "match_end",
+                )?;
+                let condition = self.builder.build_and(above, below, "match_range")?;
+                self.branch_or_fail(condition, fail)?;
+            }
+            Pattern::Variant {
+                enum_name,
+                variant,
+                fields,
+            } => {
+                let (enum_type, index, payload_type, field_types) =
+                    self.variant_layout(enum_name, variant)?;
+                let i32_type = self.context.i32_type();
+                let tag_ptr = self.builder.build_struct_gep(enum_type, ptr, 0, "tag")?;
+                let tag = self
+                    .builder
+                    .build_load(i32_type, tag_ptr, "tag")?
+                    .into_int_value();
+                let condition = self.builder.build_int_compare(
+                    IntPredicate::EQ,
+                    tag,
+                    i32_type.const_int(index, false),
+                    "match_tag",
+                )?;
+                self.branch_or_fail(condition, fail)?;
+
+                if !fields.is_empty() {
+                    let payload = self
+                        .builder
+                        .build_struct_gep(enum_type, ptr, 1, "payload")?;
+                    for (index, (field, ty)) in fields.iter().zip(&field_types).enumerate() {
+                        let field_ptr = self.builder.build_struct_gep(
+                            payload_type,
+                            payload,
+                            index as u32,
+                            "field",
+                        )?;
+                        self.compile_pattern(field, field_ptr, ty, fail, bindings)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The values each arm of an integer or char match handles, if all of its
+    /// patterns are literals or small ranges. An irrefutable last arm handles
+    /// no values, it becomes the default of the `switch`.
+    fn switch_cases(&self, arms: &[MatchArm], ty: &Type) -> Option<Vec<Vec<i128>>> {
+        match ty {
+            Type::Numeric(numeric) if numeric.is_integer() => {}
+            Type::Named(name) if name == "char" => {}
+            _ => return None,
+        }
+        let value = |literal: &Literal| match literal {
+            Literal::Numeric(literal) => match literal.value {
+                NumericValue::Integer(value) => Some(value),
+                NumericValue::Float(_) => None,
+            },
+            Literal::Char(c) => Some(*c as u8 as i128),
+            _ => None,
+        };
+
+        let mut cases = Vec::new();
+        for (index, arm) in arms.iter().enumerate() {
+            cases.push(match &arm.pattern {
+                Pattern::Wildcard | Pattern::Binding(_) if index == arms.len() - 1 => Vec::new(),
+                Pattern::Literal(literal) => vec![value(literal)?],
+                Pattern::Range {
+                    start,
+                    end,
+                    inclusive,
+                } => {
+                    let start = value(start)?;
+                    let end = value(end)? - i128::from(!inclusive);
+                    if end - start >= MAX_SWITCH_RANGE {
+                        return None;
+                    }
+                    (start..=end).collect()
+                }
+                _ => return None,
+            });
+        }
+        Some(cases)
+    }
+
+    /// Binds the variables of a matching arm and compiles its body, adding
+    /// its value to `incoming` unless it returned.
+    fn compile_arm(
+        &mut self,
+        arm: &MatchArm,
+        bindings: Vec<(String, PointerValue<'ctx>, Type)>,
+        merge_block: BasicBlock<'ctx>,
+        incoming: &mut Vec<(Option<BasicValueEnum<'ctx>>, BasicBlock<'ctx>)>,
+    ) -> anyhow::Result<()> {
+        let function = self.current_block.get_parent().unwrap();
+        let saved_vars = self.variables.clone();
+        let saved_types = self.variable_types.clone();
+        for (name, ptr, ty) in bindings {
+            self.variables
+                .insert(name.clone(), (ptr, self.get_basic_type(&ty)?));
+            self.variable_types.insert(name, ty);
+        }
+        for statement in &arm.body {
+            self.compile_statement(statement, function)?;
+        }
+        if self.current_block.get_terminator().is_none() {
+            let value = match &arm.value {
+                Some(value) => self.compile_expr(value)?,
+                None => None,
+            };
+            incoming.push((value, self.current_block));
+            self.builder.build_unconditional_branch(merge_block)?;
+        }
+        self.variables = saved_vars;
+        self.variable_types = saved_types;
+        Ok(())
+    }
+
+    /// Lowers a match on an integer or char with only literal and small
+    /// range patterns to a `switch`, which the backend can turn into a jump
+    /// table. Any other match tries its arms in order, each in its own block.
+    /// The values of the arms are merged like the branches of an if
+    /// expression.
+    fn compile_match(
+        &mut self,
+        scrutinee: &Expr,
+        arms: &[MatchArm],
+    ) -> anyhow::Result<Option<BasicValueEnum<'ctx>>> {
+        let function = self.current_block.get_parent().unwrap();
+        let ty = self
+            .expr_type(scrutinee)
+            .ok_or_else(|| anyhow::anyhow!("Cannot match on a value of unknown type"))?;
+        let value = self
+            .compile_expr(scrutinee)?
+            .ok_or_else(|| anyhow::anyhow!("Cannot match on a void value"))?;
+        let ptr = self.spill(value)?;
+        let merge_block = self.context.append_basic_block(function, "match_merge");
+
+        let mut incoming = Vec::new();
+        if let Some(cases) = self.switch_cases(arms, &ty) {
+            let int_type = value.get_type().into_int_type();
+            let default_block = self.context.append_basic_block(function, "switch_default");
+            let mut seen = HashSet::new();
+            let mut switch_cases = Vec::new();
+            let mut blocks = Vec::new();
+            for (arm, values) in arms.iter().zip(&cases) {
+                let block = match arm.pattern {
+                    Pattern::Wildcard | Pattern::Binding(_) => default_block,
+                    _ => self.context.append_basic_block(function, "switch_case"),
+                };
+                // a value belongs to the first arm that handles it
+                for value in values {
+                    if seen.insert(*value) {
+                        switch_cases.push((int_type.const_int(*value as u64, false), block));
+                    }
+                }
+                blocks.push(block);
+            }
+            self.builder
+                .build_switch(value.into_int_value(), default_block, &switch_cases)?;
+
+            for (arm, block) in arms.iter().zip(blocks) {
+                self.switch_block(block);
+                let bindings = match &arm.pattern {
+                    Pattern::Binding(name) => vec![(name.clone(), ptr, ty.clone())],
+                    _ => Vec::new(),
+                };
+                self.compile_arm(arm, bindings, merge_block, &mut incoming)?;
+            }
+            if default_block.get_terminator().is_none() {
+                self.switch_block(default_block);
+                self.builder.build_unreachable()?;
+            }
+        } else {
+            for arm in arms {
+                let next_block = self.context.append_basic_block(function, "match_next");
+                let mut bindings = Vec::new();
+                self.compile_pattern(&arm.pattern, ptr, &ty, next_block, &mut bindings)?;
+                self.compile_arm(arm, bindings, merge_block, &mut incoming)?;
+                self.switch_block(next_block);
+            }
+            // the semantic analyzer checked that some arm matches
+            self.builder.build_unreachable()?;
+        }
+
+        self.switch_block(merge_block);
+        if incoming.is_empty() {
+            self.builder.build_unreachable()?;
+            return Ok(None);
+        }
+        let Some(incoming) = incoming
+            .into_iter()
+            .map(|(value, block)| value.map(|value| (value, block)))
+            .collect::<Option<Vec<_>>>()
+        else {
+            return Ok(None);
+        };
+
+        let phi = self
+            .builder
+            .build_phi(incoming[0].0.get_type(), "tmpmatch")?;
+        for (value, block) in &incoming {
+            phi.add_incoming(&[(value, *block)]);
+        }
+        Ok(Some(phi.as_basic_value()))
+    }
+
+    /// The source level type of `expr`, as far as it can be recovered after
+    /// lowering. Used where LLVM's integer types lose the signedness.
+    fn expr_type(&self, expr: &Expr) -> Option<Type> {
+        match expr {
+            Expr::Literal(Literal::Numeric(lit)) => Some(Type::Numeric(lit.ty())),
+            Expr::Literal(Literal::Char(_)) => Some(Type::Named("char".to_string())),
+            Expr::Literal(Literal::String(_)) | Expr::Interpolated { .. } => {
+                Some(Type::Named("String".to_string()))
+            }
+            Expr::Literal(Literal::Boolean(_)) => Some(Type::Boolean),
+            Expr::Null { ty } => Some(self.resolve(ty)),
+            Expr::Variable(name) => self
+                .variable_types
+                .get(name)
+                .or_else(|| self.function_types.get(name))
+                .cloned(),
+            Expr::Binary {
+                operator:
+                    Operator::Equals
+                    | Operator::NotEquals
+                    | Operator::Less
+                    | Operator::LessEquals
+                    | Operator::Greater
+                    | Operator::GreaterEquals
+                    | Operator::LogicalAnd
+                    | Operator::LogicalOr,
+                ..
+            } => Some(Type::Boolean),
+            Expr::Binary { left, .. } => self.expr_type(left),
+            Expr::Unary {
+                operator: Operator::Ampersand,
+                operand,
+            } => Some(Type::Pointer(Box::new(self.expr_type(operand)?))),
+            Expr::Unary {
+                operator: Operator::Asterisk,
+                operand,
+            } => match self.expr_type(operand)? {
+                Type::Pointer(inner) => Some(*inner),
+                _ => None,
+            },
+            Expr::Unary { operand, .. } => self.expr_type(operand),
+            Expr::FunctionCall {
+                name,
+                type_arguments,
+                ..
+            } if BUILTINS.contains(&name.as_str()) || name == "alloc" || name == "alloc_array" => {
+                match name.as_str() {
+                    "len" => Some(Type::Numeric(NumericType::USize)),
+                    "parse_int" => Some(Type::Numeric(NumericType::I64)),
+                    "parse_float" => Some(Type::Numeric(NumericType::F64)),
+                    "slice" | "to_string" => Some(Type::Named("String".to_string())),
+                    "c_str" => Some(Type::Pointer(Box::new(Type::Numeric(NumericType::I8)))),
+                    "alloc" | "alloc_array" => Some(Type::Pointer(Box::new(
+                        self.resolve(type_arguments.first()?),
+                    ))),
+                    // `wrapping_add` and the other integer builtins
+                    _ if !type_arguments.is_empty() => Some(self.resolve(&type_arguments[0])),
+                    _ => Some(Type::Void),
+                }
+            }
+            Expr::FunctionCall {
+                name,
+                type_arguments,
+                ..
+            } => match self.generic_functions.get(name) {
+                Some(Stmt::Func { ty, .. }) => {
+                    Some(ty.substitute(&self.generic_bindings(name, type_arguments)))
+                }
+                _ => match self.function_types.get(name) {
+                    Some(Type::Function { ty, .. }) => Some(*ty.clone()),
+                    _ => None,
+                },
+            },
+            Expr::MethodCall { .. } => None,
+            Expr::Call { callee, .. } => match self.expr_type(callee)? {
+                Type::Function { ty, .. } | Type::Closure { ty, .. } => Some(*ty),
+                _ => None,
+            },
+            Expr::Lambda { params, ty, .. } => Some(self.resolve(&Type::Closure {
+                params: params.iter().map(|x| x.ty.clone()).collect(),
+                ty: Box::new(ty.clone()),
+            })),
+            Expr::Assignment { target, .. } => self.expr_type(target),
+            Expr::If { then_value, .. } => self.expr_type(then_value),
+            Expr::Variant { enum_name, .. } => Some(Type::Named(enum_name.clone())),
+            Expr::Match { arms, .. } => arms
+                .iter()
+                .find_map(|x| x.value.as_ref().and_then(|x| self.expr_type(x))),
+        }
+    }
+
+    /// The `printf` conversion for one interpolated value, and the value
+    /// converted to what the conversion expects.
+    fn format_arg(
+        &self,
+        value: BasicValueEnum<'ctx>,
+        ty: Option<Type>,
+        spec: &FormatSpec,
+    ) -> anyhow::Result<(String, BasicMetadataValueEnum<'ctx>)> {
+        let mut conversion = String::from("%");
+        if spec.left_align {
+            conversion.push('-');
+        }
+        if spec.zero_pad {
+            conversion.push('0');
+        }
+        if let Some(width) = spec.width {
+            conversion.push_str(&width.to_string());
+        }
+        if let Some(precision) = spec.precision {
+            conversion.push_str(&format!(".{precision}"));
+        }
+
+        let i32_type = self.context.i32_type();
+        let (specifier, arg): (&str, BasicMetadataValueEnum) = match value {
+            BasicValueEnum::PointerValue(ptr) => ("s", ptr.into()),
+            BasicValueEnum::StructValue(string) => ("s", self.c_str(string)?.into()),
+            BasicValueEnum::FloatValue(val) => {
+                let val = self
+                    .builder
+                    .build_float_cast(val, self.context.f64_type(), "fmtext")?;
+                let specifier = match (spec.kind, spec.precision) {
+                    (FormatKind::Exponent, _) => "e",
+                    (_, Some(_)) => "f",
+                    (_, None) => "g",
+                };
+                (specifier, val.into())
+            }
+            BasicValueEnum::IntValue(val) if val.get_type().get_bit_width() == 1 => {
+                let true_str = self.builder.build_global_string_ptr("true", "true")?;
+                let false_str = self.builder.build_global_string_ptr("false", "false")?;
+                let string = self.builder.build_select(
+                    val,
+                    true_str.as_pointer_value(),
+                    false_str.as_pointer_value(),
+                    "fmtbool",
+                )?;
+                ("s", string.into())
+            }
+            BasicValueEnum::IntValue(val) if ty == Some(Type::Named("char".to_string())) => {
+                let val = self
+                    .builder
+                    .build_int_z_extend_or_bit_cast(val, i32_type, "fmtchar")?;
+                ("c", val.into())
+            }
+            BasicValueEnum::IntValue(val) => {
+                let signed = match ty {
+                    Some(Type::Numeric(numeric)) => numeric.is_signed(),
+                    _ => true,
+                };
+                let long = val.get_type().get_bit_width() == 64;
+                let specifier = match (spec.kind, signed, long) {
+                    (FormatKind::Display, true, false) => "d",
+                    (FormatKind::Display, false, false) => "u",
+                    (FormatKind::LowerHex, _, false) => "x",
+                    (FormatKind::UpperHex, _, false) => "X",
+                    (FormatKind::Octal, _, false) => "o",
+                    (FormatKind::Display, true, true) => "lld",
+                    (FormatKind::Display, false, true) => "llu",
+                    (FormatKind::LowerHex, _, true) => "llx",
+                    (FormatKind::UpperHex, _, true) => "llX",
+                    (FormatKind::Octal, _, true) => "llo",
+                    (FormatKind::Exponent, ..) => {
+                        anyhow::bail!("Cannot format an integer as Exponent")
+                    }
+                };
+                let val = if long {
+                    val
+                } else if signed && spec.kind == FormatKind::Display {
+                    self.builder
+                        .build_int_s_extend_or_bit_cast(val, i32_type, "fmtext")?
+                } else {
+                    self.builder
+                        .build_int_z_extend_or_bit_cast(val, i32_type, "fmtext")?
+                };
+                (specifier, val.into())
+            }
+            _ => anyhow::bail!("Cannot format value {:?}", value),
+        };
+
+        conversion.push_str(specifier);
+        Ok((conversion, arg))
+    }
+
+    /// Frees the data of `value` if `expr` is an interpolation, whose buffer
+    /// nothing else refers to once it was used as an operand. With managed
+    /// memory the collector reclaims it instead.
+    fn free_temporary(&self, expr: &Expr, value: BasicValueEnum<'ctx>) -> anyhow::Result<()> {
+        let data = match (expr, value) {
+            (Expr::Interpolated { .. }, BasicValueEnum::StructValue(string)) => self
+                .builder
+                .build_extract_value(string, 0, "data")?
+                .into_pointer_value(),
+            // `c_str("...")` passed to an extern function
+            (
+                Expr::FunctionCall {
+                    name, arguments, ..
+                },
+                BasicValueEnum::PointerValue(data),
+            ) if name == "c_str" && matches!(arguments[..], [Expr::Interpolated { .. }]) => data,
+            _ => return Ok(()),
+        };
+        if self.memory == Memory::Manual {
+            self.builder.build_free(data)?;
+        }
+        Ok(())
+    }
+
+    /// `malloc`, or the collector's `lang_gc_alloc` with managed memory.
+    fn allocator(&self) -> FunctionValue<'ctx> {
+        let name = match self.memory {
+            Memory::Manual => "malloc",
+            Memory::Managed => "lang_gc_alloc",
+        };
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        let i64_type = self.context.i64_type();
+        self.module.get_function(name).unwrap_or_else(|| {
+            self.module
+                .add_function(name, ptr_type.fn_type(&[i64_type.into()], false), None)
+        })
+    }
+
+    /// Formats `args` into a newly allocated String with `snprintf`.
+    fn build_format(
+        &self,
+        format: &str,
+        args: &[BasicMetadataValueEnum<'ctx>],
+    ) -> anyhow::Result<BasicValueEnum<'ctx>> {
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        let i64_type = self.context.i64_type();
+        let snprintf = self.module.get_function("snprintf").unwrap_or_else(|| {
+            self.module.add_function(
+                "snprintf",
+                self.context
+                    .i32_type()
+                    .fn_type(&[ptr_type.into(), i64_type.into(), ptr_type.into()], true),
+                None,
+            )
+        });
+        let malloc = self.allocator();
+
+        let format = self.builder.build_global_string_ptr(format, "fmt")?;
+        let mut call_args = vec![
+            ptr_type.const_null().into(),
+            i64_type.const_zero().into(),
+            format.as_pointer_value().into(),
+        ];
+        call_args.extend_from_slice(args);
+
+        let len = self
+            .builder
+            .build_call(snprintf, &call_args, "fmtlen")?
+            .try_as_basic_value()
+            .basic()
+            .unwrap()
+            .into_int_value();
+        let len = self.builder.build_int_s_extend(len, i64_type, "fmtlen")?;
+        let size = self
+            .builder
+            .build_int_add(len, i64_type.const_int(1, false), "fmtsize")?;
+        let buffer = self
+            .builder
+            .build_call(malloc, &[size.into()], "fmtbuf")?
+            .try_as_basic_value()
+            .basic()
+            .unwrap();
+
+        call_args[0] = buffer.into();
+        call_args[1] = size.into();
+        self.builder.build_call(snprintf, &call_args, "fmt")?;
+
+        let string = self.string_type().get_undef();
+        let string = self
+            .builder
+            .build_insert_value(string, buffer, 0, "fmtstr")?;
+        let string = self.builder.build_insert_value(string, len, 1, "fmtstr")?;
+        let string = self.builder.build_insert_value(string, len, 2, "fmtstr")?;
+        Ok(string.as_basic_value_enum())
+    }
+
+    /// Lowers a file scope `let` or `static` to a global variable. Literal
+    /// values become the initializer, anything else is stored when
+    /// `lang.init` runs.
+    fn compile_global(
+        &mut self,
+        name: &str,
+        ty: Option<&Type>,
+        value: &Expr,
+    ) -> anyhow::Result<()> {
+        let init_val = self
+            .compile_expr(value)?
+            .ok_or_else(|| anyhow::anyhow!("Global '{}' has no value", name))?;
+        let val_type = init_val.get_type();
+        let global = self.module.add_global(val_type, None, name);
+        global.set_linkage(Linkage::Internal);
+        if matches!(value, Expr::Literal(_)) {
+            global.set_initializer(&init_val);
+        } else {
+            global.set_initializer(&val_type.const_zero());
+            self.builder
+                .build_store(global.as_pointer_value(), init_val)?;
+        }
+
+        let ptr = global.as_pointer_value();
+        if self.memory == Memory::Managed {
+            // the collector doesn't know where the globals are
+            let size = val_type
+                .size_of()
+                .ok_or_else(|| anyhow::anyhow!("Global '{}' has no size", name))?;
+            self.call_runtime("lang_gc_root", None, &[ptr.into(), size.into()])?;
+        }
+        self.globals.insert(name.to_string(), (ptr, val_type));
+        self.variables.insert(name.to_string(), (ptr, val_type));
+        if let Some(ty) = ty.cloned().or_else(|| self.expr_type(value)) {
+            self.global_types.insert(name.to_string(), ty.clone());
+            self.variable_types.insert(name.to_string(), ty);
+        }
+        Ok(())
+    }
+
+    /// `{ data: *i8, len: i64, cap: i64 }`, see `runtime/string.c`.
+    fn string_type(&self) -> StructType<'ctx> {
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        let i64_type = self.context.i64_type();
+        self.context
+            .struct_type(&[ptr_type.into(), i64_type.into(), i64_type.into()], false)
+    }
+
+    /// Allocates a stack slot in the entry block of the current function, so
+    /// a loop reuses it instead of growing the stack every iteration. The
+    /// collector would also keep everything the old slots point at alive.
+    fn build_entry_alloca(
+        &self,
+        ty: impl BasicType<'ctx>,
+        name: &str,
+    ) -> anyhow::Result<PointerValue<'ctx>> {
+        let entry = self
+            .current_block
+            .get_parent()
+            .and_then(|x| x.get_first_basic_block())
+            .ok_or_else(|| anyhow::anyhow!("No function to allocate '{}' in", name))?;
+        let builder = self.context.create_builder();
+        match entry.get_first_instruction() {
+            Some(first) => builder.position_before(&first),
+            None => builder.position_at_end(entry),
+        }
+        Ok(builder.build_alloca(ty, name)?)
+    }
+
+    /// Stores `value` in a new stack slot, runtime functions take Strings by
+    /// pointer.
+    fn spill(&self, value: BasicValueEnum<'ctx>) -> anyhow::Result<PointerValue<'ctx>> {
+        let ptr = self.build_entry_alloca(value.get_type(), "spill")?;
+        self.builder.build_store(ptr, value)?;
+        Ok(ptr)
+    }
+
+    fn call_runtime(
+        &self,
+        name: &str,
+        ret: Option<BasicTypeEnum<'ctx>>,
+        args: &[BasicValueEnum<'ctx>],
+    ) -> anyhow::Result<Option<BasicValueEnum<'ctx>>> {
+        let params: Vec<BasicMetadataTypeEnum> = args.iter().map(|x| x.get_type().into()).collect();
+        let fn_type = match ret {
+            Some(ret) => ret.fn_type(&params, false),
+            None => self.context.void_type().fn_type(&params, false),
+        };
+        let function = self
+            .module
+            .get_function(name)
+            .unwrap_or_else(|| self.module.add_function(name, fn_type, None));
+        let args: Vec<BasicMetadataValueEnum> = args.iter().map(|x| (*x).into()).collect();
+        Ok(self
+            .builder
+            .build_call(function, &args, name)?
+            .try_as_basic_value()
+            .basic())
+    }
+
+    /// The data of `string`, NUL terminated by `lang_string_c_str` if a push
+    /// grew a copy of it past its length.
+    fn c_str(&self, string: StructValue<'ctx>) -> anyhow::Result<BasicValueEnum<'ctx>> {
+        let string = self.spill(string.into())?;
+        let ptr_type = self.context.ptr_type(AddressSpace::default());
+        self.call_runtime("lang_string_c_str", Some(ptr_type.into()), &[string.into()])?
+            .ok_or_else(|| anyhow::anyhow!("'lang_string_c_str' returned nothing"))
+    }
+
+    /// Calls a runtime function that writes its resulting String through an
+    /// extra first argument.
+    fn call_string_runtime(
+        &self,
+        name: &str,
+        args: &[BasicValueEnum<'ctx>],
+    ) -> anyhow::Result<BasicValueEnum<'ctx>> {
+        let out = self.build_entry_alloca(self.string_type(), "str")?;
+        let mut all_args = vec![out.into()];
+        all_args.extend_from_slice(args);
+        self.call_runtime(name, None, &all_args)?;
+        Ok(self.builder.build_load(self.string_type(), out, "str")?)
+    }
+
+    /// `alloc<T>()` and `alloc_array<T>(n)` call `malloc` with the size of
+    /// `T`, `free(p)` calls `free`. With managed memory they allocate from
+    /// the collector instead.
+    fn compile_heap_builtin(
+        &mut self,
+        name: &str,
+        type_arguments: &[Type],
+        arguments: &[Expr],
+    ) -> anyhow::Result<Option<BasicValueEnum<'ctx>>> {
+        let mut args = Vec::new();
+        for arg in arguments {
+            args.push(
+                self.compile_expr(arg)?
+                    .ok_or_else(|| anyhow::anyhow!("Void value passed to function '{}'", name))?,
+            );
+        }
+        if let ("free", [BasicValueEnum::PointerValue(ptr)]) = (name, args.as_slice()) {
+            self.builder.build_free(*ptr)?;
+            return Ok(None);
+        }
+
+        let ty = match type_arguments.first() {
+            Some(ty) => self.get_basic_type(&self.resolve(ty))?,
+            None => anyhow::bail!("Missing type argument of '{}'", name),
+        };
+        let len = match (name, args.as_slice()) {
+            ("alloc", []) => None,
+            ("alloc_array", [BasicValueEnum::IntValue(len)]) => {
+                Some(self.builder.build_int_cast_sign_flag(
+                    *len,
+                    self.context.i64_type(),
+                    self.is_signed(&arguments[0])?,
+                    "len",
+                )?)
+            }
+            _ => anyhow::bail!("Invalid arguments to builtin function '{}'", name),
+        };
+        Ok(Some(BasicValueEnum::PointerValue(
+            match (self.memory, len) {
+                (Memory::Manual, None) => self.builder.build_malloc(ty, "alloc")?,
+                (Memory::Manual, Some(len)) => self.builder.build_array_malloc(ty, len, "alloc")?,
+                (Memory::Managed, len) => {
+                    let size = ty
+                        .size_of()
+                        .ok_or_else(|| anyhow::anyhow!("Cannot allocate unsized values"))?;
+                    let size = match len {
+                        Some(len) => self.builder.build_int_mul(size, len, "size")?,
+                        None => size,
+                    };
+                    self.builder
+                        .build_call(self.allocator(), &[size.into()], "alloc")?
+                        .try_as_basic_value()
+                        .basic()
+                        .unwrap()
+                        .into_pointer_value()
+                }
+            },
+        )))
+    }
+
+    fn compile_builtin(
+        &mut self,
+        name: &str,
+        arguments: &[Expr],
+    ) -> anyhow::Result<Option<BasicValueEnum<'ctx>>> {
+        let mut args = Vec::new();
+        for arg in arguments {
+            args.push(
+                self.compile_expr(arg)?
+                    .ok_or_else(|| anyhow::anyhow!("Void value passed to function '{}'", name))?,
+            );
+        }
+        let i64_type = self.context.i64_type();
+
+        let result = match (name, args.as_slice()) {
+            ("len", [BasicValueEnum::StructValue(string)]) => {
+                Some(self.builder.build_extract_value(*string, 1, "len")?)
+            }
+            ("c_str", [BasicValueEnum::StructValue(string)]) => Some(self.c_str(*string)?),
+            (
+                "slice",
+                [
+                    string @ BasicValueEnum::StructValue(_),
+                    BasicValueEnum::IntValue(start),
+                    BasicValueEnum::IntValue(end),
+                ],
+            ) => {
+                let string = self.spill(*string)?;
+                let start = self.builder.build_int_cast(*start, i64_type, "start")?;
+                let end = self.builder.build_int_cast(*end, i64_type, "end")?;
+                Some(self.call_string_runtime(
+                    "lang_string_slice",
+                    &[string.into(), start.into(), end.into()],
+                )?)
+            }
+            (
+                "push",
+                [
+                    BasicValueEnum::PointerValue(target),
+                    tail @ BasicValueEnum::StructValue(_),
+                ],
+            ) => {
+                let tail = self.spill(*tail)?;
+                self.call_runtime("lang_string_push", None, &[(*target).into(), tail.into()])?;
+                None
+            }
+            ("parse_int" | "parse_float", [string @ BasicValueEnum::StructValue(_)]) => {
+                let string = self.spill(*string)?;
+                let ret = if name == "parse_int" {
+                    i64_type.into()
+                } else {
+                    self.context.f64_type().into()
+                };
+                self.call_runtime(&format!("lang_string_{name}"), Some(ret), &[string.into()])?
+            }
+            (_, [BasicValueEnum::IntValue(lhs), BasicValueEnum::IntValue(rhs)])
+                if name.starts_with("wrapping_") || name.starts_with("saturating_") =>
+            {
+                let signed = self.is_signed(&arguments[0])?;
+                Some(self.build_integer_builtin(name, *lhs, *rhs, signed)?.into())
+            }
+            ("to_string", [string @ BasicValueEnum::StructValue(_)]) => Some(*string),
+            ("to_string", [BasicValueEnum::FloatValue(value)]) => {
+                let value =
+                    self.builder
+                        .build_float_cast(*value, self.context.f64_type(), "tmpext")?;
+                Some(self.call_string_runtime("lang_string_from_f64", &[value.into()])?)
+            }
+            ("to_string", [BasicValueEnum::IntValue(value)]) => {
+                let i32_type = self.context.i32_type();
+                let (function, value) = match self.expr_type(&arguments[0]) {
+                    _ if value.get_type().get_bit_width() == 1 => (
+                        "lang_string_from_bool",
+                        self.builder
+                            .build_int_z_extend(*value, i32_type, "tmpext")?,
+                    ),
+                    Some(Type::Named(name)) if name == "char" => (
+                        "lang_string_from_char",
+                        self.builder
+                            .build_int_z_extend_or_bit_cast(*value, i32_type, "tmpext")?,
+                    ),
+                    Some(Type::Numeric(numeric)) if !numeric.is_signed() => (
+                        "lang_string_from_u64",
+                        self.builder
+                            .build_int_z_extend_or_bit_cast(*value, i64_type, "tmpext")?,
+                    ),
+                    _ => (
+                        "lang_string_from_i64",
+                        self.builder
+                            .build_int_s_extend_or_bit_cast(*value, i64_type, "tmpext")?,
+                    ),
+                };
+                Some(self.call_string_runtime(function, &[value.into()])?)
+            }
+            _ => anyhow::bail!("Invalid arguments for builtin function '{}'", name),
+        };
+        // `c_str` and `to_string` hand out the data of their argument
+        if !matches!(name, "c_str" | "to_string") {
+            for (arg, value) in arguments.iter().zip(args) {
+                self.free_temporary(arg, value)?;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Integer `+`, `-` or `*`, which panics on overflow with overflow checks
+    /// and wraps around without them.
+    fn build_int_arithmetic(
+        &mut self,
+        operator: Operator,
+        lhs: IntValue<'ctx>,
+        rhs: IntValue<'ctx>,
+        signed: bool,
+    ) -> anyhow::Result<IntValue<'ctx>> {
+        if !self.overflow_checks {
+            return Ok(match operator {
+                Operator::Plus => self.builder.build_int_add(lhs, rhs, "tmpadd")?,
+                Operator::Minus => self.builder.build_int_sub(lhs, rhs, "tmpsub")?,
+                _ => self.builder.build_int_mul(lhs, rhs, "tmpmul")?,
+            });
+        }
+        let (result, overflow) = self.build_with_overflow(operator, lhs, rhs, signed)?;
+        self.build_panic_if(overflow, "Arithmetic overflow")?;
+        Ok(result)
+    }
+
+    /// Integer `/` or `%`. With overflow checks a zero divisor and `MIN / -1`
+    /// panic, without them they are undefined like in C.
+    fn build_int_division(
+        &mut self,
+        operator: Operator,
+        lhs: IntValue<'ctx>,
+        rhs: IntValue<'ctx>,
+        signed: bool,
+    ) -> anyhow::Result<IntValue<'ctx>> {
+        if self.overflow_checks {
+            let ty = lhs.get_type();
+            let zero = self.builder.build_int_compare(
+                IntPredicate::EQ,
+                rhs,
+                ty.const_zero(),
+                "divzero",
+            )?;
+            self.build_panic_if(zero, "Division by zero")?;
+            if signed {
+                let min = ty.const_int(1 << (ty.get_bit_width() - 1), false);
+                let lhs_min =
+                    self.builder
+                        .build_int_compare(IntPredicate::EQ, lhs, min, "divmin")?;
+                let rhs_minus_one = self.builder.build_int_compare(
+                    IntPredicate::EQ,
+                    rhs,
+                    ty.const_all_ones(),
+                    "divminusone",
+                )?;
+                let overflow = self
+                    .builder
+                    .build_and(lhs_min, rhs_minus_one, "divoverflow")?;
+                self.build_panic_if(overflow, "Arithmetic overflow")?;
+            }
+        }
+        Ok(match (operator, signed) {
+            (Operator::Slash, true) => self.builder.build_int_signed_div(lhs, rhs, "tmpdiv")?,
+            (Operator::Slash, false) => self.builder.build_int_unsigned_div(lhs, rhs, "tmpdiv")?,
+            (_, true) => self.builder.build_int_signed_rem(lhs, rhs, "tmprem")?,
+            (_, false) => self.builder.build_int_unsigned_rem(lhs, rhs, "tmprem")?,
+        })
+    }
+
+    /// `<<` or `>>`. With overflow checks shifting by the bit width or more,
+    /// or by a negative amount, panics. Without them the result is undefined.
+    fn build_shift(
+        &mut self,
+        operator: Operator,
+        lhs: IntValue<'ctx>,
+        rhs: IntValue<'ctx>,
+        signed: bool,
+    ) -> anyhow::Result<IntValue<'ctx>> {
+        if self.overflow_checks {
+            let width = rhs
+                .get_type()
+                .const_int(lhs.get_type().get_bit_width() as u64, false);
+            let out_of_range =
+                self.builder
+                    .build_int_compare(IntPredicate::UGE, rhs, width, "shiftrange")?;
+            self.build_panic_if(out_of_range, "Shift amount is out of range")?;
+        }
+        Ok(match operator {
+            Operator::ShiftLeft => self.builder.build_left_shift(lhs, rhs, "tmpshl")?,
+            _ => self.builder.build_right_shift(lhs, rhs, signed, "tmpshr")?,
+        })
+    }
+
+    /// Calls `llvm.sadd.with.overflow` and friends, returning the wrapped
+    /// result and whether it overflowed.
+    fn build_with_overflow(
+        &self,
+        operator: Operator,
+        lhs: IntValue<'ctx>,
+        rhs: IntValue<'ctx>,
+        signed: bool,
+    ) -> anyhow::Result<(IntValue<'ctx>, IntValue<'ctx>)> {
+        let operation = match operator {
+            Operator::Plus => "add",
+            Operator::Minus => "sub",
+            _ => "mul",
+        };
+        let sign = if signed { "s" } else { "u" };
+        let result = self
+            .call_intrinsic(&format!("llvm.{sign}{operation}.with.overflow"), lhs, rhs)?
+            .into_struct_value();
+        Ok((
+            self.builder
+                .build_extract_value(result, 0, "result")?
+                .into_int_value(),
+            self.builder
+                .build_extract_value(result, 1, "overflow")?
+                .into_int_value(),
+        ))
+    }
+
+    /// Calls the integer intrinsic `name` overloaded for the type of `lhs`.
+    fn call_intrinsic(
+        &self,
+        name: &str,
+        lhs: IntValue<'ctx>,
+        rhs: IntValue<'ctx>,
+    ) -> anyhow::Result<BasicValueEnum<'ctx>> {
+        let function = Intrinsic::find(name)
+            .and_then(|x| x.get_declaration(&self.module, &[lhs.get_type().into()]))
+            .ok_or_else(|| anyhow::anyhow!("Unknown intrinsic '{}'", name))?;
+        self.builder
+            .build_call(function, &[lhs.into(), rhs.into()], "tmpintrinsic")?
+            .try_as_basic_value()
+            .basic()
+            .ok_or_else(|| anyhow::anyhow!("Intrinsic '{}' returns void", name))
+    }
+
+    /// Continues in a new block if `condition` is false, otherwise calls
+    /// `lang_panic` with `message` and the location of the statement being
+    /// compiled, which aborts the program.
+    fn build_panic_if(&mut self, condition: IntValue<'ctx>, message: &str) -> anyhow::Result<()> {
+        let function = self
+            .current_block
+            .get_parent()
+            .ok_or_else(|| anyhow::anyhow!("Panic outside of a function"))?;
+        let panic_block = self.context.append_basic_block(function, "panic");
+        let continue_block = self.context.append_basic_block(function, "no_panic");
+        self.builder
+            .build_conditional_branch(condition, panic_block, continue_block)?;
+
+        self.switch_block(panic_block);
+        let i32_type = self.context.i32_type();
+        let args = [
+            self.builder
+                .build_global_string_ptr(message, "panic_message")?,
+            self.builder
+                .build_global_string_ptr(function.get_name().to_str()?, "panic_function")?,
+            self.builder
+                .build_global_string_ptr(&self.source_name, "panic_file")?,
+        ]
+        .map(|x| x.as_pointer_value().into());
+        let location = [self.location.line, self.location.column]
+            .map(|x| i32_type.const_int(x as u64, false).into());
+        self.call_runtime("lang_panic", None, &[args.as_slice(), &location].concat())?;
+        self.builder.build_unreachable()?;
+
+        self.switch_block(continue_block);
+        Ok(())
+    }
+
+    /// `wrapping_add(a, b)` wraps around like arithmetic without overflow
+    /// checks, `saturating_add(a, b)` clamps to the range of the type. Both
+    /// exist for `add`, `sub` and `mul`.
+    fn build_integer_builtin(
+        &mut self,
+        name: &str,
+        lhs: IntValue<'ctx>,
+        rhs: IntValue<'ctx>,
+        signed: bool,
+    ) -> anyhow::Result<IntValue<'ctx>> {
+        let operator = match name.rsplit('_').next() {
+            Some("add") => Operator::Plus,
+            Some("sub") => Operator::Minus,
+            Some("mul") => Operator::Asterisk,
+            _ => anyhow::bail!("Unknown builtin function '{}'", name),
+        };
+        let sign = if signed { "s" } else { "u" };
+        Ok(match (name.starts_with("wrapping_"), operator) {
+            (true, Operator::Plus) => self.builder.build_int_add(lhs, rhs, "tmpadd")?,
+            (true, Operator::Minus) => self.builder.build_int_sub(lhs, rhs, "tmpsub")?,
+            (true, _) => self.builder.build_int_mul(lhs, rhs, "tmpmul")?,
+            (false, Operator::Plus) => self
+                .call_intrinsic(&format!("llvm.{sign}add.sat"), lhs, rhs)?
+                .into_int_value(),
+            (false, Operator::Minus) => self
+                .call_intrinsic(&format!("llvm.{sign}sub.sat"), lhs, rhs)?
+                .into_int_value(),
+            // there is no saturating multiplication intrinsic
+            (false, _) => {
+                let ty = lhs.get_type();
+                let (result, overflow) = self.build_with_overflow(operator, lhs, rhs, signed)?;
+                let bits = ty.get_bit_width();
+                let limit = if signed {
+                    let max = ty.const_int(u64::MAX >> (65 - bits), false);
+                    let min = ty.const_int(1 << (bits - 1), false);
+                    let signs = self.builder.build_xor(lhs, rhs, "signs")?;
+                    let negative = self.builder.build_int_compare(
+                        IntPredicate::SLT,
+                        signs,
+                        ty.const_zero(),
+                        "negative",
+                    )?;
+                    self.builder
+                        .build_select(negative, min, max, "limit")?
+                        .into_int_value()
+                } else {
+                    ty.const_all_ones()
+                };
+                self.builder
+                    .build_select(overflow, limit, result, "tmpsat")?
+                    .into_int_value()
+            }
+        })
+    }
+
+    /// Whether the integer `expr` is signed, which picks `ashr` or `lshr`,
+    /// `sdiv` or `udiv` and the signed or unsigned comparisons. `char` and
+    /// `bool` are unsigned.
+    fn is_signed(&self, expr: &Expr) -> anyhow::Result<bool> {
+        match self.expr_type(expr).map(|x| self.resolve(&x)) {
+            Some(Type::Numeric(numeric)) => Ok(numeric.is_signed()),
+            Some(_) => Ok(false),
+            None => anyhow::bail!("Cannot tell whether {:?} is signed", expr),
+        }
+    }
+
+    fn compile_statement(
+        &mut self,
+        statement: &Statement,
+        function: FunctionValue<'ctx>,
+    ) -> anyhow::Result<()> {
+        self.location = statement.location;
+        self.compile_stmt(&statement.stmt, function)
+    }
+
     fn compile_stmt(&mut self, stmt: &Stmt, function: FunctionValue<'ctx>) -> anyhow::Result<()> {
         match stmt {
             Stmt::Expr(expr) => {
@@ -84,7 +1587,7 @@ impl<'ctx> CodeGen<'ctx> {
             }
             Stmt::Scope { statements } => {
                 for statement in statements {
-                    self.compile_stmt(&statement.stmt, function)?;
+                    self.compile_statement(statement, function)?;
                 }
                 Ok(())
             }
@@ -103,11 +1606,18 @@ impl<'ctx> CodeGen<'ctx> {
             Stmt::Break => {
                 todo!()
             }
+            Stmt::Func {
+                name, type_params, ..
+            } if !type_params.is_empty() => {
+                self.generic_functions.insert(name.clone(), stmt.clone());
+                Ok(())
+            }
             Stmt::Func {
                 name,
                 params,
                 ty,
                 body,
+                ..
             } => {
                 let mut function_params = Vec::new();
                 let mut variadic = false;
@@ -142,13 +1652,12 @@ impl<'ctx> CodeGen<'ctx> {
                         },
                         Type::Named(name) => match name.as_str() {
                             "String" => {
-                                function_params
-                                    .push(self.context.ptr_type(AddressSpace::default()).into());
+                                function_params.push(self.string_type().into());
                             }
                             "char" => {
                                 function_params.push(self.context.i8_type().into());
                             }
-                            _ => anyhow::bail!("Unknown named type: {}", name),
+                            _ => function_params.push(self.get_basic_type(&param.ty)?.into()),
                         },
                         Type::Variadic => {
                             if variadic {
@@ -159,10 +1668,13 @@ impl<'ctx> CodeGen<'ctx> {
                             }
                             variadic = true;
                         }
-                        Type::Pointer(_) => {
+                        Type::Pointer(_) | Type::Function { .. } => {
                             function_params
                                 .push(self.context.ptr_type(AddressSpace::default()).into());
                         }
+                        Type::Param(_) | Type::Closure { .. } => {
+                            function_params.push(self.get_basic_type(&param.ty)?.into());
+                        }
                     }
                 }
                 let function_type = match &ty {
@@ -192,24 +1704,33 @@ impl<'ctx> CodeGen<'ctx> {
                         }
                     },
                     Type::Named(name) => match name.as_str() {
-                        "String" => self
-                            .context
-                            .ptr_type(AddressSpace::default())
-                            .fn_type(&function_params, variadic),
+                        "String" => self.string_type().fn_type(&function_params, variadic),
                         "char" => self.context.i8_type().fn_type(&function_params, variadic),
-                        _ => anyhow::bail!("Unknown named type: {}", name),
+                        _ => self.get_basic_type(ty)?.fn_type(&function_params, variadic),
                     },
                     Type::Variadic => {
                         anyhow::bail!("Function '{}' type can't be variadic", name);
                     }
-                    Type::Pointer(_) => self
+                    Type::Pointer(_) | Type::Function { .. } => self
                         .context
                         .ptr_type(AddressSpace::default())
                         .fn_type(&function_params, variadic),
+                    Type::Param(_) | Type::Closure { .. } => {
+                        self.get_basic_type(ty)?.fn_type(&function_params, variadic)
+                    }
                 };
 
                 let saved_vars = self.variables.clone();
-                self.variables.clear();
+                let saved_types = self.variable_types.clone();
+                self.variables = self.globals.clone();
+                self.variable_types = self.global_types.clone();
+                self.function_types.insert(
+                    name.clone(),
+                    Type::Function {
+                        params: params.iter().map(|x| x.ty.clone()).collect(),
+                        ty: Box::new(ty.clone()),
+                    },
+                );
 
                 let func = self.module.add_function(name, function_type, None);
                 let last_block = self.current_block;
@@ -222,18 +1743,25 @@ impl<'ctx> CodeGen<'ctx> {
                     let func_param = func.get_nth_param(index as u32).unwrap();
                     self.builder.build_store(ptr, func_param)?;
                     self.variables.insert(param.name.clone(), (ptr, basic_type));
+                    self.variable_types
+                        .insert(param.name.clone(), param.ty.clone());
                 }
 
                 match &body.stmt {
                     Stmt::Scope { statements } => {
                         for statement in statements {
-                            self.compile_stmt(&statement.stmt, func)?;
+                            self.compile_statement(statement, func)?;
                         }
                     }
                     _ => anyhow::bail!("Function body must be a scope"),
                 }
 
+                if *ty == Type::Void && self.current_block.get_terminator().is_none() {
+                    self.builder.build_return(None)?;
+                }
+
                 self.variables = saved_vars;
+                self.variable_types = saved_types;
                 self.switch_block(last_block);
 
                 Ok(())
@@ -289,10 +1817,13 @@ impl<'ctx> CodeGen<'ctx> {
                             }
                             variadic = true;
                         }
-                        Type::Pointer(_) => {
+                        Type::Pointer(_) | Type::Function { .. } => {
                             function_params
                                 .push(self.context.ptr_type(AddressSpace::default()).into());
                         }
+                        Type::Param(_) | Type::Closure { .. } => {
+                            function_params.push(self.get_basic_type(&param.ty)?.into());
+                        }
                     }
                 }
                 let function_type = match &ty {
@@ -332,17 +1863,34 @@ impl<'ctx> CodeGen<'ctx> {
                     Type::Variadic => {
                         anyhow::bail!("Function '{}' type can't be variadic", name);
                     }
-                    Type::Pointer(_) => self
+                    Type::Pointer(_) | Type::Function { .. } => self
                         .context
                         .ptr_type(AddressSpace::default())
                         .fn_type(&function_params, variadic),
+                    Type::Param(param) => anyhow::bail!("Unbound type parameter: {}", param),
+                    Type::Closure { .. } => {
+                        anyhow::bail!("Extern function '{}' can't return a closure", name)
+                    }
                 };
 
                 self.module.add_function(name, function_type, None);
+                self.function_types.insert(
+                    name.clone(),
+                    Type::Function {
+                        params: params.iter().map(|x| x.ty.clone()).collect(),
+                        ty: Box::new(ty.clone()),
+                    },
+                );
+                self.externs.insert(name.clone());
 
                 Ok(())
             }
-            Stmt::Let { name, value, ty } => {
+            Stmt::Static { name, .. } => {
+                anyhow::bail!("Static '{}' must be declared at file scope", name)
+            }
+            Stmt::Let {
+                name, value, ty, ..
+            } => {
                 let init_val = self.compile_expr(value)?.unwrap();
                 let val_type = init_val.get_type();
                 let decl_ty = ty.as_ref().map(|x| self.get_basic_type(x)).transpose()?;
@@ -356,9 +1904,13 @@ impl<'ctx> CodeGen<'ctx> {
                         val_type
                     );
                 }
-                let ptr = self.builder.build_alloca(val_type, name)?;
+                let ptr = self.build_entry_alloca(val_type, name)?;
                 self.builder.build_store(ptr, init_val)?;
                 self.variables.insert(name.clone(), (ptr, val_type));
+                match ty.clone().or_else(|| self.expr_type(value)) {
+                    Some(ty) => self.variable_types.insert(name.clone(), self.resolve(&ty)),
+                    None => self.variable_types.remove(name),
+                };
                 Ok(())
             }
             Stmt::While { condition, body } => {
@@ -389,7 +1941,7 @@ impl<'ctx> CodeGen<'ctx> {
                     _ => anyhow::bail!("While body must be a scope"),
                 };
                 for statement in block {
-                    self.compile_stmt(&statement.stmt, function)?;
+                    self.compile_statement(statement, function)?;
                 }
 
                 if let Some(last_instruction) = while_then.get_last_instruction()
@@ -421,7 +1973,7 @@ impl<'ctx> CodeGen<'ctx> {
 
                 self.switch_block(then_block);
                 for statement in then_branch {
-                    self.compile_stmt(&statement.stmt, function)?;
+                    self.compile_statement(statement, function)?;
                 }
                 if self.current_block.get_terminator().is_none() {
                     self.builder.build_unconditional_branch(merge_block)?;
@@ -430,7 +1982,7 @@ impl<'ctx> CodeGen<'ctx> {
                 self.switch_block(else_block);
                 if let Some(else_branch) = else_branch {
                     for statement in else_branch {
-                        self.compile_stmt(&statement.stmt, function)?;
+                        self.compile_statement(statement, function)?;
                     }
                 }
                 if self.current_block.get_terminator().is_none() {
@@ -441,99 +1993,44 @@ impl<'ctx> CodeGen<'ctx> {
 
                 Ok(())
             }
-            Stmt::Semicolon => Ok(()),
+            Stmt::Enum { name, variants } => self.declare_enum(name, variants),
+            Stmt::Impl { methods, .. } => {
+                for method in methods {
+                    self.compile_statement(method, function)?;
+                }
+                Ok(())
+            }
+            // uses of constants are folded away by the semantic analyzer
+            Stmt::Semicolon | Stmt::Const { .. } => Ok(()),
         }
     }
 
     fn compile_expr(&mut self, expr: &Expr) -> anyhow::Result<Option<BasicValueEnum<'ctx>>> {
         match expr {
+            Expr::MethodCall { method, .. } => anyhow::bail!("Unresolved method call: {}", method),
             Expr::Literal(lit) => match lit {
                 Literal::Numeric(lit) => {
-                    let value = lit.split("_").next().unwrap();
-                    let Some(num_type) = NumericType::from_literal(lit)? else {
-                        return Ok(if value.contains(".") {
-                            Some(BasicValueEnum::FloatValue(
-                                self.context.f64_type().const_float(value.parse()?),
-                            ))
-                        } else {
-                            Some(BasicValueEnum::IntValue(
-                                self.context
-                                    .i32_type()
-                                    .const_int(value.parse::<i32>()? as u64, true),
-                            ))
-                        });
-                    };
-                    if value.starts_with("-")
-                        && matches!(
-                            num_type,
-                            NumericType::U8
-                                | NumericType::U16
-                                | NumericType::U32
-                                | NumericType::U64
-                                | NumericType::USize
-                        )
-                    {
-                        anyhow::bail!("Negative value for unsigned numeric type");
-                    }
-                    match num_type {
-                        NumericType::I8 => Ok(Some(BasicValueEnum::IntValue(
-                            self.context
-                                .i8_type()
-                                .const_int(value.parse::<i8>()? as u64, true),
-                        ))),
-                        NumericType::U8 => Ok(Some(BasicValueEnum::IntValue(
-                            self.context
-                                .i8_type()
-                                .const_int(value.parse::<u8>()? as u64, false),
-                        ))),
-                        NumericType::I16 => Ok(Some(BasicValueEnum::IntValue(
-                            self.context
-                                .i16_type()
-                                .const_int(value.parse::<i16>()? as u64, true),
-                        ))),
-                        NumericType::U16 => Ok(Some(BasicValueEnum::IntValue(
-                            self.context
-                                .i16_type()
-                                .const_int(value.parse::<u16>()? as u64, false),
-                        ))),
-                        NumericType::I32 => Ok(Some(BasicValueEnum::IntValue(
-                            self.context
-                                .i32_type()
-                                .const_int(value.parse::<i32>()? as u64, true),
-                        ))),
-                        NumericType::U32 => Ok(Some(BasicValueEnum::IntValue(
-                            self.context
-                                .i32_type()
-                                .const_int(value.parse::<u32>()? as u64, false),
-                        ))),
-                        NumericType::I64 | NumericType::ISize => {
-                            Ok(Some(BasicValueEnum::IntValue(
-                                self.context
-                                    .i64_type()
-                                    .const_int(value.parse::<i64>()? as u64, true),
-                            )))
+                    let basic_type = self.get_basic_type(&Type::Numeric(lit.ty()))?;
+                    Ok(Some(match lit.value {
+                        NumericValue::Integer(v) => BasicValueEnum::IntValue(
+                            basic_type
+                                .into_int_type()
+                                .const_int(v as u64, lit.ty().is_signed()),
+                        ),
+                        NumericValue::Float(v) => {
+                            BasicValueEnum::FloatValue(basic_type.into_float_type().const_float(v))
                         }
-                        NumericType::U64 | NumericType::USize => {
-                            Ok(Some(BasicValueEnum::IntValue(
-                                self.context
-                                    .i64_type()
-                                    .const_int(value.parse::<u64>()?, false),
-                            )))
-                        }
-                        NumericType::F32 => Ok(Some(BasicValueEnum::FloatValue(
-                            self.context.f32_type().const_float(value.parse()?),
-                        ))),
-                        NumericType::F64 => Ok(Some(BasicValueEnum::FloatValue(
-                            self.context.f64_type().const_float(value.parse()?),
-                        ))),
-                    }
+                    }))
                 }
                 Literal::String(s) => {
-                    let str_const = self
-                        .builder
-                        .build_global_string_ptr(&unescaper::unescape(s)?, "str")?;
-                    Ok(Some(BasicValueEnum::PointerValue(
-                        str_const.as_pointer_value(),
+                    let str_const = self.builder.build_global_string_ptr(s, "str")?;
+                    let i64_type = self.context.i64_type();
+                    Ok(Some(BasicValueEnum::StructValue(
+                        self.string_type().const_named_struct(&[
+                            str_const.as_pointer_value().into(),
+                            i64_type.const_int(s.len() as u64, false).into(),
+                            i64_type.const_zero().into(),
+                        ]),
                     )))
                 }
                 Literal::Char(c) => Ok(Some(BasicValueEnum::IntValue(
@@ -546,12 +2043,65 @@ impl<'ctx> CodeGen<'ctx> {
                 ))),
             },
             Expr::Variable(name) => {
+                if !self.variables.contains_key(name)
+                    && let Some(function) = self.module.get_function(name)
+                {
+                    let ptr = function.as_global_value().as_pointer_value();
+                    return Ok(Some(BasicValueEnum::PointerValue(ptr)));
+                }
                 let (ptr, ty) = self
                     .variables
                     .get(name)
                     .ok_or_else(|| anyhow::anyhow!("Undefined variable: {}", name))?;
                 Ok(Some(self.builder.build_load(*ty, *ptr, name)?))
             }
+            Expr::Binary {
+                left,
+                operator: operator @ (Operator::LogicalAnd | Operator::LogicalOr),
+                right,
+            } => {
+                let function = self.current_block.get_parent().unwrap();
+                let lhs = self.compile_expr(left)?.unwrap().into_int_value();
+                let lhsb = self.builder.build_int_compare(
+                    IntPredicate::NE,
+                    lhs,
+                    lhs.get_type().const_zero(),
+                    "lhs_bool",
+                )?;
+                let lhs_block = self.current_block;
+                let rhs_block = self.context.append_basic_block(function, "logic_rhs");
+                let merge_block = self.context.append_basic_block(function, "logic_merge");
+
+                // `a && b` skips `b` when `a` is false, `a || b` when `a` is true
+                let short_circuit = *operator == Operator::LogicalOr;
+                if short_circuit {
+                    self.builder
+                        .build_conditional_branch(lhsb, merge_block, rhs_block)?;
+                } else {
+                    self.builder
+                        .build_conditional_branch(lhsb, rhs_block, merge_block)?;
+                }
+
+                self.switch_block(rhs_block);
+                let rhs = self.compile_expr(right)?.unwrap().into_int_value();
+                let rhsb = self.builder.build_int_compare(
+                    IntPredicate::NE,
+                    rhs,
+                    rhs.get_type().const_zero(),
+                    "rhs_bool",
+                )?;
+                let rhs_block = self.current_block;
+                self.builder.build_unconditional_branch(merge_block)?;
+
+                self.switch_block(merge_block);
+                let bool_type = self.context.bool_type();
+                let phi = self.builder.build_phi(bool_type, "tmplogic")?;
+                phi.add_incoming(&[
+                    (&bool_type.const_int(short_circuit as u64, false), lhs_block),
+                    (&rhsb, rhs_block),
+                ]);
+                Ok(Some(phi.as_basic_value()))
+            }
             Expr::Binary {
                 left,
                 operator,
@@ -560,13 +2110,108 @@ impl<'ctx> CodeGen<'ctx> {
                 let left_val = self.compile_expr(left)?;
                 let right_val = self.compile_expr(right)?;
                 match (left_val, right_val, operator) {
+                    (
+                        Some(lhs @ BasicValueEnum::StructValue(_)),
+                        Some(rhs @ BasicValueEnum::StructValue(_)),
+                        Operator::Plus,
+                    ) => {
+                        let lhs_ptr = self.spill(lhs)?;
+                        let rhs_ptr = self.spill(rhs)?;
+                        let string = self.call_string_runtime(
+                            "lang_string_concat",
+                            &[lhs_ptr.into(), rhs_ptr.into()],
+                        )?;
+                        self.free_temporary(left, lhs)?;
+                        self.free_temporary(right, rhs)?;
+                        Ok(Some(string))
+                    }
+                    (
+                        Some(lhs @ BasicValueEnum::StructValue(_)),
+                        Some(rhs @ BasicValueEnum::StructValue(_)),
+                        Operator::Equals | Operator::NotEquals,
+                    ) => {
+                        let lhs_ptr = self.spill(lhs)?;
+                        let rhs_ptr = self.spill(rhs)?;
+                        let i32_type = self.context.i32_type();
+                        let eq = self
+                            .call_runtime(
+                                "lang_string_eq",
+                                Some(i32_type.into()),
+                                &[lhs_ptr.into(), rhs_ptr.into()],
+                            )?
+                            .unwrap()
+                            .into_int_value();
+                        self.free_temporary(left, lhs)?;
+                        self.free_temporary(right, rhs)?;
+                        let predicate = if *operator == Operator::Equals {
+                            IntPredicate::NE
+                        } else {
+                            IntPredicate::EQ
+                        };
+                        Ok(Some(BasicValueEnum::IntValue(
+                            self.builder.build_int_compare(
+                                predicate,
+                                eq,
+                                i32_type.const_zero(),
+                                "tmpstreq",
+                            )?,
+                        )))
+                    }
+                    (
+                        Some(BasicValueEnum::PointerValue(ptr)),
+                        Some(BasicValueEnum::IntValue(offset)),
+                        Operator::Plus | Operator::Minus,
+                    ) => {
+                        let pointee = match self.expr_type(left) {
+                            Some(Type::Pointer(inner)) => self.get_basic_type(&inner)?,
+                            _ => anyhow::bail!("Can only offset pointer types"),
+                        };
+                        let mut offset = self.builder.build_int_cast_sign_flag(
+                            offset,
+                            self.context.i64_type(),
+                            self.is_signed(right)?,
+                            "offset",
+                        )?;
+                        if *operator == Operator::Minus {
+                            offset = self.builder.build_int_neg(offset, "offset")?;
+                        }
+                        let ptr =
+                            unsafe { self.builder.build_gep(pointee, ptr, &[offset], "tmpgep")? };
+                        Ok(Some(BasicValueEnum::PointerValue(ptr)))
+                    }
+                    (
+                        Some(BasicValueEnum::PointerValue(lhs)),
+                        Some(BasicValueEnum::PointerValue(rhs)),
+                        Operator::Equals
+                        | Operator::NotEquals
+                        | Operator::Less
+                        | Operator::LessEquals
+                        | Operator::Greater
+                        | Operator::GreaterEquals,
+                    ) => {
+                        let predicate = match operator {
+                            Operator::Equals => IntPredicate::EQ,
+                            Operator::NotEquals => IntPredicate::NE,
+                            Operator::Less => IntPredicate::ULT,
+                            Operator::LessEquals => IntPredicate::ULE,
+                            Operator::Greater => IntPredicate::UGT,
+                            _ => IntPredicate::UGE,
+                        };
+                        Ok(Some(BasicValueEnum::IntValue(
+                            self.builder
+                                .build_int_compare(predicate, lhs, rhs, "tmpptrcmp")?,
+                        )))
+                    }
                     (
                         Some(BasicValueEnum::IntValue(lhs)),
                         Some(BasicValueEnum::IntValue(rhs)),
-                        Operator::Plus,
-                    ) => Ok(Some(BasicValueEnum::IntValue(
-                        self.builder.build_int_add(lhs, rhs, "tmpadd")?,
-                    ))),
+                        operator @ (Operator::Plus | Operator::Minus | Operator::Asterisk),
+                    ) => Ok(Some(BasicValueEnum::IntValue(self.build_int_arithmetic(
+                        *operator,
+                        lhs,
+                        rhs,
+                        self.is_signed(left)?,
+                    )?))),
                     (
                         Some(BasicValueEnum::FloatValue(lhs)),
                         Some(BasicValueEnum::FloatValue(rhs)),
@@ -575,13 +2220,6 @@ impl<'ctx> CodeGen<'ctx> {
                         self.builder.build_float_add(lhs, rhs, "tmpadd")?,
                     ))),
 
-                    (
-                        Some(BasicValueEnum::IntValue(lhs)),
-                        Some(BasicValueEnum::IntValue(rhs)),
-                        Operator::Minus,
-                    ) => Ok(Some(BasicValueEnum::IntValue(
-                        self.builder.build_int_sub(lhs, rhs, "tmpsub")?,
-                    ))),
                     (
                         Some(BasicValueEnum::FloatValue(lhs)),
                         Some(BasicValueEnum::FloatValue(rhs)),
@@ -590,13 +2228,6 @@ impl<'ctx> CodeGen<'ctx> {
                         self.builder.build_float_sub(lhs, rhs, "tmpsub")?,
                     ))),
 
-                    (
-                        Some(BasicValueEnum::IntValue(lhs)),
-                        Some(BasicValueEnum::IntValue(rhs)),
-                        Operator::Asterisk,
-                    ) => Ok(Some(BasicValueEnum::IntValue(
-                        self.builder.build_int_mul(lhs, rhs, "tmpmul")?,
-                    ))),
                     (
                         Some(BasicValueEnum::FloatValue(lhs)),
                         Some(BasicValueEnum::FloatValue(rhs)),
@@ -609,9 +2240,12 @@ impl<'ctx> CodeGen<'ctx> {
                         Some(BasicValueEnum::IntValue(lhs)),
                         Some(BasicValueEnum::IntValue(rhs)),
                         Operator::Slash,
-                    ) => Ok(Some(BasicValueEnum::IntValue(
-                        self.builder.build_int_signed_div(lhs, rhs, "tmpdiv")?,
-                    ))),
+                    ) => Ok(Some(BasicValueEnum::IntValue(self.build_int_division(
+                        Operator::Slash,
+                        lhs,
+                        rhs,
+                        self.is_signed(left)?,
+                    )?))),
                     (
                         Some(BasicValueEnum::FloatValue(lhs)),
                         Some(BasicValueEnum::FloatValue(rhs)),
@@ -624,9 +2258,12 @@ impl<'ctx> CodeGen<'ctx> {
                         Some(BasicValueEnum::IntValue(lhs)),
                         Some(BasicValueEnum::IntValue(rhs)),
                         Operator::Percent,
-                    ) => Ok(Some(BasicValueEnum::IntValue(
-                        self.builder.build_int_signed_rem(lhs, rhs, "tmprem")?,
-                    ))),
+                    ) => Ok(Some(BasicValueEnum::IntValue(self.build_int_division(
+                        Operator::Percent,
+                        lhs,
+                        rhs,
+                        self.is_signed(left)?,
+                    )?))),
                     (
                         Some(BasicValueEnum::FloatValue(lhs)),
                         Some(BasicValueEnum::FloatValue(rhs)),
@@ -673,10 +2310,16 @@ impl<'ctx> CodeGen<'ctx> {
                         Some(BasicValueEnum::IntValue(lhs)),
                         Some(BasicValueEnum::IntValue(rhs)),
                         Operator::Greater,
-                    ) => Ok(Some(BasicValueEnum::IntValue(
-                        self.builder
-                            .build_int_compare(IntPredicate::SGT, lhs, rhs, "tmpgt")?,
-                    ))),
+                    ) => {
+                        let predicate = match self.is_signed(left)? {
+                            true => IntPredicate::SGT,
+                            false => IntPredicate::UGT,
+                        };
+                        Ok(Some(BasicValueEnum::IntValue(
+                            self.builder
+                                .build_int_compare(predicate, lhs, rhs, "tmpgt")?,
+                        )))
+                    }
                     (
                         Some(BasicValueEnum::FloatValue(lhs)),
                         Some(BasicValueEnum::FloatValue(rhs)),
@@ -690,10 +2333,16 @@ impl<'ctx> CodeGen<'ctx> {
                         Some(BasicValueEnum::IntValue(lhs)),
                         Some(BasicValueEnum::IntValue(rhs)),
                         Operator::Less,
-                    ) => Ok(Some(BasicValueEnum::IntValue(
-                        self.builder
-                            .build_int_compare(IntPredicate::SLT, lhs, rhs, "tmplt")?,
-                    ))),
+                    ) => {
+                        let predicate = match self.is_signed(left)? {
+                            true => IntPredicate::SLT,
+                            false => IntPredicate::ULT,
+                        };
+                        Ok(Some(BasicValueEnum::IntValue(
+                            self.builder
+                                .build_int_compare(predicate, lhs, rhs, "tmplt")?,
+                        )))
+                    }
                     (
                         Some(BasicValueEnum::FloatValue(lhs)),
                         Some(BasicValueEnum::FloatValue(rhs)),
@@ -707,10 +2356,16 @@ impl<'ctx> CodeGen<'ctx> {
                         Some(BasicValueEnum::IntValue(lhs)),
                         Some(BasicValueEnum::IntValue(rhs)),
                         Operator::GreaterEquals,
-                    ) => Ok(Some(BasicValueEnum::IntValue(
-                        self.builder
-                            .build_int_compare(IntPredicate::SGE, lhs, rhs, "tmpge")?,
-                    ))),
+                    ) => {
+                        let predicate = match self.is_signed(left)? {
+                            true => IntPredicate::SGE,
+                            false => IntPredicate::UGE,
+                        };
+                        Ok(Some(BasicValueEnum::IntValue(
+                            self.builder
+                                .build_int_compare(predicate, lhs, rhs, "tmpge")?,
+                        )))
+                    }
                     (
                         Some(BasicValueEnum::FloatValue(lhs)),
                         Some(BasicValueEnum::FloatValue(rhs)),
@@ -724,10 +2379,16 @@ impl<'ctx> CodeGen<'ctx> {
                         Some(BasicValueEnum::IntValue(lhs)),
                         Some(BasicValueEnum::IntValue(rhs)),
                         Operator::LessEquals,
-                    ) => Ok(Some(BasicValueEnum::IntValue(
-                        self.builder
-                            .build_int_compare(IntPredicate::SLE, lhs, rhs, "tmple")?,
-                    ))),
+                    ) => {
+                        let predicate = match self.is_signed(left)? {
+                            true => IntPredicate::SLE,
+                            false => IntPredicate::ULE,
+                        };
+                        Ok(Some(BasicValueEnum::IntValue(
+                            self.builder
+                                .build_int_compare(predicate, lhs, rhs, "tmple")?,
+                        )))
+                    }
                     (
                         Some(BasicValueEnum::FloatValue(lhs)),
                         Some(BasicValueEnum::FloatValue(rhs)),
@@ -740,65 +2401,45 @@ impl<'ctx> CodeGen<'ctx> {
                     (
                         Some(BasicValueEnum::IntValue(lhs)),
                         Some(BasicValueEnum::IntValue(rhs)),
-                        Operator::LogicalAnd,
-                    ) => {
-                        let lhsb = self.builder.build_int_compare(
-                            IntPredicate::NE,
-                            lhs,
-                            lhs.get_type().const_zero(),
-                            "lhs_bool",
-                        )?;
-
-                        let rhsb = self.builder.build_int_compare(
-                            IntPredicate::NE,
-                            rhs,
-                            rhs.get_type().const_zero(),
-                            "rhs_bool",
-                        )?;
-
-                        Ok(Some(BasicValueEnum::IntValue(
-                            self.builder.build_and(lhsb, rhsb, "tmpand")?,
-                        )))
-                    }
+                        Operator::Ampersand,
+                    ) => Ok(Some(BasicValueEnum::IntValue(self.builder.build_and(
+                        lhs,
+                        rhs,
+                        "tmpbitand",
+                    )?))),
                     (
                         Some(BasicValueEnum::IntValue(lhs)),
                         Some(BasicValueEnum::IntValue(rhs)),
-                        Operator::LogicalOr,
-                    ) => {
-                        let lhsb = self.builder.build_int_compare(
-                            IntPredicate::NE,
-                            lhs,
-                            lhs.get_type().const_zero(),
-                            "lhs_bool",
-                        )?;
-
-                        let rhsb = self.builder.build_int_compare(
-                            IntPredicate::NE,
-                            rhs,
-                            rhs.get_type().const_zero(),
-                            "rhs_bool",
-                        )?;
-
-                        Ok(Some(BasicValueEnum::IntValue(
-                            self.builder.build_or(lhsb, rhsb, "tmpor")?,
-                        )))
-                    }
+                        Operator::Pipe,
+                    ) => Ok(Some(BasicValueEnum::IntValue(
+                        self.builder.build_or(lhs, rhs, "tmpbitor")?,
+                    ))),
                     (
                         Some(BasicValueEnum::IntValue(lhs)),
                         Some(BasicValueEnum::IntValue(rhs)),
-                        Operator::Ampersand,
-                    ) => Ok(Some(BasicValueEnum::IntValue(self.builder.build_and(
+                        Operator::Caret,
+                    ) => Ok(Some(BasicValueEnum::IntValue(self.builder.build_xor(
                         lhs,
                         rhs,
-                        "tmpbitand",
+                        "tmpbitxor",
                     )?))),
                     (
                         Some(BasicValueEnum::IntValue(lhs)),
                         Some(BasicValueEnum::IntValue(rhs)),
-                        Operator::Pipe,
+                        operator @ Operator::ShiftLeft,
                     ) => Ok(Some(BasicValueEnum::IntValue(
-                        self.builder.build_or(lhs, rhs, "tmpbitor")?,
+                        self.build_shift(*operator, lhs, rhs, false)?,
                     ))),
+                    (
+                        Some(BasicValueEnum::IntValue(lhs)),
+                        Some(BasicValueEnum::IntValue(rhs)),
+                        operator @ Operator::ShiftRight,
+                    ) => Ok(Some(BasicValueEnum::IntValue(self.build_shift(
+                        *operator,
+                        lhs,
+                        rhs,
+                        self.is_signed(left)?,
+                    )?))),
 
                     _ => anyhow::bail!("Unsupported binary operation"),
                 }
@@ -821,42 +2462,254 @@ impl<'ctx> CodeGen<'ctx> {
                         Some(BasicValueEnum::PointerValue(p)) => p,
                         _ => anyhow::bail!("Can only dereference pointer types"),
                     };
-                    let var_name = match operand.as_ref() {
-                        Expr::Variable(name) => name,
-                        _ => anyhow::bail!("Can only take address of variables"),
+                    let ty = match self.expr_type(operand) {
+                        Some(Type::Pointer(inner)) => self.get_basic_type(&inner)?,
+                        _ => anyhow::bail!("Can only dereference pointer types"),
                     };
-                    let (_, ty) = self
-                        .variables
-                        .get(var_name)
-                        .ok_or_else(|| anyhow::anyhow!("Undefined variable: {}", var_name))?;
-                    let loaded_val = self.builder.build_load(*ty, ptr, "tmpload")?;
+                    let loaded_val = self.builder.build_load(ty, ptr, "tmpload")?;
                     return Ok(Some(loaded_val));
                 }
                 let operand = self.compile_expr(operand)?;
                 match (operand, operator) {
-                    (Some(BasicValueEnum::IntValue(val)), Operator::Minus) => Ok(Some(
-                        BasicValueEnum::IntValue(self.builder.build_int_neg(val, "tmpneg")?),
-                    )),
+                    (Some(BasicValueEnum::IntValue(val)), Operator::Minus) => {
+                        let zero = val.get_type().const_zero();
+                        Ok(Some(BasicValueEnum::IntValue(self.build_int_arithmetic(
+                            Operator::Minus,
+                            zero,
+                            val,
+                            true,
+                        )?)))
+                    }
                     (Some(BasicValueEnum::FloatValue(val)), Operator::Minus) => Ok(Some(
                         BasicValueEnum::FloatValue(self.builder.build_float_neg(val, "tmpneg")?),
                     )),
-                    (Some(BasicValueEnum::IntValue(val)), Operator::Exclem) => Ok(Some(
-                        BasicValueEnum::IntValue(self.builder.build_not(val, "tmpnot")?),
-                    )),
+                    (Some(BasicValueEnum::IntValue(val)), Operator::Exclem | Operator::Tilde) => {
+                        Ok(Some(BasicValueEnum::IntValue(
+                            self.builder.build_not(val, "tmpnot")?,
+                        )))
+                    }
                     _ => anyhow::bail!("Unsupported unary operation"),
                 }
             }
-            Expr::FunctionCall { name, arguments } => {
+            Expr::FunctionCall {
+                name,
+                type_arguments,
+                arguments,
+            } if matches!(name.as_str(), "alloc" | "alloc_array" | "free") => {
+                self.compile_heap_builtin(name, type_arguments, arguments)
+            }
+            Expr::FunctionCall {
+                name, arguments, ..
+            } if BUILTINS.contains(&name.as_str()) => self.compile_builtin(name, arguments),
+            Expr::Null { .. } => Ok(Some(
+                self.context
+                    .ptr_type(AddressSpace::default())
+                    .const_null()
+                    .into(),
+            )),
+            Expr::FunctionCall {
+                name,
+                type_arguments,
+                arguments,
+            } => {
+                let name = &match type_arguments.is_empty() {
+                    true => name.clone(),
+                    false => self.instantiate(name, type_arguments)?,
+                };
                 let func = self.module.get_function(name);
-                let args = arguments
-                    .iter()
-                    .map(|x| self.compile_expr(x))
-                    .collect::<anyhow::Result<Vec<_>>>()?;
+                let is_extern = self.externs.contains(name);
+                let mut values = Vec::new();
+                for arg in arguments {
+                    let value = match arg {
+                        // C functions get string literals as NUL terminated `*i8`s
+                        Expr::Literal(Literal::String(s)) if is_extern => {
+                            let str_const = self.builder.build_global_string_ptr(s, "str")?;
+                            BasicValueEnum::PointerValue(str_const.as_pointer_value())
+                        }
+                        _ => self.compile_expr(arg)?.ok_or_else(|| {
+                            anyhow::anyhow!("Void value passed to function '{}'", name)
+                        })?,
+                    };
+                    anyhow::ensure!(
+                        !(is_extern && value.is_struct_value()),
+                        "String values must be converted with c_str() before being passed to extern function '{}'",
+                        name
+                    );
+                    values.push(value);
+                }
                 let func = func.ok_or_else(|| anyhow::anyhow!("Function not found: {}", name))?;
-                let args: Vec<_> = args.iter().map(|arg| arg.unwrap().into()).collect();
+                let args: Vec<_> = values.iter().map(|&x| x.into()).collect();
                 let return_val = self.builder.build_call(func, &args, name)?;
+                if is_extern {
+                    for (arg, value) in arguments.iter().zip(values) {
+                        self.free_temporary(arg, value)?;
+                    }
+                }
+                Ok(return_val.try_as_basic_value().basic())
+            }
+            Expr::Call { callee, arguments } => {
+                let (callee, function_type, mut args) = match self.expr_type(callee) {
+                    Some(Type::Function { params, ty }) => {
+                        let function_type = self.function_type(&params, &ty)?;
+                        match self.compile_expr(callee)? {
+                            Some(BasicValueEnum::PointerValue(ptr)) => {
+                                (ptr, function_type, Vec::new())
+                            }
+                            _ => anyhow::bail!("Cannot call a non-function value"),
+                        }
+                    }
+                    Some(Type::Closure { params, ty }) => {
+                        // the captured values come first
+                        let params: Vec<Type> =
+                            std::iter::once(Type::Pointer(Box::new(Type::Void)))
+                                .chain(params)
+                                .collect();
+                        let function_type = self.function_type(&params, &ty)?;
+                        let closure = match self.compile_expr(callee)? {
+                            Some(BasicValueEnum::StructValue(closure)) => closure,
+                            _ => anyhow::bail!("Cannot call a non-function value"),
+                        };
+                        let function = self.builder.build_extract_value(closure, 0, "function")?;
+                        let captures = self.builder.build_extract_value(closure, 1, "captures")?;
+                        (
+                            function.into_pointer_value(),
+                            function_type,
+                            vec![captures.into()],
+                        )
+                    }
+                    _ => anyhow::bail!("Cannot call a non-function value"),
+                };
+                for arg in arguments {
+                    let value = self
+                        .compile_expr(arg)?
+                        .ok_or_else(|| anyhow::anyhow!("Void value passed to function value"))?;
+                    args.push(value.into());
+                }
+                let return_val =
+                    self.builder
+                        .build_indirect_call(function_type, callee, &args, "call")?;
                 Ok(return_val.try_as_basic_value().basic())
             }
+            Expr::Lambda {
+                params,
+                ty,
+                body,
+                value,
+                captures,
+            } => self.compile_lambda(params, ty, body, value.as_deref(), captures),
+            Expr::Interpolated { parts } => {
+                let mut format = String::new();
+                let mut args = Vec::new();
+                let mut values = Vec::new();
+                for part in parts {
+                    match part {
+                        FormatPart::Text(text) => format.push_str(&text.replace('%', "%%")),
+                        FormatPart::Expr { expr, spec } => {
+                            let ty = self.expr_type(expr);
+                            let value = self
+                                .compile_expr(expr)?
+                                .ok_or_else(|| anyhow::anyhow!("Cannot format a void value"))?;
+                            let (conversion, arg) = self.format_arg(value, ty, spec)?;
+                            format.push_str(&conversion);
+                            args.push(arg);
+                            values.push((expr, value));
+                        }
+                    }
+                }
+                let string = self.build_format(&format, &args)?;
+                for (expr, value) in values {
+                    self.free_temporary(expr, value)?;
+                }
+                Ok(Some(string))
+            }
+            Expr::If {
+                condition,
+                then_branch,
+                then_value,
+                else_branch,
+                else_value,
+            } => {
+                let function = self.current_block.get_parent().unwrap();
+                let cond_val = self.compile_expr(condition)?.unwrap();
+
+                let then_block = self.context.append_basic_block(function, "if_then");
+                let else_block = self.context.append_basic_block(function, "if_else");
+                let merge_block = self.context.append_basic_block(function, "if_merge");
+
+                self.builder.build_conditional_branch(
+                    cond_val.into_int_value(),
+                    then_block,
+                    else_block,
+                )?;
+
+                let mut incoming = Vec::new();
+                for (block, branch, value) in [
+                    (then_block, then_branch, then_value),
+                    (else_block, else_branch, else_value),
+                ] {
+                    self.switch_block(block);
+                    for statement in branch {
+                        self.compile_statement(statement, function)?;
+                    }
+                    if self.current_block.get_terminator().is_none() {
+                        let value = self.compile_expr(value)?;
+                        incoming.push((value, self.current_block));
+                        self.builder.build_unconditional_branch(merge_block)?;
+                    }
+                }
+
+                self.switch_block(merge_block);
+                if incoming.is_empty() {
+                    self.builder.build_unreachable()?;
+                    return Ok(None);
+                }
+                let Some(incoming) = incoming
+                    .into_iter()
+                    .map(|(value, block)| value.map(|value| (value, block)))
+                    .collect::<Option<Vec<_>>>()
+                else {
+                    return Ok(None);
+                };
+
+                let phi = self.builder.build_phi(incoming[0].0.get_type(), "tmpif")?;
+                for (value, block) in &incoming {
+                    phi.add_incoming(&[(value, *block)]);
+                }
+                Ok(Some(phi.as_basic_value()))
+            }
+            Expr::Variant {
+                enum_name,
+                variant,
+                arguments,
+            } => {
+                let (enum_type, index, payload_type, _) =
+                    self.variant_layout(enum_name, variant)?;
+                let slot = self.build_entry_alloca(enum_type, "variant")?;
+                self.builder.build_store(slot, enum_type.const_zero())?;
+                let tag_ptr = self.builder.build_struct_gep(enum_type, slot, 0, "tag")?;
+                self.builder
+                    .build_store(tag_ptr, self.context.i32_type().const_int(index, false))?;
+
+                if !arguments.is_empty() {
+                    let mut payload = payload_type.get_undef();
+                    for (index, arg) in arguments.iter().enumerate() {
+                        let value = self.compile_expr(arg)?.ok_or_else(|| {
+                            anyhow::anyhow!("Void value in variant '{}::{}'", enum_name, variant)
+                        })?;
+                        payload = self
+                            .builder
+                            .build_insert_value(payload, value, index as u32, "payload")?
+                            .into_struct_value();
+                    }
+                    let payload_ptr = self
+                        .builder
+                        .build_struct_gep(enum_type, slot, 1, "payload")?;
+                    self.builder.build_store(payload_ptr, payload)?;
+                }
+
+                Ok(Some(self.builder.build_load(enum_type, slot, "variant")?))
+            }
+            Expr::Match { scrutinee, arms } => self.compile_match(scrutinee, arms),
             Expr::Assignment { target, value } => {
                 let ptr = match target.as_ref() {
                     Expr::Variable(name) => {
@@ -890,12 +2743,33 @@ impl<'ctx> CodeGen<'ctx> {
         self.builder.position_at_end(self.current_block);
 
         for statement in global_scope {
-            self.compile_stmt(&statement.stmt, self.main_function)?;
+            self.location = statement.location;
+            match &statement.stmt {
+                Stmt::Let {
+                    name, ty, value, ..
+                } => self.compile_global(name, ty.as_ref(), value)?,
+                Stmt::Static {
+                    name, ty, value, ..
+                } => self.compile_global(name, Some(ty), value)?,
+                stmt => self.compile_stmt(stmt, self.main_function)?,
+            }
         }
 
         self.builder
             .build_return(Some(&self.context.i32_type().const_int(0, false)))?;
 
+        if let Some(entry) = self
+            .module
+            .get_function("main")
+            .and_then(|main| main.get_first_basic_block())
+        {
+            match entry.get_first_instruction() {
+                Some(first) => self.builder.position_before(&first),
+                None => self.builder.position_at_end(entry),
+            }
+            self.builder.build_call(self.main_function, &[], "init")?;
+        }
+
         self.module.verify().map_err(|e| anyhow::anyhow!("{e}"))?;
 
         Ok(self.module.clone())
@@ -0,0 +1,206 @@
+use std::{
+    collections::BTreeSet,
+    io::{BufRead, Write},
+};
+
+use crate::{
+    interpreter::{DebugHook, Exit, InterpretValue, Interpreter},
+    parser::{Statement, Stmt},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StepMode {
+    Continue,
+    StepIn,
+    StepOver(usize),
+    StepOut(usize),
+}
+
+/// A line based command debugger driving the interpreter through [`DebugHook`].
+pub struct Debugger<R, W> {
+    input: R,
+    output: W,
+    source: Vec<String>,
+    breakpoints: BTreeSet<usize>,
+    mode: StepMode,
+}
+
+const HELP: &str = "commands:
+  break <line>, b     set a breakpoint
+  delete <line>, d    remove a breakpoint
+  continue, c         run until the next breakpoint
+  step, s             step into the next statement
+  next, n             step over function calls
+  finish, f           run until the current function returns
+  print <name>, p     print a variable
+  vars, v             print the variables of every scope
+  set <name> <value>  change a variable
+  backtrace, bt       print the call stack
+  quit, q             stop the program";
+
+impl<R: BufRead, W: Write> Debugger<R, W> {
+    pub fn new(input: R, output: W, source: &str) -> Self {
+        Debugger {
+            input,
+            output,
+            source: source.lines().map(str::to_string).collect(),
+            breakpoints: BTreeSet::new(),
+            mode: StepMode::StepIn,
+        }
+    }
+
+    fn should_stop(&self, interpreter: &Interpreter, statement: &Statement) -> bool {
+        if matches!(statement.stmt, Stmt::Scope { .. } | Stmt::Semicolon) {
+            return false;
+        }
+        if self.breakpoints.contains(&statement.location.line) {
+            return true;
+        }
+        let depth = interpreter.call_depth();
+        match self.mode {
+            StepMode::Continue => false,
+            StepMode::StepIn => true,
+            StepMode::StepOver(from) => depth <= from,
+            StepMode::StepOut(from) => depth < from,
+        }
+    }
+
+    fn prompt(&mut self, interpreter: &mut Interpreter, line: usize) -> anyhow::Result<()> {
+        let source = self
+            .source
+            .get(line.wrapping_sub(1))
+            .map_or("", |x| x.trim());
+        writeln!(self.output, "{line}: {source}")?;
+
+        loop {
+            write!(self.output, "(debug) ")?;
+            self.output.flush()?;
+
+            let mut command = String::new();
+            if self.input.read_line(&mut command)? == 0 {
+                self.breakpoints.clear();
+                self.mode = StepMode::Continue;
+                return Ok(());
+            }
+            let mut words = command.split_whitespace();
+            let depth = interpreter.call_depth();
+
+            match (words.next(), words.next()) {
+                (Some("continue" | "c"), _) => {
+                    self.mode = StepMode::Continue;
+                    return Ok(());
+                }
+                (Some("step" | "s"), _) => {
+                    self.mode = StepMode::StepIn;
+                    return Ok(());
+                }
+                (Some("next" | "n"), _) => {
+                    self.mode = StepMode::StepOver(depth);
+                    return Ok(());
+                }
+                (Some("finish" | "f"), _) => {
+                    self.mode = StepMode::StepOut(depth);
+                    return Ok(());
+                }
+                (Some("quit" | "q"), _) => anyhow::bail!(Exit(0)),
+                (Some("break" | "b"), Some(line)) => match line.parse() {
+                    Ok(line) => {
+                        self.breakpoints.insert(line);
+                        writeln!(self.output, "breakpoint set at line {line}")?;
+                    }
+                    Err(_) => writeln!(self.output, "invalid line '{line}'")?,
+                },
+                (Some("delete" | "d"), Some(line)) => match line.parse() {
+                    Ok(line) if self.breakpoints.remove(&line) => {
+                        writeln!(self.output, "breakpoint at line {line} removed")?;
+                    }
+                    _ => writeln!(self.output, "no breakpoint at line {line}")?,
+                },
+                (Some("print" | "p"), Some(name)) => match interpreter.get_variable(name) {
+                    Ok(value) => writeln!(self.output, "{name} = {}", describe(&value))?,
+                    Err(e) => writeln!(self.output, "{e}")?,
+                },
+                (Some("vars" | "v"), _) => {
+                    for (index, scope) in interpreter.scopes().iter().enumerate() {
+                        writeln!(self.output, "scope #{index}:")?;
+                        for (name, value) in scope {
+                            writeln!(self.output, "  {name} = {}", describe(value))?;
+                        }
+                    }
+                }
+                (Some("set"), Some(name)) => {
+                    let value = words.filter(|x| *x != "=").collect::<Vec<_>>().join(" ");
+                    let result = interpreter
+                        .get_variable(name)
+                        .and_then(|current| parse_like(&current, &value))
+                        .and_then(|value| interpreter.set_variable(name, value));
+                    if let Err(e) = result {
+                        writeln!(self.output, "{e}")?;
+                    }
+                }
+                (Some("backtrace" | "bt"), _) => {
+                    for frame in interpreter.backtrace() {
+                        writeln!(
+                            self.output,
+                            "  at {} (line {})",
+                            frame.function, frame.location.line
+                        )?;
+                    }
+                }
+                (Some("help" | "h"), _) => writeln!(self.output, "{HELP}")?,
+                (None, _) => {}
+                (Some(command), _) => writeln!(
+                    self.output,
+                    "unknown command '{}', try 'help'",
+                    command.trim()
+                )?,
+            }
+        }
+    }
+}
+
+impl<R: BufRead + Send, W: Write + Send> DebugHook for Debugger<R, W> {
+    fn before_statement(
+        &mut self,
+        interpreter: &mut Interpreter,
+        statement: &Statement,
+    ) -> anyhow::Result<()> {
+        if self.should_stop(interpreter, statement) {
+            self.prompt(interpreter, statement.location.line)?;
+        }
+        Ok(())
+    }
+}
+
+fn describe(value: &InterpretValue) -> String {
+    match value {
+        InterpretValue::String(s) => format!("{s:?}"),
+        x => x.as_string(),
+    }
+}
+
+/// Parses `text` as a value of the same type as `current`.
+fn parse_like(current: &InterpretValue, text: &str) -> anyhow::Result<InterpretValue> {
+    Ok(match current {
+        InterpretValue::I8(_) => InterpretValue::I8(text.parse()?),
+        InterpretValue::I16(_) => InterpretValue::I16(text.parse()?),
+        InterpretValue::I32(_) => InterpretValue::I32(text.parse()?),
+        InterpretValue::I64(_) => InterpretValue::I64(text.parse()?),
+        InterpretValue::ISize(_) => InterpretValue::ISize(text.parse()?),
+        InterpretValue::U8(_) => InterpretValue::U8(text.parse()?),
+        InterpretValue::U16(_) => InterpretValue::U16(text.parse()?),
+        InterpretValue::U32(_) => InterpretValue::U32(text.parse()?),
+        InterpretValue::U64(_) => InterpretValue::U64(text.parse()?),
+        InterpretValue::USize(_) => InterpretValue::USize(text.parse()?),
+        InterpretValue::F32(_) => InterpretValue::F32(text.parse()?),
+        InterpretValue::F64(_) => InterpretValue::F64(text.parse()?),
+        InterpretValue::Boolean(_) => InterpretValue::Boolean(text.parse()?),
+        InterpretValue::String(_) => InterpretValue::String(
+            text.strip_prefix('"')
+                .and_then(|x| x.strip_suffix('"'))
+                .unwrap_or(text)
+                .to_string(),
+        ),
+        x => anyhow::bail!("Cannot set a value of type {x:?}"),
+    })
+}
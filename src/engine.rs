@@ -0,0 +1,352 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    heap::{Allocation, Memory},
+    interpreter::{CancelHandle, DebugHook, Exit, InterpretValue, Interpreter, Limits},
+    lexer::Lexer,
+    native_functions,
+    parser::{Parser, Statement, Type},
+    sema::SymbolTable,
+    token::NumericType,
+};
+
+/// A parsed and type-checked script, ready to be run by an [`Engine`].
+#[derive(Debug, Clone)]
+pub struct Program {
+    name: String,
+    statements: Vec<Statement>,
+}
+
+impl Program {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn statements(&self) -> &[Statement] {
+        &self.statements
+    }
+}
+
+/// Embeds the interpreter as a scripting engine.
+///
+/// Host functions and globals registered on the engine are visible to the
+/// semantic analyzer when compiling, so scripts can call and read them like
+/// any other declaration.
+pub struct Engine {
+    interpreter: Interpreter,
+    host_functions: HashMap<String, (Vec<Type>, Type)>,
+    globals: HashMap<String, Type>,
+    output: Option<Arc<Mutex<String>>>,
+    memory: Memory,
+}
+
+// an engine is built on one thread and run on another
+const _: fn() = || {
+    fn assert_send<T: Send>() {}
+    assert_send::<Engine>();
+};
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Engine {
+            interpreter: Interpreter::new(),
+            host_functions: HashMap::new(),
+            globals: HashMap::new(),
+            output: None,
+            memory: Memory::Manual,
+        }
+    }
+
+    /// Chooses who frees `alloc`ed memory in the programs compiled and run
+    /// afterwards, see [`Memory`].
+    pub fn set_memory(&mut self, memory: Memory) {
+        self.memory = memory;
+        self.interpreter.set_memory(memory);
+    }
+
+    /// Redirects `print` into an in-memory buffer, see [`Engine::take_output`].
+    pub fn capture_output(&mut self) {
+        let output = Arc::new(Mutex::new(String::new()));
+        let buffer = output.clone();
+        self.interpreter.replace_native("print", move |args| {
+            let mut buffer = buffer.lock().unwrap();
+            buffer.push_str(&native_functions::format_print(&args));
+            buffer.push('\n');
+            Ok(InterpretValue::Void)
+        });
+        self.output = Some(output);
+    }
+
+    /// Returns everything printed since the last call, or an empty string if
+    /// output isn't being captured.
+    pub fn take_output(&mut self) -> String {
+        match &self.output {
+            Some(output) => std::mem::take(&mut *output.lock().unwrap()),
+            None => String::new(),
+        }
+    }
+
+    pub fn compile(&self, source: &str) -> anyhow::Result<Program> {
+        self.compile_named("<script>", source)
+    }
+
+    pub fn compile_file(&self, path: &str) -> anyhow::Result<Program> {
+        self.compile_named(path, &std::fs::read_to_string(path)?)
+    }
+
+    /// Compiles `source`, using `name` as the file name in runtime backtraces.
+    pub fn compile_named(&self, name: &str, source: &str) -> anyhow::Result<Program> {
+        let mut lexer = Lexer::new(source.to_string());
+        lexer
+            .tokenize()
+            .map_err(|e| anyhow::anyhow!("Lexer: {e} at {:?}", lexer.location()))?;
+
+        let mut parser = Parser::new(lexer.tokens().clone());
+        parser.parse().map_err(|e| {
+            anyhow::anyhow!(
+                "Parser: {e} at {:?}",
+                parser.tokens.get(parser.current_index)
+            )
+        })?;
+
+        let mut sym_table = SymbolTable::new();
+        sym_table.set_memory(self.memory);
+        for (name, (params, ty)) in &self.host_functions {
+            sym_table.declare_function(name, params.clone(), ty.clone())?;
+        }
+        for (name, ty) in &self.globals {
+            sym_table.declare_variable(name, ty.clone())?;
+        }
+        sym_table
+            .build(&mut parser.global_scope)
+            .map_err(|e| anyhow::anyhow!("Semantic: {e}"))?;
+
+        Ok(Program {
+            name: name.to_string(),
+            statements: parser.global_scope,
+        })
+    }
+
+    /// Executes the top-level statements of `program`, defining its functions
+    /// and globals. Nothing is called automatically, use [`Engine::call`].
+    ///
+    /// Returns the exit code if the script called `exit`.
+    pub fn run(&mut self, program: &Program) -> anyhow::Result<Option<i32>> {
+        self.interpreter.set_source_name(&program.name);
+        match self.interpreter.interpret(&program.statements) {
+            Ok(_) => Ok(None),
+            Err(e) => match e.downcast_ref::<Exit>() {
+                Some(Exit(code)) => Ok(Some(*code)),
+                None => Err(interpreter_error(e)),
+            },
+        }
+    }
+
+    pub fn set_debug_hook(&mut self, hook: Box<dyn DebugHook>) {
+        self.interpreter.set_debug_hook(hook);
+    }
+
+    pub fn has_function(&self, name: &str) -> bool {
+        self.interpreter.has_function(name)
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.interpreter.set_limits(limits);
+    }
+
+    /// The `alloc`s the script hasn't freed, checked after it finished.
+    pub fn leaks(&self) -> Vec<Allocation> {
+        self.interpreter.leaks()
+    }
+
+    /// How many managed allocations the script can still reach.
+    pub fn live_allocations(&mut self) -> usize {
+        self.interpreter.live_allocations()
+    }
+
+    /// A handle that aborts the running script with
+    /// [`LimitExceeded::Cancelled`](crate::interpreter::LimitExceeded), usable
+    /// from another thread.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.interpreter.cancel_handle()
+    }
+
+    pub fn register_function<Args, F>(&mut self, name: &str, func: F) -> anyhow::Result<()>
+    where
+        F: HostFunction<Args>,
+    {
+        self.interpreter
+            .define_native(name, move |args| func.call(args))?;
+        self.host_functions
+            .insert(name.to_string(), (F::param_types(), F::return_type()));
+        Ok(())
+    }
+
+    pub fn call<R: FromValue>(&mut self, name: &str, args: impl IntoArgs) -> anyhow::Result<R> {
+        let value = self
+            .interpreter
+            .call(name, args.into_args())
+            .map_err(interpreter_error)?;
+        R::from_value(value)
+    }
+
+    pub fn get_global<T: FromValue>(&self, name: &str) -> anyhow::Result<T> {
+        T::from_value(self.interpreter.get_global(name)?)
+    }
+
+    pub fn set_global<T: IntoValue>(&mut self, name: &str, value: T) {
+        self.globals.insert(name.to_string(), T::value_type());
+        self.interpreter.set_global(name, value.into_value());
+    }
+}
+
+/// Keeps the original error downcastable to
+/// [`Exit`](crate::interpreter::Exit) and
+/// [`LimitExceeded`](crate::interpreter::LimitExceeded). The message is
+/// already in the chain below, `{:#}` shows it after the prefix.
+fn interpreter_error(e: anyhow::Error) -> anyhow::Error {
+    e.context("Interpreter")
+}
+
+pub trait IntoValue {
+    fn value_type() -> Type;
+    fn into_value(self) -> InterpretValue;
+}
+
+pub trait FromValue: Sized {
+    fn from_value(value: InterpretValue) -> anyhow::Result<Self>;
+}
+
+macro_rules! impl_value {
+    ($ty:ty, $variant:ident, $value_type:expr) => {
+        impl IntoValue for $ty {
+            fn value_type() -> Type {
+                $value_type
+            }
+
+            fn into_value(self) -> InterpretValue {
+                InterpretValue::$variant(self)
+            }
+        }
+
+        impl FromValue for $ty {
+            fn from_value(value: InterpretValue) -> anyhow::Result<Self> {
+                match value {
+                    InterpretValue::$variant(v) => Ok(v),
+                    x => anyhow::bail!("Expected {} value, got {x:?}", stringify!($variant)),
+                }
+            }
+        }
+    };
+}
+
+impl_value!(i8, I8, Type::Numeric(NumericType::I8));
+impl_value!(i16, I16, Type::Numeric(NumericType::I16));
+impl_value!(i32, I32, Type::Numeric(NumericType::I32));
+impl_value!(i64, I64, Type::Numeric(NumericType::I64));
+impl_value!(isize, ISize, Type::Numeric(NumericType::ISize));
+impl_value!(u8, U8, Type::Numeric(NumericType::U8));
+impl_value!(u16, U16, Type::Numeric(NumericType::U16));
+impl_value!(u32, U32, Type::Numeric(NumericType::U32));
+impl_value!(u64, U64, Type::Numeric(NumericType::U64));
+impl_value!(usize, USize, Type::Numeric(NumericType::USize));
+impl_value!(f32, F32, Type::Numeric(NumericType::F32));
+impl_value!(f64, F64, Type::Numeric(NumericType::F64));
+impl_value!(bool, Boolean, Type::Boolean);
+impl_value!(String, String, Type::Named("String".to_string()));
+
+impl IntoValue for () {
+    fn value_type() -> Type {
+        Type::Void
+    }
+
+    fn into_value(self) -> InterpretValue {
+        InterpretValue::Void
+    }
+}
+
+impl FromValue for () {
+    fn from_value(_: InterpretValue) -> anyhow::Result<Self> {
+        Ok(())
+    }
+}
+
+impl FromValue for InterpretValue {
+    fn from_value(value: InterpretValue) -> anyhow::Result<Self> {
+        Ok(value)
+    }
+}
+
+pub trait IntoArgs {
+    fn into_args(self) -> Vec<InterpretValue>;
+}
+
+impl IntoArgs for Vec<InterpretValue> {
+    fn into_args(self) -> Vec<InterpretValue> {
+        self
+    }
+}
+
+/// A Rust function callable from scripts, with arguments and return value
+/// converted from and to [`InterpretValue`].
+pub trait HostFunction<Args>: Send + Sync + 'static {
+    fn param_types() -> Vec<Type>;
+    fn return_type() -> Type;
+    fn call(&self, args: Vec<InterpretValue>) -> anyhow::Result<InterpretValue>;
+}
+
+macro_rules! impl_host_function {
+    ($len:expr $(, $arg:ident)*) => {
+        impl<$($arg: IntoValue),*> IntoArgs for ($($arg,)*) {
+            #[allow(non_snake_case)]
+            fn into_args(self) -> Vec<InterpretValue> {
+                let ($($arg,)*) = self;
+                vec![$($arg.into_value()),*]
+            }
+        }
+
+        impl<F, R, $($arg),*> HostFunction<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + Send + Sync + 'static,
+            R: IntoValue,
+            $($arg: FromValue + IntoValue),*
+        {
+            fn param_types() -> Vec<Type> {
+                vec![$($arg::value_type()),*]
+            }
+
+            fn return_type() -> Type {
+                R::value_type()
+            }
+
+            #[allow(non_snake_case, unused_mut, unused_variables)]
+            fn call(&self, args: Vec<InterpretValue>) -> anyhow::Result<InterpretValue> {
+                anyhow::ensure!(
+                    args.len() == $len,
+                    "Expected {} arguments but got {}",
+                    $len,
+                    args.len()
+                );
+                let mut args = args.into_iter();
+                $(let $arg = $arg::from_value(args.next().unwrap())?;)*
+                Ok((self)($($arg),*).into_value())
+            }
+        }
+    };
+}
+
+impl_host_function!(0);
+impl_host_function!(1, A);
+impl_host_function!(2, A, B);
+impl_host_function!(3, A, B, C);
+impl_host_function!(4, A, B, C, D);
+impl_host_function!(5, A, B, C, D, E);
@@ -0,0 +1,311 @@
+//! The memory behind `alloc` and `alloc_array` in the interpreter. With
+//! manual memory every allocation is tracked, so the mistakes a compiled
+//! program gets away with are errors here, and the allocations never freed
+//! are reported at exit. With managed memory allocations are reference
+//! counted and released once nothing points at them anymore. Variables whose
+//! address is taken move into a block as well, so pointers to them work the
+//! same way.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex, Weak},
+};
+
+use crate::{interpreter::InterpretValue, parser::Type, token::Location};
+
+/// Who releases the memory of `alloc` and `alloc_array`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Memory {
+    /// The program calls `free`, what it doesn't free is leaked.
+    #[default]
+    Manual,
+    /// Allocations are freed once they are unreachable and `free` isn't
+    /// available. Compiled programs link a tracing collector, the
+    /// interpreter counts references.
+    Managed,
+}
+
+/// What an allocation holds and where it was made.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Allocation {
+    pub ty: Type,
+    pub len: usize,
+    pub function: String,
+    pub location: Location,
+}
+
+impl std::fmt::Display for Allocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} x {} allocated in {} at {}:{}",
+            self.len, self.ty, self.function, self.location.line, self.location.column
+        )
+    }
+}
+
+#[derive(Debug)]
+struct Block {
+    id: usize,
+    allocation: Allocation,
+    /// Whether the block holds a variable instead of an allocation.
+    variable: bool,
+    /// `None` once freed, the values are `None` until written.
+    values: Mutex<Option<Vec<Option<InterpretValue>>>>,
+}
+
+/// A reference to an allocation, pointers compare equal when they share it.
+#[derive(Debug, Clone)]
+pub struct BlockRef(Arc<Block>);
+
+impl BlockRef {
+    /// Identifies the allocation, in the order they were made.
+    pub fn id(&self) -> usize {
+        self.0.id
+    }
+
+    pub(crate) fn read(&self, offset: isize) -> anyhow::Result<InterpretValue> {
+        let values = self.0.values.lock().unwrap();
+        let index = Heap::index(&self.0, values.as_deref(), offset)?;
+        values.as_ref().unwrap()[index].clone().ok_or_else(|| {
+            anyhow::anyhow!(
+                "Read of uninitialized value {} of {}",
+                offset,
+                self.0.allocation
+            )
+        })
+    }
+
+    pub(crate) fn write(&self, offset: isize, value: InterpretValue) -> anyhow::Result<()> {
+        let mut values = self.0.values.lock().unwrap();
+        let index = Heap::index(&self.0, values.as_deref(), offset)?;
+        values.as_mut().unwrap()[index] = Some(value);
+        Ok(())
+    }
+}
+
+impl PartialEq for BlockRef {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+#[derive(Debug)]
+pub struct Heap {
+    memory: Memory,
+    next_id: usize,
+    /// The manual allocations not freed yet, by id.
+    blocks: BTreeMap<usize, Arc<Block>>,
+    /// The managed allocations, which live as long as a pointer to them.
+    managed: Vec<Weak<Block>>,
+    /// How many managed allocations trigger the next cycle collection.
+    threshold: usize,
+}
+
+/// Managed allocations made before the first cycle collection.
+const INITIAL_THRESHOLD: usize = 256;
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self::new(Memory::default())
+    }
+}
+
+impl Heap {
+    pub fn new(memory: Memory) -> Self {
+        Heap {
+            memory,
+            next_id: 0,
+            blocks: BTreeMap::new(),
+            managed: Vec::new(),
+            threshold: INITIAL_THRESHOLD,
+        }
+    }
+
+    pub fn memory(&self) -> Memory {
+        self.memory
+    }
+
+    /// Returns a pointer to the first value of the allocation, or an error
+    /// if there isn't enough memory for it.
+    pub fn alloc(&mut self, allocation: Allocation) -> anyhow::Result<InterpretValue> {
+        let mut values = Vec::new();
+        if values.try_reserve_exact(allocation.len).is_err() {
+            anyhow::bail!("Out of memory allocating {}", allocation);
+        }
+        values.resize(allocation.len, None);
+        let ty = allocation.ty.clone();
+        self.next_id += 1;
+        let block = Arc::new(Block {
+            id: self.next_id,
+            values: Mutex::new(Some(values)),
+            allocation,
+            variable: false,
+        });
+        match self.memory {
+            Memory::Manual => {
+                self.blocks.insert(block.id, block.clone());
+            }
+            Memory::Managed => self.managed.push(Arc::downgrade(&block)),
+        }
+        Ok(InterpretValue::HeapPointer {
+            block: BlockRef(block),
+            offset: 0,
+            ty,
+        })
+    }
+
+    /// Moves the value of a variable whose address is taken into a block, so
+    /// pointers to it read and write the variable. It lives as long as the
+    /// variable or a pointer to it, and is neither freed nor a leak.
+    pub fn variable(&mut self, value: InterpretValue, allocation: Allocation) -> BlockRef {
+        self.next_id += 1;
+        BlockRef(Arc::new(Block {
+            id: self.next_id,
+            values: Mutex::new(Some(vec![Some(value)])),
+            allocation,
+            variable: true,
+        }))
+    }
+
+    pub fn free(&mut self, block: &BlockRef, offset: isize) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            !block.0.variable,
+            "Cannot free {}, it is a variable",
+            block.0.allocation
+        );
+        anyhow::ensure!(
+            self.memory == Memory::Manual,
+            "Cannot free {}, the memory is managed",
+            block.0.allocation
+        );
+        let mut values = block.0.values.lock().unwrap();
+        anyhow::ensure!(values.is_some(), "Double free of {}", block.0.allocation);
+        anyhow::ensure!(
+            offset == 0,
+            "Freeing a pointer {} values into {}",
+            offset,
+            block.0.allocation
+        );
+        *values = None;
+        self.blocks.remove(&block.id());
+        Ok(())
+    }
+
+    pub fn read(&self, block: &BlockRef, offset: isize) -> anyhow::Result<InterpretValue> {
+        block.read(offset)
+    }
+
+    pub fn write(
+        &mut self,
+        block: &BlockRef,
+        offset: isize,
+        value: InterpretValue,
+    ) -> anyhow::Result<()> {
+        block.write(offset, value)
+    }
+
+    fn index(
+        block: &Block,
+        values: Option<&[Option<InterpretValue>]>,
+        offset: isize,
+    ) -> anyhow::Result<usize> {
+        let Some(values) = values else {
+            anyhow::bail!("Use after free of {}", block.allocation);
+        };
+        match usize::try_from(offset) {
+            Ok(index) if index < values.len() => Ok(index),
+            _ => anyhow::bail!("Value {} is out of bounds of {}", offset, block.allocation),
+        }
+    }
+
+    /// The allocations that weren't freed, in the order they were made.
+    /// Managed memory never leaks.
+    pub fn leaks(&self) -> Vec<Allocation> {
+        self.blocks.values().map(|x| x.allocation.clone()).collect()
+    }
+
+    /// How many managed allocations are still alive.
+    pub fn live(&self) -> usize {
+        self.managed.iter().filter(|x| x.strong_count() > 0).count()
+    }
+
+    pub fn allocated(&self) -> usize {
+        let managed = self.managed.iter().filter_map(Weak::upgrade);
+        self.blocks
+            .values()
+            .cloned()
+            .chain(managed)
+            .map(|x| match &*x.values.lock().unwrap() {
+                Some(values) => values
+                    .iter()
+                    .map(|x| {
+                        x.as_ref()
+                            .map_or(std::mem::size_of::<InterpretValue>(), InterpretValue::size)
+                    })
+                    .sum(),
+                None => 0,
+            })
+            .sum()
+    }
+
+    /// Whether enough managed allocations were made since the last
+    /// collection to look for cycles again.
+    pub fn needs_collection(&self) -> bool {
+        self.managed.len() > self.threshold
+    }
+
+    /// Releases the managed allocations that are only reachable from each
+    /// other, which reference counting alone never frees. An allocation is
+    /// alive if something besides other allocations points at it, or if it
+    /// is reachable from one that is.
+    pub fn collect_cycles(&mut self) {
+        let blocks: Vec<Arc<Block>> = self.managed.iter().filter_map(Weak::upgrade).collect();
+        let indices: HashMap<usize, usize> = blocks
+            .iter()
+            .enumerate()
+            .map(|(index, block)| (block.id, index))
+            .collect();
+        let references = |block: &Block| {
+            let mut references = Vec::new();
+            if let Some(values) = &*block.values.lock().unwrap() {
+                for value in values.iter().flatten() {
+                    value.for_each_block(&mut |x| {
+                        if let Some(index) = indices.get(&x.id()) {
+                            references.push(*index);
+                        }
+                    });
+                }
+            }
+            references
+        };
+
+        let edges: Vec<Vec<usize>> = blocks.iter().map(|x| references(x)).collect();
+        let mut internal = vec![0; blocks.len()];
+        for index in edges.iter().flatten() {
+            internal[*index] += 1;
+        }
+        // `blocks` holds one of the references itself
+        let mut worklist: Vec<usize> = (0..blocks.len())
+            .filter(|x| Arc::strong_count(&blocks[*x]) - 1 > internal[*x])
+            .collect();
+        let mut alive = vec![false; blocks.len()];
+        while let Some(index) = worklist.pop() {
+            if !std::mem::replace(&mut alive[index], true) {
+                worklist.extend(&edges[index]);
+            }
+        }
+
+        let mut garbage = Vec::new();
+        for (block, alive) in blocks.iter().zip(&alive) {
+            if !alive {
+                garbage.push(block.values.lock().unwrap().take());
+            }
+        }
+        // dropping the values releases the pointers keeping the cycles alive
+        drop(garbage);
+        drop(blocks);
+        self.managed.retain(|x| x.strong_count() > 0);
+        self.threshold = INITIAL_THRESHOLD.max(self.managed.len() * 2);
+    }
+}
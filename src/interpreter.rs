@@ -1,10 +1,21 @@
 #![allow(unused)]
 
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
 
 use crate::{
-    parser::{Expr, Param, Statement, Stmt, Type},
-    token::{Literal, Location, NumericType, Operator},
+    heap::{Allocation, BlockRef, Heap, Memory},
+    parser::{Expr, FormatPart, MatchArm, Param, Pattern, Statement, Stmt, Type},
+    token::{
+        FormatKind, FormatSpec, Literal, Location, NumericLiteral, NumericType, NumericValue,
+        Operator,
+    },
 };
 
 #[derive(Debug, Clone, PartialEq)]
@@ -22,72 +33,283 @@ pub enum InterpretValue {
     F32(f32),
     F64(f64),
     Boolean(bool),
+    /// A byte that formats as the character it encodes.
+    Char(u8),
     String(String),
+    /// A null pointer, the pointers a program takes or allocates are
+    /// [`InterpretValue::HeapPointer`]s.
     Pointer(usize, Type),
+    Enum {
+        enum_name: String,
+        variant: String,
+        fields: Vec<InterpretValue>,
+    },
+    /// A function used as a value, called by its name.
+    Function(String),
+    Closure(Arc<Closure>),
+    /// A pointer into an allocation or a variable's block of the [`Heap`].
+    HeapPointer {
+        block: BlockRef,
+        offset: isize,
+        ty: Type,
+    },
     Void,
 }
 
+/// A lambda with copies of the variables it captured.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Closure {
+    params: Vec<Param>,
+    body: Vec<Statement>,
+    value: Option<Expr>,
+    captures: Vec<(String, InterpretValue)>,
+}
+
 impl InterpretValue {
     pub fn from_literal(lit: Literal) -> anyhow::Result<Self> {
         match lit {
-            Literal::Numeric(lit) => {
-                let value = lit.split("_").next().unwrap();
-                let Some(num_type) = NumericType::from_literal(&lit)? else {
-                    return Ok(if value.contains(".") {
-                        InterpretValue::F64(value.parse()?)
-                    } else {
-                        InterpretValue::I32(value.parse()?)
-                    });
-                };
-                if value.starts_with("-")
-                    && matches!(
-                        num_type,
-                        NumericType::U8
-                            | NumericType::U16
-                            | NumericType::U32
-                            | NumericType::U64
-                            | NumericType::USize
-                    )
-                {
-                    anyhow::bail!("Negative value for unsigned numeric type");
+            Literal::Numeric(lit) => Ok(match (lit.value, lit.ty()) {
+                (NumericValue::Integer(v), NumericType::I8) => InterpretValue::I8(v as i8),
+                (NumericValue::Integer(v), NumericType::I16) => InterpretValue::I16(v as i16),
+                (NumericValue::Integer(v), NumericType::I32) => InterpretValue::I32(v as i32),
+                (NumericValue::Integer(v), NumericType::I64) => InterpretValue::I64(v as i64),
+                (NumericValue::Integer(v), NumericType::ISize) => InterpretValue::ISize(v as isize),
+                (NumericValue::Integer(v), NumericType::U8) => InterpretValue::U8(v as u8),
+                (NumericValue::Integer(v), NumericType::U16) => InterpretValue::U16(v as u16),
+                (NumericValue::Integer(v), NumericType::U32) => InterpretValue::U32(v as u32),
+                (NumericValue::Integer(v), NumericType::U64) => InterpretValue::U64(v as u64),
+                (NumericValue::Integer(v), NumericType::USize) => InterpretValue::USize(v as usize),
+                (NumericValue::Float(v), NumericType::F32) => InterpretValue::F32(v as f32),
+                (NumericValue::Float(v), _) => InterpretValue::F64(v),
+                (NumericValue::Integer(_), ty) => {
+                    anyhow::bail!("Integer literal with non-integer type {ty:?}")
                 }
-                match num_type {
-                    NumericType::I8 => Ok(InterpretValue::I8(value.parse()?)),
-                    NumericType::I16 => Ok(InterpretValue::I16(value.parse()?)),
-                    NumericType::I32 => Ok(InterpretValue::I32(value.parse()?)),
-                    NumericType::I64 => Ok(InterpretValue::I64(value.parse()?)),
-                    NumericType::ISize => Ok(InterpretValue::ISize(value.parse()?)),
-                    NumericType::U8 => Ok(InterpretValue::U8(value.parse()?)),
-                    NumericType::U16 => Ok(InterpretValue::U16(value.parse()?)),
-                    NumericType::U32 => Ok(InterpretValue::U32(value.parse()?)),
-                    NumericType::U64 => Ok(InterpretValue::U64(value.parse()?)),
-                    NumericType::USize => Ok(InterpretValue::USize(value.parse()?)),
-                    NumericType::F32 => Ok(InterpretValue::F32(value.parse()?)),
-                    NumericType::F64 => Ok(InterpretValue::F64(value.parse()?)),
-                }
-            }
+            }),
             Literal::String(lit) => Ok(InterpretValue::String(lit)),
-            Literal::Char(lit) => Ok(InterpretValue::U8(lit as u8)),
+            Literal::Char(lit) => Ok(InterpretValue::Char(lit as u8)),
             Literal::Boolean(lit) => Ok(InterpretValue::Boolean(lit == "true")),
         }
     }
 
-    pub fn add(&self, other: &InterpretValue) -> anyhow::Result<InterpretValue> {
-        match (self, other) {
-            (InterpretValue::I8(a), InterpretValue::I8(b)) => Ok(InterpretValue::I8(a + b)),
-            (InterpretValue::I16(a), InterpretValue::I16(b)) => Ok(InterpretValue::I16(a + b)),
-            (InterpretValue::I32(a), InterpretValue::I32(b)) => Ok(InterpretValue::I32(a + b)),
-            (InterpretValue::I64(a), InterpretValue::I64(b)) => Ok(InterpretValue::I64(a + b)),
-            (InterpretValue::ISize(a), InterpretValue::ISize(b)) => {
-                Ok(InterpretValue::ISize(a + b))
+    /// The type of the value, `None` for functions and closures, whose
+    /// signature the value doesn't know.
+    pub fn ty(&self) -> Option<Type> {
+        Some(match self {
+            InterpretValue::I8(_) => Type::Numeric(NumericType::I8),
+            InterpretValue::I16(_) => Type::Numeric(NumericType::I16),
+            InterpretValue::I32(_) => Type::Numeric(NumericType::I32),
+            InterpretValue::I64(_) => Type::Numeric(NumericType::I64),
+            InterpretValue::ISize(_) => Type::Numeric(NumericType::ISize),
+            InterpretValue::U8(_) => Type::Numeric(NumericType::U8),
+            InterpretValue::U16(_) => Type::Numeric(NumericType::U16),
+            InterpretValue::U32(_) => Type::Numeric(NumericType::U32),
+            InterpretValue::U64(_) => Type::Numeric(NumericType::U64),
+            InterpretValue::USize(_) => Type::Numeric(NumericType::USize),
+            InterpretValue::F32(_) => Type::Numeric(NumericType::F32),
+            InterpretValue::F64(_) => Type::Numeric(NumericType::F64),
+            InterpretValue::Boolean(_) => Type::Boolean,
+            InterpretValue::Char(_) => Type::Named("char".to_string()),
+            InterpretValue::String(_) => Type::Named("String".to_string()),
+            InterpretValue::Pointer(_, t) | InterpretValue::HeapPointer { ty: t, .. } => {
+                Type::Pointer(Box::new(t.clone()))
             }
-            (InterpretValue::U8(a), InterpretValue::U8(b)) => Ok(InterpretValue::U8(a + b)),
-            (InterpretValue::U16(a), InterpretValue::U16(b)) => Ok(InterpretValue::U16(a + b)),
-            (InterpretValue::U32(a), InterpretValue::U32(b)) => Ok(InterpretValue::U32(a + b)),
-            (InterpretValue::U64(a), InterpretValue::U64(b)) => Ok(InterpretValue::U64(a + b)),
-            (InterpretValue::USize(a), InterpretValue::USize(b)) => {
-                Ok(InterpretValue::USize(a + b))
+            InterpretValue::Enum { enum_name, .. } => Type::Named(enum_name.clone()),
+            InterpretValue::Function(_) | InterpretValue::Closure(_) => return None,
+            InterpretValue::Void => Type::Void,
+        })
+    }
+
+    /// The literal evaluating to this value, used to fold constants.
+    pub fn to_literal(&self) -> Option<Literal> {
+        let integer = |value: i128, ty| {
+            Literal::Numeric(NumericLiteral {
+                value: NumericValue::Integer(value),
+                suffix: Some(ty),
+            })
+        };
+        let float = |value: f64, ty| {
+            Literal::Numeric(NumericLiteral {
+                value: NumericValue::Float(value),
+                suffix: Some(ty),
+            })
+        };
+        Some(match self {
+            InterpretValue::I8(v) => integer(*v as i128, NumericType::I8),
+            InterpretValue::I16(v) => integer(*v as i128, NumericType::I16),
+            InterpretValue::I32(v) => integer(*v as i128, NumericType::I32),
+            InterpretValue::I64(v) => integer(*v as i128, NumericType::I64),
+            InterpretValue::ISize(v) => integer(*v as i128, NumericType::ISize),
+            InterpretValue::U8(v) => integer(*v as i128, NumericType::U8),
+            InterpretValue::U16(v) => integer(*v as i128, NumericType::U16),
+            InterpretValue::U32(v) => integer(*v as i128, NumericType::U32),
+            InterpretValue::U64(v) => integer(*v as i128, NumericType::U64),
+            InterpretValue::USize(v) => integer(*v as i128, NumericType::USize),
+            InterpretValue::F32(v) => float(*v as f64, NumericType::F32),
+            InterpretValue::F64(v) => float(*v, NumericType::F64),
+            InterpretValue::Boolean(v) => Literal::Boolean(v.to_string()),
+            InterpretValue::Char(v) => Literal::Char(*v as char),
+            InterpretValue::String(v) => Literal::String(v.clone()),
+            InterpretValue::Pointer(..)
+            | InterpretValue::HeapPointer { .. }
+            | InterpretValue::Enum { .. }
+            | InterpretValue::Function(_)
+            | InterpretValue::Closure(_)
+            | InterpretValue::Void => {
+                return None;
+            }
+        })
+    }
+
+    pub fn binary_op(
+        &self,
+        operator: Operator,
+        other: &InterpretValue,
+    ) -> anyhow::Result<InterpretValue> {
+        if let Some(result) = self.pointer_op(operator, other) {
+            return result;
+        }
+        match operator {
+            Operator::Plus => self.add(other),
+            Operator::Minus => self.sub(other),
+            Operator::Asterisk => self.mul(other),
+            Operator::Slash => self.div(other),
+            Operator::Percent => self.rem(other),
+            Operator::Equals => self.eq(other),
+            Operator::NotEquals => self.neq(other),
+            Operator::Greater => self.gt(other),
+            Operator::Less => self.lt(other),
+            Operator::GreaterEquals => self.gte(other),
+            Operator::LessEquals => self.lte(other),
+            Operator::LogicalAnd => self.and(other),
+            Operator::LogicalOr => self.or(other),
+            Operator::Ampersand => self.bitand(other),
+            Operator::Pipe => self.bitor(other),
+            Operator::Caret => self.bitxor(other),
+            Operator::ShiftLeft => self.shl(other),
+            Operator::ShiftRight => self.shr(other),
+            _ => anyhow::bail!("Unknown binary operator '{:?}'", operator),
+        }
+    }
+
+    /// Arithmetic and comparisons on pointers, `None` if neither side is one.
+    fn pointer_op(
+        &self,
+        operator: Operator,
+        other: &InterpretValue,
+    ) -> Option<anyhow::Result<InterpretValue>> {
+        let address = |value: &InterpretValue| match value {
+            InterpretValue::HeapPointer { block, offset, .. } => Some((Some(block.id()), *offset)),
+            InterpretValue::Pointer(ptr, _) => Some((None, *ptr as isize)),
+            _ => None,
+        };
+        if address(self).is_none() && address(other).is_none() {
+            return None;
+        }
+        let result = match (operator, self, address(self), address(other)) {
+            (
+                Operator::Plus | Operator::Minus,
+                InterpretValue::HeapPointer { block, offset, ty },
+                _,
+                None,
+            ) if other.is_integer() => {
+                let step = match operator {
+                    Operator::Plus => other.as_integer(),
+                    _ => -other.as_integer(),
+                };
+                Ok(InterpretValue::HeapPointer {
+                    block: block.clone(),
+                    offset: offset + step,
+                    ty: ty.clone(),
+                })
+            }
+            // `p[i]` is `*(p + i)`, the offset fails before the read would
+            (Operator::Plus | Operator::Minus, InterpretValue::Pointer(0, _), _, None)
+                if other.is_integer() =>
+            {
+                Err(anyhow::anyhow!("Null pointer dereference"))
             }
+            (Operator::Equals, _, Some(a), Some(b)) => Ok(InterpretValue::Boolean(a == b)),
+            (Operator::NotEquals, _, Some(a), Some(b)) => Ok(InterpretValue::Boolean(a != b)),
+            (
+                Operator::Greater | Operator::Less | Operator::GreaterEquals | Operator::LessEquals,
+                _,
+                Some((a_block, a)),
+                Some((b_block, b)),
+            ) if a_block == b_block => Ok(InterpretValue::Boolean(match operator {
+                Operator::Greater => a > b,
+                Operator::Less => a < b,
+                Operator::GreaterEquals => a >= b,
+                _ => a <= b,
+            })),
+            (
+                Operator::Greater | Operator::Less | Operator::GreaterEquals | Operator::LessEquals,
+                _,
+                Some(_),
+                Some(_),
+            ) => Err(anyhow::anyhow!(
+                "Cannot order pointers into different allocations with '{:?}'",
+                operator
+            )),
+            _ => Err(anyhow::anyhow!(
+                "Operator '{:?}' is not supported for pointers",
+                operator
+            )),
+        };
+        Some(result)
+    }
+
+    pub fn unary_op(&self, operator: Operator) -> anyhow::Result<InterpretValue> {
+        match operator {
+            Operator::Minus => self.neg(),
+            Operator::Exclem => self.not(),
+            Operator::Tilde => self.bitnot(),
+            Operator::Asterisk => self._deref(),
+            _ => anyhow::bail!("Unknown unary operator '{:?}'", operator),
+        }
+    }
+
+    pub fn add(&self, other: &InterpretValue) -> anyhow::Result<InterpretValue> {
+        match (self, other) {
+            (InterpretValue::I8(a), InterpretValue::I8(b)) => a
+                .checked_add(*b)
+                .map(InterpretValue::I8)
+                .ok_or_else(overflow),
+            (InterpretValue::I16(a), InterpretValue::I16(b)) => a
+                .checked_add(*b)
+                .map(InterpretValue::I16)
+                .ok_or_else(overflow),
+            (InterpretValue::I32(a), InterpretValue::I32(b)) => a
+                .checked_add(*b)
+                .map(InterpretValue::I32)
+                .ok_or_else(overflow),
+            (InterpretValue::I64(a), InterpretValue::I64(b)) => a
+                .checked_add(*b)
+                .map(InterpretValue::I64)
+                .ok_or_else(overflow),
+            (InterpretValue::ISize(a), InterpretValue::ISize(b)) => a
+                .checked_add(*b)
+                .map(InterpretValue::ISize)
+                .ok_or_else(overflow),
+            (InterpretValue::U8(a), InterpretValue::U8(b)) => a
+                .checked_add(*b)
+                .map(InterpretValue::U8)
+                .ok_or_else(overflow),
+            (InterpretValue::U16(a), InterpretValue::U16(b)) => a
+                .checked_add(*b)
+                .map(InterpretValue::U16)
+                .ok_or_else(overflow),
+            (InterpretValue::U32(a), InterpretValue::U32(b)) => a
+                .checked_add(*b)
+                .map(InterpretValue::U32)
+                .ok_or_else(overflow),
+            (InterpretValue::U64(a), InterpretValue::U64(b)) => a
+                .checked_add(*b)
+                .map(InterpretValue::U64)
+                .ok_or_else(overflow),
+            (InterpretValue::USize(a), InterpretValue::USize(b)) => a
+                .checked_add(*b)
+                .map(InterpretValue::USize)
+                .ok_or_else(overflow),
             (InterpretValue::F32(a), InterpretValue::F32(b)) => Ok(InterpretValue::F32(a + b)),
             (InterpretValue::F64(a), InterpretValue::F64(b)) => Ok(InterpretValue::F64(a + b)),
             (InterpretValue::String(a), InterpretValue::String(b)) => {
@@ -99,20 +321,46 @@ impl InterpretValue {
 
     pub fn sub(&self, other: &InterpretValue) -> anyhow::Result<InterpretValue> {
         match (self, other) {
-            (InterpretValue::I8(a), InterpretValue::I8(b)) => Ok(InterpretValue::I8(a - b)),
-            (InterpretValue::I16(a), InterpretValue::I16(b)) => Ok(InterpretValue::I16(a - b)),
-            (InterpretValue::I32(a), InterpretValue::I32(b)) => Ok(InterpretValue::I32(a - b)),
-            (InterpretValue::I64(a), InterpretValue::I64(b)) => Ok(InterpretValue::I64(a - b)),
-            (InterpretValue::ISize(a), InterpretValue::ISize(b)) => {
-                Ok(InterpretValue::ISize(a - b))
-            }
-            (InterpretValue::U8(a), InterpretValue::U8(b)) => Ok(InterpretValue::U8(a - b)),
-            (InterpretValue::U16(a), InterpretValue::U16(b)) => Ok(InterpretValue::U16(a - b)),
-            (InterpretValue::U32(a), InterpretValue::U32(b)) => Ok(InterpretValue::U32(a - b)),
-            (InterpretValue::U64(a), InterpretValue::U64(b)) => Ok(InterpretValue::U64(a - b)),
-            (InterpretValue::USize(a), InterpretValue::USize(b)) => {
-                Ok(InterpretValue::USize(a - b))
-            }
+            (InterpretValue::I8(a), InterpretValue::I8(b)) => a
+                .checked_sub(*b)
+                .map(InterpretValue::I8)
+                .ok_or_else(overflow),
+            (InterpretValue::I16(a), InterpretValue::I16(b)) => a
+                .checked_sub(*b)
+                .map(InterpretValue::I16)
+                .ok_or_else(overflow),
+            (InterpretValue::I32(a), InterpretValue::I32(b)) => a
+                .checked_sub(*b)
+                .map(InterpretValue::I32)
+                .ok_or_else(overflow),
+            (InterpretValue::I64(a), InterpretValue::I64(b)) => a
+                .checked_sub(*b)
+                .map(InterpretValue::I64)
+                .ok_or_else(overflow),
+            (InterpretValue::ISize(a), InterpretValue::ISize(b)) => a
+                .checked_sub(*b)
+                .map(InterpretValue::ISize)
+                .ok_or_else(overflow),
+            (InterpretValue::U8(a), InterpretValue::U8(b)) => a
+                .checked_sub(*b)
+                .map(InterpretValue::U8)
+                .ok_or_else(overflow),
+            (InterpretValue::U16(a), InterpretValue::U16(b)) => a
+                .checked_sub(*b)
+                .map(InterpretValue::U16)
+                .ok_or_else(overflow),
+            (InterpretValue::U32(a), InterpretValue::U32(b)) => a
+                .checked_sub(*b)
+                .map(InterpretValue::U32)
+                .ok_or_else(overflow),
+            (InterpretValue::U64(a), InterpretValue::U64(b)) => a
+                .checked_sub(*b)
+                .map(InterpretValue::U64)
+                .ok_or_else(overflow),
+            (InterpretValue::USize(a), InterpretValue::USize(b)) => a
+                .checked_sub(*b)
+                .map(InterpretValue::USize)
+                .ok_or_else(overflow),
             (InterpretValue::F32(a), InterpretValue::F32(b)) => Ok(InterpretValue::F32(a - b)),
             (InterpretValue::F64(a), InterpretValue::F64(b)) => Ok(InterpretValue::F64(a - b)),
             x => anyhow::bail!("Subtraction is not supported for given value types {x:?}"),
@@ -121,20 +369,46 @@ impl InterpretValue {
 
     pub fn mul(&self, other: &InterpretValue) -> anyhow::Result<InterpretValue> {
         match (self, other) {
-            (InterpretValue::I8(a), InterpretValue::I8(b)) => Ok(InterpretValue::I8(a * b)),
-            (InterpretValue::I16(a), InterpretValue::I16(b)) => Ok(InterpretValue::I16(a * b)),
-            (InterpretValue::I32(a), InterpretValue::I32(b)) => Ok(InterpretValue::I32(a * b)),
-            (InterpretValue::I64(a), InterpretValue::I64(b)) => Ok(InterpretValue::I64(a * b)),
-            (InterpretValue::ISize(a), InterpretValue::ISize(b)) => {
-                Ok(InterpretValue::ISize(a * b))
-            }
-            (InterpretValue::U8(a), InterpretValue::U8(b)) => Ok(InterpretValue::U8(a * b)),
-            (InterpretValue::U16(a), InterpretValue::U16(b)) => Ok(InterpretValue::U16(a * b)),
-            (InterpretValue::U32(a), InterpretValue::U32(b)) => Ok(InterpretValue::U32(a * b)),
-            (InterpretValue::U64(a), InterpretValue::U64(b)) => Ok(InterpretValue::U64(a * b)),
-            (InterpretValue::USize(a), InterpretValue::USize(b)) => {
-                Ok(InterpretValue::USize(a * b))
-            }
+            (InterpretValue::I8(a), InterpretValue::I8(b)) => a
+                .checked_mul(*b)
+                .map(InterpretValue::I8)
+                .ok_or_else(overflow),
+            (InterpretValue::I16(a), InterpretValue::I16(b)) => a
+                .checked_mul(*b)
+                .map(InterpretValue::I16)
+                .ok_or_else(overflow),
+            (InterpretValue::I32(a), InterpretValue::I32(b)) => a
+                .checked_mul(*b)
+                .map(InterpretValue::I32)
+                .ok_or_else(overflow),
+            (InterpretValue::I64(a), InterpretValue::I64(b)) => a
+                .checked_mul(*b)
+                .map(InterpretValue::I64)
+                .ok_or_else(overflow),
+            (InterpretValue::ISize(a), InterpretValue::ISize(b)) => a
+                .checked_mul(*b)
+                .map(InterpretValue::ISize)
+                .ok_or_else(overflow),
+            (InterpretValue::U8(a), InterpretValue::U8(b)) => a
+                .checked_mul(*b)
+                .map(InterpretValue::U8)
+                .ok_or_else(overflow),
+            (InterpretValue::U16(a), InterpretValue::U16(b)) => a
+                .checked_mul(*b)
+                .map(InterpretValue::U16)
+                .ok_or_else(overflow),
+            (InterpretValue::U32(a), InterpretValue::U32(b)) => a
+                .checked_mul(*b)
+                .map(InterpretValue::U32)
+                .ok_or_else(overflow),
+            (InterpretValue::U64(a), InterpretValue::U64(b)) => a
+                .checked_mul(*b)
+                .map(InterpretValue::U64)
+                .ok_or_else(overflow),
+            (InterpretValue::USize(a), InterpretValue::USize(b)) => a
+                .checked_mul(*b)
+                .map(InterpretValue::USize)
+                .ok_or_else(overflow),
             (InterpretValue::F32(a), InterpretValue::F32(b)) => Ok(InterpretValue::F32(a * b)),
             (InterpretValue::F64(a), InterpretValue::F64(b)) => Ok(InterpretValue::F64(a * b)),
             x => anyhow::bail!("Multiplication is not supported for given value types {x:?}"),
@@ -142,21 +416,51 @@ impl InterpretValue {
     }
 
     pub fn div(&self, other: &InterpretValue) -> anyhow::Result<InterpretValue> {
+        anyhow::ensure!(
+            !(other.is_integer() && other.as_integer() == 0),
+            "Division by zero"
+        );
         match (self, other) {
-            (InterpretValue::I8(a), InterpretValue::I8(b)) => Ok(InterpretValue::I8(a / b)),
-            (InterpretValue::I16(a), InterpretValue::I16(b)) => Ok(InterpretValue::I16(a / b)),
-            (InterpretValue::I32(a), InterpretValue::I32(b)) => Ok(InterpretValue::I32(a / b)),
-            (InterpretValue::I64(a), InterpretValue::I64(b)) => Ok(InterpretValue::I64(a / b)),
-            (InterpretValue::ISize(a), InterpretValue::ISize(b)) => {
-                Ok(InterpretValue::ISize(a / b))
-            }
-            (InterpretValue::U8(a), InterpretValue::U8(b)) => Ok(InterpretValue::U8(a / b)),
-            (InterpretValue::U16(a), InterpretValue::U16(b)) => Ok(InterpretValue::U16(a / b)),
-            (InterpretValue::U32(a), InterpretValue::U32(b)) => Ok(InterpretValue::U32(a / b)),
-            (InterpretValue::U64(a), InterpretValue::U64(b)) => Ok(InterpretValue::U64(a / b)),
-            (InterpretValue::USize(a), InterpretValue::USize(b)) => {
-                Ok(InterpretValue::USize(a / b))
-            }
+            (InterpretValue::I8(a), InterpretValue::I8(b)) => a
+                .checked_div(*b)
+                .map(InterpretValue::I8)
+                .ok_or_else(overflow),
+            (InterpretValue::I16(a), InterpretValue::I16(b)) => a
+                .checked_div(*b)
+                .map(InterpretValue::I16)
+                .ok_or_else(overflow),
+            (InterpretValue::I32(a), InterpretValue::I32(b)) => a
+                .checked_div(*b)
+                .map(InterpretValue::I32)
+                .ok_or_else(overflow),
+            (InterpretValue::I64(a), InterpretValue::I64(b)) => a
+                .checked_div(*b)
+                .map(InterpretValue::I64)
+                .ok_or_else(overflow),
+            (InterpretValue::ISize(a), InterpretValue::ISize(b)) => a
+                .checked_div(*b)
+                .map(InterpretValue::ISize)
+                .ok_or_else(overflow),
+            (InterpretValue::U8(a), InterpretValue::U8(b)) => a
+                .checked_div(*b)
+                .map(InterpretValue::U8)
+                .ok_or_else(overflow),
+            (InterpretValue::U16(a), InterpretValue::U16(b)) => a
+                .checked_div(*b)
+                .map(InterpretValue::U16)
+                .ok_or_else(overflow),
+            (InterpretValue::U32(a), InterpretValue::U32(b)) => a
+                .checked_div(*b)
+                .map(InterpretValue::U32)
+                .ok_or_else(overflow),
+            (InterpretValue::U64(a), InterpretValue::U64(b)) => a
+                .checked_div(*b)
+                .map(InterpretValue::U64)
+                .ok_or_else(overflow),
+            (InterpretValue::USize(a), InterpretValue::USize(b)) => a
+                .checked_div(*b)
+                .map(InterpretValue::USize)
+                .ok_or_else(overflow),
             (InterpretValue::F32(a), InterpretValue::F32(b)) => Ok(InterpretValue::F32(a / b)),
             (InterpretValue::F64(a), InterpretValue::F64(b)) => Ok(InterpretValue::F64(a / b)),
             x => anyhow::bail!("Division is not supported for given value types {x:?}"),
@@ -164,21 +468,51 @@ impl InterpretValue {
     }
 
     pub fn rem(&self, other: &InterpretValue) -> anyhow::Result<InterpretValue> {
+        anyhow::ensure!(
+            !(other.is_integer() && other.as_integer() == 0),
+            "Division by zero"
+        );
         match (self, other) {
-            (InterpretValue::I8(a), InterpretValue::I8(b)) => Ok(InterpretValue::I8(a % b)),
-            (InterpretValue::I16(a), InterpretValue::I16(b)) => Ok(InterpretValue::I16(a % b)),
-            (InterpretValue::I32(a), InterpretValue::I32(b)) => Ok(InterpretValue::I32(a % b)),
-            (InterpretValue::I64(a), InterpretValue::I64(b)) => Ok(InterpretValue::I64(a % b)),
-            (InterpretValue::ISize(a), InterpretValue::ISize(b)) => {
-                Ok(InterpretValue::ISize(a % b))
-            }
-            (InterpretValue::U8(a), InterpretValue::U8(b)) => Ok(InterpretValue::U8(a % b)),
-            (InterpretValue::U16(a), InterpretValue::U16(b)) => Ok(InterpretValue::U16(a % b)),
-            (InterpretValue::U32(a), InterpretValue::U32(b)) => Ok(InterpretValue::U32(a % b)),
-            (InterpretValue::U64(a), InterpretValue::U64(b)) => Ok(InterpretValue::U64(a % b)),
-            (InterpretValue::USize(a), InterpretValue::USize(b)) => {
-                Ok(InterpretValue::USize(a % b))
-            }
+            (InterpretValue::I8(a), InterpretValue::I8(b)) => a
+                .checked_rem(*b)
+                .map(InterpretValue::I8)
+                .ok_or_else(overflow),
+            (InterpretValue::I16(a), InterpretValue::I16(b)) => a
+                .checked_rem(*b)
+                .map(InterpretValue::I16)
+                .ok_or_else(overflow),
+            (InterpretValue::I32(a), InterpretValue::I32(b)) => a
+                .checked_rem(*b)
+                .map(InterpretValue::I32)
+                .ok_or_else(overflow),
+            (InterpretValue::I64(a), InterpretValue::I64(b)) => a
+                .checked_rem(*b)
+                .map(InterpretValue::I64)
+                .ok_or_else(overflow),
+            (InterpretValue::ISize(a), InterpretValue::ISize(b)) => a
+                .checked_rem(*b)
+                .map(InterpretValue::ISize)
+                .ok_or_else(overflow),
+            (InterpretValue::U8(a), InterpretValue::U8(b)) => a
+                .checked_rem(*b)
+                .map(InterpretValue::U8)
+                .ok_or_else(overflow),
+            (InterpretValue::U16(a), InterpretValue::U16(b)) => a
+                .checked_rem(*b)
+                .map(InterpretValue::U16)
+                .ok_or_else(overflow),
+            (InterpretValue::U32(a), InterpretValue::U32(b)) => a
+                .checked_rem(*b)
+                .map(InterpretValue::U32)
+                .ok_or_else(overflow),
+            (InterpretValue::U64(a), InterpretValue::U64(b)) => a
+                .checked_rem(*b)
+                .map(InterpretValue::U64)
+                .ok_or_else(overflow),
+            (InterpretValue::USize(a), InterpretValue::USize(b)) => a
+                .checked_rem(*b)
+                .map(InterpretValue::USize)
+                .ok_or_else(overflow),
             x => anyhow::bail!("Modulus is not supported for given value types {x:?}"),
         }
     }
@@ -193,6 +527,9 @@ impl InterpretValue {
                 Ok(InterpretValue::Boolean(a == b))
             }
             (InterpretValue::U8(a), InterpretValue::U8(b)) => Ok(InterpretValue::Boolean(a == b)),
+            (InterpretValue::Char(a), InterpretValue::Char(b)) => {
+                Ok(InterpretValue::Boolean(a == b))
+            }
             (InterpretValue::U16(a), InterpretValue::U16(b)) => Ok(InterpretValue::Boolean(a == b)),
             (InterpretValue::U32(a), InterpretValue::U32(b)) => Ok(InterpretValue::Boolean(a == b)),
             (InterpretValue::U64(a), InterpretValue::U64(b)) => Ok(InterpretValue::Boolean(a == b)),
@@ -221,6 +558,9 @@ impl InterpretValue {
                 Ok(InterpretValue::Boolean(a != b))
             }
             (InterpretValue::U8(a), InterpretValue::U8(b)) => Ok(InterpretValue::Boolean(a != b)),
+            (InterpretValue::Char(a), InterpretValue::Char(b)) => {
+                Ok(InterpretValue::Boolean(a != b))
+            }
             (InterpretValue::U16(a), InterpretValue::U16(b)) => Ok(InterpretValue::Boolean(a != b)),
             (InterpretValue::U32(a), InterpretValue::U32(b)) => Ok(InterpretValue::Boolean(a != b)),
             (InterpretValue::U64(a), InterpretValue::U64(b)) => Ok(InterpretValue::Boolean(a != b)),
@@ -251,6 +591,9 @@ impl InterpretValue {
                 Ok(InterpretValue::Boolean(a > b))
             }
             (InterpretValue::U8(a), InterpretValue::U8(b)) => Ok(InterpretValue::Boolean(a > b)),
+            (InterpretValue::Char(a), InterpretValue::Char(b)) => {
+                Ok(InterpretValue::Boolean(a > b))
+            }
             (InterpretValue::U16(a), InterpretValue::U16(b)) => Ok(InterpretValue::Boolean(a > b)),
             (InterpretValue::U32(a), InterpretValue::U32(b)) => Ok(InterpretValue::Boolean(a > b)),
             (InterpretValue::U64(a), InterpretValue::U64(b)) => Ok(InterpretValue::Boolean(a > b)),
@@ -275,6 +618,9 @@ impl InterpretValue {
                 Ok(InterpretValue::Boolean(a < b))
             }
             (InterpretValue::U8(a), InterpretValue::U8(b)) => Ok(InterpretValue::Boolean(a < b)),
+            (InterpretValue::Char(a), InterpretValue::Char(b)) => {
+                Ok(InterpretValue::Boolean(a < b))
+            }
             (InterpretValue::U16(a), InterpretValue::U16(b)) => Ok(InterpretValue::Boolean(a < b)),
             (InterpretValue::U32(a), InterpretValue::U32(b)) => Ok(InterpretValue::Boolean(a < b)),
             (InterpretValue::U64(a), InterpretValue::U64(b)) => Ok(InterpretValue::Boolean(a < b)),
@@ -297,6 +643,9 @@ impl InterpretValue {
                 Ok(InterpretValue::Boolean(a >= b))
             }
             (InterpretValue::U8(a), InterpretValue::U8(b)) => Ok(InterpretValue::Boolean(a >= b)),
+            (InterpretValue::Char(a), InterpretValue::Char(b)) => {
+                Ok(InterpretValue::Boolean(a >= b))
+            }
             (InterpretValue::U16(a), InterpretValue::U16(b)) => Ok(InterpretValue::Boolean(a >= b)),
             (InterpretValue::U32(a), InterpretValue::U32(b)) => Ok(InterpretValue::Boolean(a >= b)),
             (InterpretValue::U64(a), InterpretValue::U64(b)) => Ok(InterpretValue::Boolean(a >= b)),
@@ -321,6 +670,9 @@ impl InterpretValue {
                 Ok(InterpretValue::Boolean(a <= b))
             }
             (InterpretValue::U8(a), InterpretValue::U8(b)) => Ok(InterpretValue::Boolean(a <= b)),
+            (InterpretValue::Char(a), InterpretValue::Char(b)) => {
+                Ok(InterpretValue::Boolean(a <= b))
+            }
             (InterpretValue::U16(a), InterpretValue::U16(b)) => Ok(InterpretValue::Boolean(a <= b)),
             (InterpretValue::U32(a), InterpretValue::U32(b)) => Ok(InterpretValue::Boolean(a <= b)),
             (InterpretValue::U64(a), InterpretValue::U64(b)) => Ok(InterpretValue::Boolean(a <= b)),
@@ -410,108 +762,175 @@ impl InterpretValue {
         }
     }
 
-    pub fn neg(&self) -> anyhow::Result<InterpretValue> {
-        match self {
-            InterpretValue::I8(a) => Ok(InterpretValue::I8(-a)),
-            InterpretValue::I16(a) => Ok(InterpretValue::I16(-a)),
-            InterpretValue::I32(a) => Ok(InterpretValue::I32(-a)),
-            InterpretValue::I64(a) => Ok(InterpretValue::I64(-a)),
-            InterpretValue::ISize(a) => Ok(InterpretValue::ISize(-a)),
-            InterpretValue::F32(a) => Ok(InterpretValue::F32(-a)),
-            InterpretValue::F64(a) => Ok(InterpretValue::F64(-a)),
-            _ => anyhow::bail!("Negation is not supported for given value type"),
+    pub fn bitxor(&self, other: &InterpretValue) -> anyhow::Result<InterpretValue> {
+        match (self, other) {
+            (InterpretValue::I8(a), InterpretValue::I8(b)) => Ok(InterpretValue::I8(a ^ b)),
+            (InterpretValue::I16(a), InterpretValue::I16(b)) => Ok(InterpretValue::I16(a ^ b)),
+            (InterpretValue::I32(a), InterpretValue::I32(b)) => Ok(InterpretValue::I32(a ^ b)),
+            (InterpretValue::I64(a), InterpretValue::I64(b)) => Ok(InterpretValue::I64(a ^ b)),
+            (InterpretValue::ISize(a), InterpretValue::ISize(b)) => {
+                Ok(InterpretValue::ISize(a ^ b))
+            }
+            (InterpretValue::U8(a), InterpretValue::U8(b)) => Ok(InterpretValue::U8(a ^ b)),
+            (InterpretValue::U16(a), InterpretValue::U16(b)) => Ok(InterpretValue::U16(a ^ b)),
+            (InterpretValue::U32(a), InterpretValue::U32(b)) => Ok(InterpretValue::U32(a ^ b)),
+            (InterpretValue::U64(a), InterpretValue::U64(b)) => Ok(InterpretValue::U64(a ^ b)),
+            (InterpretValue::USize(a), InterpretValue::USize(b)) => {
+                Ok(InterpretValue::USize(a ^ b))
+            }
+            _ => anyhow::bail!("Bitwise XOR is not supported for given value types"),
         }
     }
 
-    pub fn _ref(&self) -> anyhow::Result<InterpretValue> {
+    pub fn shl(&self, other: &InterpretValue) -> anyhow::Result<InterpretValue> {
+        let amount = u32::try_from(other.as_integer()).unwrap_or(u32::MAX);
+        let out_of_range =
+            || anyhow::anyhow!("Shift amount {} is out of range", other.as_integer());
         match self {
-            InterpretValue::I8(a) => Ok(InterpretValue::Pointer(
-                &raw const a as usize,
-                Type::Numeric(NumericType::I8),
+            InterpretValue::I8(a) => Ok(InterpretValue::I8(
+                a.checked_shl(amount).ok_or_else(out_of_range)?,
+            )),
+            InterpretValue::I16(a) => Ok(InterpretValue::I16(
+                a.checked_shl(amount).ok_or_else(out_of_range)?,
             )),
-            InterpretValue::I16(a) => Ok(InterpretValue::Pointer(
-                &raw const a as usize,
-                Type::Numeric(NumericType::I16),
+            InterpretValue::I32(a) => Ok(InterpretValue::I32(
+                a.checked_shl(amount).ok_or_else(out_of_range)?,
             )),
-            InterpretValue::I32(a) => Ok(InterpretValue::Pointer(
-                &raw const a as usize,
-                Type::Numeric(NumericType::I32),
+            InterpretValue::I64(a) => Ok(InterpretValue::I64(
+                a.checked_shl(amount).ok_or_else(out_of_range)?,
             )),
-            InterpretValue::I64(a) => Ok(InterpretValue::Pointer(
-                &raw const a as usize,
-                Type::Numeric(NumericType::I64),
+            InterpretValue::ISize(a) => Ok(InterpretValue::ISize(
+                a.checked_shl(amount).ok_or_else(out_of_range)?,
             )),
-            InterpretValue::ISize(a) => Ok(InterpretValue::Pointer(
-                &raw const a as usize,
-                Type::Numeric(NumericType::ISize),
+            InterpretValue::U8(a) => Ok(InterpretValue::U8(
+                a.checked_shl(amount).ok_or_else(out_of_range)?,
+            )),
+            InterpretValue::U16(a) => Ok(InterpretValue::U16(
+                a.checked_shl(amount).ok_or_else(out_of_range)?,
+            )),
+            InterpretValue::U32(a) => Ok(InterpretValue::U32(
+                a.checked_shl(amount).ok_or_else(out_of_range)?,
+            )),
+            InterpretValue::U64(a) => Ok(InterpretValue::U64(
+                a.checked_shl(amount).ok_or_else(out_of_range)?,
+            )),
+            InterpretValue::USize(a) => Ok(InterpretValue::USize(
+                a.checked_shl(amount).ok_or_else(out_of_range)?,
+            )),
+            _ => anyhow::bail!("Left shift is not supported for given value types"),
+        }
+    }
+
+    pub fn shr(&self, other: &InterpretValue) -> anyhow::Result<InterpretValue> {
+        let amount = u32::try_from(other.as_integer()).unwrap_or(u32::MAX);
+        let out_of_range =
+            || anyhow::anyhow!("Shift amount {} is out of range", other.as_integer());
+        match self {
+            InterpretValue::I8(a) => Ok(InterpretValue::I8(
+                a.checked_shr(amount).ok_or_else(out_of_range)?,
             )),
-            InterpretValue::U8(a) => Ok(InterpretValue::Pointer(
-                &raw const a as usize,
-                Type::Numeric(NumericType::U8),
+            InterpretValue::I16(a) => Ok(InterpretValue::I16(
+                a.checked_shr(amount).ok_or_else(out_of_range)?,
             )),
-            InterpretValue::U16(a) => Ok(InterpretValue::Pointer(
-                &raw const a as usize,
-                Type::Numeric(NumericType::U16),
+            InterpretValue::I32(a) => Ok(InterpretValue::I32(
+                a.checked_shr(amount).ok_or_else(out_of_range)?,
             )),
-            InterpretValue::U32(a) => Ok(InterpretValue::Pointer(
-                &raw const a as usize,
-                Type::Numeric(NumericType::U32),
+            InterpretValue::I64(a) => Ok(InterpretValue::I64(
+                a.checked_shr(amount).ok_or_else(out_of_range)?,
             )),
-            InterpretValue::U64(a) => Ok(InterpretValue::Pointer(
-                &raw const a as usize,
-                Type::Numeric(NumericType::U64),
+            InterpretValue::ISize(a) => Ok(InterpretValue::ISize(
+                a.checked_shr(amount).ok_or_else(out_of_range)?,
             )),
-            InterpretValue::USize(a) => Ok(InterpretValue::Pointer(
-                &raw const a as usize,
-                Type::Numeric(NumericType::USize),
+            InterpretValue::U8(a) => Ok(InterpretValue::U8(
+                a.checked_shr(amount).ok_or_else(out_of_range)?,
             )),
-            InterpretValue::F32(a) => Ok(InterpretValue::Pointer(
-                &raw const a as usize,
-                Type::Numeric(NumericType::F32),
+            InterpretValue::U16(a) => Ok(InterpretValue::U16(
+                a.checked_shr(amount).ok_or_else(out_of_range)?,
             )),
-            InterpretValue::F64(a) => Ok(InterpretValue::Pointer(
-                &raw const a as usize,
-                Type::Numeric(NumericType::F64),
+            InterpretValue::U32(a) => Ok(InterpretValue::U32(
+                a.checked_shr(amount).ok_or_else(out_of_range)?,
             )),
-            InterpretValue::Boolean(a) => Ok(InterpretValue::Pointer(
-                &raw const a as usize,
-                Type::Boolean,
+            InterpretValue::U64(a) => Ok(InterpretValue::U64(
+                a.checked_shr(amount).ok_or_else(out_of_range)?,
             )),
-            InterpretValue::String(a) => Ok(InterpretValue::Pointer(
-                &raw const a as usize,
-                Type::Named("String".to_string()),
+            InterpretValue::USize(a) => Ok(InterpretValue::USize(
+                a.checked_shr(amount).ok_or_else(out_of_range)?,
             )),
-            _ => anyhow::bail!("Reference is not supported for given value type"),
+            _ => anyhow::bail!("Right shift is not supported for given value types"),
+        }
+    }
+
+    pub fn bitnot(&self) -> anyhow::Result<InterpretValue> {
+        match self {
+            x if x.is_integer() => x.not(),
+            _ => anyhow::bail!("Bitwise NOT is not supported for given value type"),
+        }
+    }
+
+    /// `wrapping_add`, `saturating_mul` and the other integer builtins, which
+    /// never fail with an overflow.
+    pub fn integer_builtin(
+        &self,
+        name: &str,
+        other: &InterpretValue,
+    ) -> anyhow::Result<InterpretValue> {
+        macro_rules! integer_builtin {
+            ($($variant:ident),*) => {
+                match (self, other) {
+                    $((InterpretValue::$variant(a), InterpretValue::$variant(b)) => {
+                        Ok(InterpretValue::$variant(match name {
+                            "wrapping_add" => a.wrapping_add(*b),
+                            "wrapping_sub" => a.wrapping_sub(*b),
+                            "wrapping_mul" => a.wrapping_mul(*b),
+                            "saturating_add" => a.saturating_add(*b),
+                            "saturating_sub" => a.saturating_sub(*b),
+                            "saturating_mul" => a.saturating_mul(*b),
+                            _ => anyhow::bail!("Unknown builtin function '{}'", name),
+                        }))
+                    })*
+                    _ => anyhow::bail!(
+                        "'{}' expected two integers of the same type, got {:?} and {:?}",
+                        name,
+                        self,
+                        other
+                    ),
+                }
+            };
+        }
+        integer_builtin!(I8, I16, I32, I64, ISize, U8, U16, U32, U64, USize)
+    }
+
+    pub fn neg(&self) -> anyhow::Result<InterpretValue> {
+        match self {
+            InterpretValue::I8(a) => a.checked_neg().map(InterpretValue::I8).ok_or_else(overflow),
+            InterpretValue::I16(a) => a
+                .checked_neg()
+                .map(InterpretValue::I16)
+                .ok_or_else(overflow),
+            InterpretValue::I32(a) => a
+                .checked_neg()
+                .map(InterpretValue::I32)
+                .ok_or_else(overflow),
+            InterpretValue::I64(a) => a
+                .checked_neg()
+                .map(InterpretValue::I64)
+                .ok_or_else(overflow),
+            InterpretValue::ISize(a) => a
+                .checked_neg()
+                .map(InterpretValue::ISize)
+                .ok_or_else(overflow),
+            InterpretValue::F32(a) => Ok(InterpretValue::F32(-a)),
+            InterpretValue::F64(a) => Ok(InterpretValue::F64(-a)),
+            _ => anyhow::bail!("Negation is not supported for given value type"),
         }
     }
 
+    /// Pointers to variables and allocations are read by the interpreter,
+    /// which knows the heap, the only other pointer is null.
     pub fn _deref(&self) -> anyhow::Result<InterpretValue> {
-        unsafe {
-            match self {
-                InterpretValue::Pointer(ptr, ty) => match ty {
-                    Type::Boolean => Ok(InterpretValue::Boolean(*(*ptr as *mut bool))),
-                    Type::Numeric(n) => match n {
-                        NumericType::I8 => Ok(InterpretValue::I8(*(*ptr as *mut i8))),
-                        NumericType::I16 => Ok(InterpretValue::I16(*(*ptr as *mut i16))),
-                        NumericType::I32 => Ok(InterpretValue::I32(*(*ptr as *mut i32))),
-                        NumericType::I64 => Ok(InterpretValue::I64(*(*ptr as *mut i64))),
-                        NumericType::ISize => Ok(InterpretValue::ISize(*(*ptr as *mut isize))),
-                        NumericType::U8 => Ok(InterpretValue::U8(*(*ptr as *mut u8))),
-                        NumericType::U16 => Ok(InterpretValue::U16(*(*ptr as *mut u16))),
-                        NumericType::U32 => Ok(InterpretValue::U32(*(*ptr as *mut u32))),
-                        NumericType::U64 => Ok(InterpretValue::U64(*(*ptr as *mut u64))),
-                        NumericType::USize => Ok(InterpretValue::USize(*(*ptr as *mut usize))),
-                        NumericType::F32 => Ok(InterpretValue::F32(*(*ptr as *mut f32))),
-                        NumericType::F64 => Ok(InterpretValue::F64(*(*ptr as *mut f64))),
-                    },
-                    Type::Named(n) => match n.as_str() {
-                        "String" => Ok(InterpretValue::String((*(*ptr as *mut String)).clone())),
-                        _ => anyhow::bail!("Dereference is not supported for given value type"),
-                    },
-                    _ => anyhow::bail!("Dereference is not supported for given value type"),
-                },
-                _ => anyhow::bail!("Dereference is not supported for given value type"),
-            }
+        match self {
+            InterpretValue::Pointer(0, _) => anyhow::bail!("Null pointer dereference"),
+            _ => anyhow::bail!("Dereference is not supported for given value type"),
         }
     }
 
@@ -530,12 +949,144 @@ impl InterpretValue {
             InterpretValue::F32(v) => v.to_string(),
             InterpretValue::F64(v) => v.to_string(),
             InterpretValue::Boolean(v) => v.to_string(),
+            InterpretValue::Char(v) => (*v as char).to_string(),
             InterpretValue::String(v) => v.clone(),
             InterpretValue::Pointer(v, t) => format!("Pointer({t:?}): {v:#x}"),
+            InterpretValue::HeapPointer { block, offset, ty } => {
+                format!("HeapPointer({ty:?}): {}+{offset}", block.id())
+            }
+            InterpretValue::Function(name) => format!("func {name}"),
+            InterpretValue::Closure(_) => "closure".to_string(),
+            InterpretValue::Enum {
+                enum_name,
+                variant,
+                fields,
+            } if fields.is_empty() => format!("{enum_name}::{variant}"),
+            InterpretValue::Enum {
+                enum_name,
+                variant,
+                fields,
+            } => format!(
+                "{enum_name}::{variant}({})",
+                fields
+                    .iter()
+                    .map(InterpretValue::as_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
             InterpretValue::Void => "void".to_string(),
         }
     }
 
+    /// Formats the value for an interpolation, matching the `printf`
+    /// conversion the LLVM backend uses for `spec`.
+    pub fn format(&self, spec: &FormatSpec) -> anyhow::Result<String> {
+        let body = match (self, spec.kind) {
+            (x, FormatKind::LowerHex) if x.is_integer() => format!("{:x}", x.as_bits()),
+            (x, FormatKind::UpperHex) if x.is_integer() => format!("{:X}", x.as_bits()),
+            (x, FormatKind::Octal) if x.is_integer() => format!("{:o}", x.as_bits()),
+            (x, FormatKind::Display) if x.is_integer() => x.as_string(),
+            (InterpretValue::F32(_) | InterpretValue::F64(_), kind) => {
+                let value = match self {
+                    InterpretValue::F32(v) => *v as f64,
+                    InterpretValue::F64(v) => *v,
+                    _ => unreachable!(),
+                };
+                match (kind, spec.precision) {
+                    _ if value.is_nan() => "nan".to_string(),
+                    _ if value.is_infinite() => {
+                        if value < 0.0 { "-inf" } else { "inf" }.to_string()
+                    }
+                    (FormatKind::Exponent, precision) => {
+                        c_exponent(&format!("{:.*e}", precision.unwrap_or(6), value))
+                    }
+                    (_, Some(precision)) => format!("{value:.precision$}"),
+                    (_, None) => c_general(value),
+                }
+            }
+            (InterpretValue::String(s), FormatKind::Display) => match spec.precision {
+                Some(precision) => s.chars().take(precision).collect(),
+                None => s.clone(),
+            },
+            (InterpretValue::Boolean(b), FormatKind::Display) => b.to_string(),
+            (InterpretValue::Char(c), FormatKind::Display) => (*c as char).to_string(),
+            (x, kind) => anyhow::bail!("Cannot format {x:?} as {kind:?}"),
+        };
+
+        let width = spec.width.unwrap_or(0);
+        let len = body.chars().count();
+        if len >= width {
+            return Ok(body);
+        }
+        let padding = width - len;
+        Ok(if spec.left_align {
+            body + &" ".repeat(padding)
+        } else if spec.zero_pad && !matches!(self, InterpretValue::String(_)) {
+            let digits = body.trim_start_matches('-');
+            let sign = &body[..body.len() - digits.len()];
+            format!("{sign}{}{digits}", "0".repeat(padding))
+        } else {
+            " ".repeat(padding) + &body
+        })
+    }
+
+    /// The two's complement bits of an integer, at its own width.
+    fn as_bits(&self) -> u64 {
+        match self {
+            InterpretValue::I8(v) => *v as u8 as u64,
+            InterpretValue::I16(v) => *v as u16 as u64,
+            InterpretValue::I32(v) => *v as u32 as u64,
+            InterpretValue::I64(v) => *v as u64,
+            InterpretValue::ISize(v) => *v as u64,
+            x => x.as_integer() as u64,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        std::mem::size_of::<InterpretValue>()
+            + match self {
+                InterpretValue::String(v) => v.capacity(),
+                InterpretValue::Enum { fields, .. } => fields.iter().map(Self::size).sum(),
+                InterpretValue::Closure(closure) => {
+                    closure.captures.iter().map(|(_, x)| x.size()).sum()
+                }
+                _ => 0,
+            }
+    }
+
+    /// Calls `f` with every heap allocation the value points at.
+    pub fn for_each_block(&self, f: &mut impl FnMut(&BlockRef)) {
+        match self {
+            InterpretValue::HeapPointer { block, .. } => f(block),
+            InterpretValue::Enum { fields, .. } => {
+                fields.iter().for_each(|x| x.for_each_block(f));
+            }
+            InterpretValue::Closure(closure) => {
+                closure
+                    .captures
+                    .iter()
+                    .for_each(|(_, x)| x.for_each_block(f));
+            }
+            _ => {}
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(
+            self,
+            InterpretValue::I8(_)
+                | InterpretValue::I16(_)
+                | InterpretValue::I32(_)
+                | InterpretValue::I64(_)
+                | InterpretValue::ISize(_)
+                | InterpretValue::U8(_)
+                | InterpretValue::U16(_)
+                | InterpretValue::U32(_)
+                | InterpretValue::U64(_)
+                | InterpretValue::USize(_)
+        )
+    }
+
     pub fn as_integer(&self) -> isize {
         match self {
             InterpretValue::I8(v) => *v as isize,
@@ -562,6 +1113,9 @@ pub enum Function {
     Native {
         func: Arc<dyn NativeFunction>,
     },
+    Extern {
+        params: Vec<Param>,
+    },
 }
 
 pub trait NativeFunction: Send + Sync + 'static {
@@ -577,15 +1131,43 @@ where
     }
 }
 
+/// Where the value of a variable lives.
+#[derive(Debug, Clone)]
+enum Slot {
+    Value(InterpretValue),
+    /// Once its address is taken the value moves into a block of the heap,
+    /// which the pointers to it share.
+    Block(BlockRef),
+}
+
+impl Slot {
+    fn value(&self) -> InterpretValue {
+        match self {
+            Slot::Value(value) => value.clone(),
+            Slot::Block(block) => block.read(0).expect("variables are never freed"),
+        }
+    }
+}
+
 pub struct Environment {
-    scopes: Vec<HashMap<String, InterpretValue>>,
+    /// The globals followed by the local scopes of the running function.
+    scopes: Vec<HashMap<String, Slot>>,
+    /// The local scopes of the functions waiting for a call to return.
+    callers: Vec<Vec<HashMap<String, Slot>>>,
     functions: HashMap<String, Function>,
 }
 
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Environment {
     pub fn new() -> Self {
         Environment {
             scopes: vec![HashMap::new()],
+            callers: Vec::new(),
             functions: HashMap::new(),
         }
     }
@@ -598,9 +1180,21 @@ impl Environment {
         self.scopes.pop();
     }
 
+    /// Hides the caller's locals so the callee only sees the globals.
+    fn enter_call(&mut self) {
+        let locals = self.scopes.split_off(1);
+        self.callers.push(locals);
+        self.push_scope();
+    }
+
+    fn leave_call(&mut self) {
+        self.scopes.truncate(1);
+        self.scopes.extend(self.callers.pop().unwrap_or_default());
+    }
+
     fn set(&mut self, name: String, value: InterpretValue) -> anyhow::Result<()> {
         if let Some(scope) = self.scopes.last_mut() {
-            scope.insert(name, value);
+            scope.insert(name, Slot::Value(value));
             Ok(())
         } else {
             anyhow::bail!("No scope available to set variable");
@@ -609,27 +1203,49 @@ impl Environment {
 
     fn get(&self, name: &str) -> anyhow::Result<InterpretValue> {
         for scope in self.scopes.iter().rev() {
-            if let Some(value) = scope.get(name) {
-                return Ok(value.clone());
+            if let Some(slot) = scope.get(name) {
+                return Ok(slot.value());
             }
         }
+        if self.functions.contains_key(name) {
+            return Ok(InterpretValue::Function(name.to_string()));
+        }
         anyhow::bail!("Variable '{}' not found", name);
     }
 
-    fn get_mut(&mut self, name: &str) -> anyhow::Result<&mut InterpretValue> {
+    fn get_mut(&mut self, name: &str) -> anyhow::Result<&mut Slot> {
         for scope in self.scopes.iter_mut().rev() {
-            if let Some(value) = scope.get_mut(name) {
-                return Ok(value);
+            if let Some(slot) = scope.get_mut(name) {
+                return Ok(slot);
             }
         }
         anyhow::bail!("Variable '{}' not found", name);
     }
 
     fn update(&mut self, name: String, value: InterpretValue) -> anyhow::Result<()> {
-        *self.get_mut(&name)? = value;
+        match self.get_mut(&name)? {
+            Slot::Value(slot) => *slot = value,
+            Slot::Block(block) => block.write(0, value)?,
+        }
         Ok(())
     }
 
+    /// The block holding variable `name`, moving its value into one made by
+    /// `make` the first time its address is taken.
+    fn address(
+        &mut self,
+        name: &str,
+        make: impl FnOnce(InterpretValue) -> BlockRef,
+    ) -> anyhow::Result<BlockRef> {
+        let slot = self.get_mut(name)?;
+        let block = match slot {
+            Slot::Block(block) => block.clone(),
+            Slot::Value(value) => make(std::mem::replace(value, InterpretValue::Void)),
+        };
+        *slot = Slot::Block(block.clone());
+        Ok(block)
+    }
+
     fn define_function(
         &mut self,
         name: String,
@@ -644,6 +1260,23 @@ impl Environment {
         Ok(())
     }
 
+    fn define_extern(&mut self, name: String, params: Vec<Param>) -> anyhow::Result<()> {
+        if self.functions.contains_key(&name) {
+            anyhow::bail!("Function '{}' is already defined", name);
+        }
+        self.functions.insert(name, Function::Extern { params });
+        Ok(())
+    }
+
+    fn allocated(&self) -> usize {
+        self.scopes
+            .iter()
+            .chain(self.callers.iter().flatten())
+            .flat_map(|scope| scope.values())
+            .map(|slot| slot.value().size())
+            .sum()
+    }
+
     fn define_rust_function<F>(&mut self, name: String, func: F) -> anyhow::Result<()>
     where
         F: Fn(Vec<InterpretValue>) -> anyhow::Result<InterpretValue> + Send + Sync + 'static,
@@ -679,8 +1312,143 @@ pub enum ControlFlow {
     Continue,
 }
 
+/// What an expression left by `return`, `break` or `continue` evaluates to.
+impl From<ControlFlow> for (InterpretValue, ControlFlow) {
+    fn from(flow: ControlFlow) -> Self {
+        (InterpretValue::Void, flow)
+    }
+}
+
+/// Raised by the `exit` native instead of terminating the host process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Exit(pub i32);
+
+impl std::fmt::Display for Exit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Script exited with code {}", self.0)
+    }
+}
+
+impl std::error::Error for Exit {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitExceeded {
+    Steps(u64),
+    Time(Duration),
+    CallDepth(usize),
+    Memory(usize),
+    Cancelled,
+}
+
+impl std::fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LimitExceeded::Steps(n) => write!(f, "Step limit of {n} exceeded"),
+            LimitExceeded::Time(d) => write!(f, "Time limit of {d:?} exceeded"),
+            LimitExceeded::CallDepth(n) => write!(f, "Call depth limit of {n} exceeded"),
+            LimitExceeded::Memory(n) => write!(f, "Memory limit of {n} bytes exceeded"),
+            LimitExceeded::Cancelled => write!(f, "Execution cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+/// Which natives and externs a script is allowed to call.
+#[derive(Debug, Clone, Default)]
+pub enum CallPolicy {
+    #[default]
+    AllowAll,
+    AllowOnly(HashSet<String>),
+    Deny(HashSet<String>),
+}
+
+impl CallPolicy {
+    pub fn allows(&self, name: &str) -> bool {
+        match self {
+            CallPolicy::AllowAll => true,
+            CallPolicy::AllowOnly(names) => names.contains(name),
+            CallPolicy::Deny(names) => !names.contains(name),
+        }
+    }
+}
+
+/// How many statements run between the checks of [`Limits::max_memory`],
+/// which walk every value.
+const MEMORY_CHECK_INTERVAL: u64 = 64;
+
+/// Resource limits for untrusted scripts, `None` means unlimited.
+#[derive(Debug, Clone, Default)]
+pub struct Limits {
+    pub max_steps: Option<u64>,
+    pub timeout: Option<Duration>,
+    pub max_call_depth: Option<usize>,
+    /// Checked every few statements, a script may briefly exceed it.
+    pub max_memory: Option<usize>,
+    pub policy: CallPolicy,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub function: String,
+    pub call_site: Option<Location>,
+    pub location: Location,
+}
+
+/// A runtime error with the call stack at the point it was raised, innermost
+/// frame first.
+#[derive(Debug, Clone)]
+pub struct RuntimeError {
+    pub message: String,
+    pub source_name: String,
+    pub backtrace: Vec<Frame>,
+}
+
+impl std::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)?;
+        for frame in &self.backtrace {
+            write!(
+                f,
+                "\n    at {} ({}:{}:{})",
+                frame.function, self.source_name, frame.location.line, frame.location.column
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Called by the interpreter before each statement, used to implement
+/// debuggers on top of it. `Send` so the engine holding it can move to
+/// another thread.
+pub trait DebugHook: Send {
+    fn before_statement(
+        &mut self,
+        interpreter: &mut Interpreter,
+        statement: &Statement,
+    ) -> anyhow::Result<()>;
+}
+
 pub struct Interpreter {
     env: Environment,
+    heap: Heap,
+    hook: Option<Box<dyn DebugHook>>,
+    frames: Vec<Frame>,
+    source_name: String,
+    limits: Limits,
+    cancel: CancelHandle,
+    steps: u64,
+    call_depth: usize,
+    started: Instant,
 }
 
 macro_rules! native_func {
@@ -693,37 +1461,208 @@ macro_rules! native_func {
     };
 }
 
+/// Unwraps the value of an evaluated expression, or returns early with the
+/// `return`, `break` or `continue` that left it, like `?` does for errors.
+macro_rules! value {
+    ($evaluated:expr) => {
+        match $evaluated {
+            (value, ControlFlow::None) => value,
+            (_, flow) => return Ok(flow.into()),
+        }
+    };
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Interpreter {
     pub fn new() -> Self {
         let mut env = Environment::new();
         native_func!(env, print);
         native_func!(env, exit);
-        Interpreter { env }
+        native_func!(env, len);
+        native_func!(env, slice);
+        native_func!(env, push);
+        native_func!(env, to_string);
+        native_func!(env, parse_int);
+        native_func!(env, parse_float);
+        native_func!(env, c_str);
+        native_func!(env, wrapping_add);
+        native_func!(env, wrapping_sub);
+        native_func!(env, wrapping_mul);
+        native_func!(env, saturating_add);
+        native_func!(env, saturating_sub);
+        native_func!(env, saturating_mul);
+        Interpreter {
+            env,
+            heap: Heap::default(),
+            hook: None,
+            frames: Vec::new(),
+            source_name: "<script>".to_string(),
+            limits: Limits::default(),
+            cancel: CancelHandle::default(),
+            steps: 0,
+            call_depth: 0,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn set_source_name(&mut self, name: &str) {
+        self.source_name = name.to_string();
+    }
+
+    pub fn set_debug_hook(&mut self, hook: Box<dyn DebugHook>) {
+        self.hook = Some(hook);
+    }
+
+    /// Variables of every scope, innermost first.
+    pub fn scopes(&self) -> Vec<Vec<(String, InterpretValue)>> {
+        self.env
+            .scopes
+            .iter()
+            .rev()
+            .map(|scope| {
+                let mut vars = scope
+                    .iter()
+                    .map(|(name, slot)| (name.clone(), slot.value()))
+                    .collect::<Vec<_>>();
+                vars.sort_by(|a, b| a.0.cmp(&b.0));
+                vars
+            })
+            .collect()
+    }
+
+    pub fn get_variable(&self, name: &str) -> anyhow::Result<InterpretValue> {
+        self.env.get(name)
+    }
+
+    pub fn set_variable(&mut self, name: &str, value: InterpretValue) -> anyhow::Result<()> {
+        self.env.update(name.to_string(), value)
+    }
+
+    pub fn has_function(&self, name: &str) -> bool {
+        self.env.functions.contains_key(name)
+    }
+
+    pub fn call_depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// The heap allocations that haven't been freed yet.
+    pub fn leaks(&self) -> Vec<Allocation> {
+        self.heap.leaks()
+    }
+
+    /// Replaces the heap, so only call it before running a program.
+    pub fn set_memory(&mut self, memory: Memory) {
+        self.heap = Heap::new(memory);
+    }
+
+    /// How many managed allocations are still reachable, after collecting
+    /// the cycles among the rest.
+    pub fn live_allocations(&mut self) -> usize {
+        self.heap.collect_cycles();
+        self.heap.live()
+    }
+
+    pub fn backtrace(&self) -> Vec<Frame> {
+        self.frames.iter().rev().cloned().collect()
+    }
+
+    fn attach_backtrace(&self, e: anyhow::Error) -> anyhow::Error {
+        if e.is::<RuntimeError>() || e.is::<Exit>() {
+            return e;
+        }
+        let error = RuntimeError {
+            message: e.to_string(),
+            source_name: self.source_name.clone(),
+            backtrace: self.backtrace(),
+        };
+        e.context(error)
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    fn start_budget(&mut self) {
+        self.steps = 0;
+        self.started = Instant::now();
+    }
+
+    fn step(&mut self) -> anyhow::Result<()> {
+        // a cancel made while nothing ran stops the next run
+        if self.cancel.0.swap(false, Ordering::Relaxed) {
+            anyhow::bail!(LimitExceeded::Cancelled);
+        }
+
+        self.steps += 1;
+        if let Some(max_steps) = self.limits.max_steps
+            && self.steps > max_steps
+        {
+            anyhow::bail!(LimitExceeded::Steps(max_steps));
+        }
+        if let Some(timeout) = self.limits.timeout
+            && self.started.elapsed() > timeout
+        {
+            anyhow::bail!(LimitExceeded::Time(timeout));
+        }
+        if let Some(max_memory) = self.limits.max_memory
+            && self.steps.is_multiple_of(MEMORY_CHECK_INTERVAL)
+            && self.env.allocated() + self.heap.allocated() > max_memory
+        {
+            anyhow::bail!(LimitExceeded::Memory(max_memory));
+        }
+        if self.heap.needs_collection() {
+            self.heap.collect_cycles();
+        }
+
+        Ok(())
     }
 
     fn exec_stmt(&mut self, statement: &Statement) -> anyhow::Result<ControlFlow> {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.location = statement.location;
+        }
+        self.step()
+            .and_then(|_| self.run_hook(statement))
+            .and_then(|_| self.exec_stmt_inner(statement))
+            .map_err(|e| self.attach_backtrace(e))
+    }
+
+    fn run_hook(&mut self, statement: &Statement) -> anyhow::Result<()> {
+        if let Some(mut hook) = self.hook.take() {
+            let result = hook.before_statement(self, statement);
+            self.hook = Some(hook);
+            result?;
+        }
+        Ok(())
+    }
+
+    fn exec_stmt_inner(&mut self, statement: &Statement) -> anyhow::Result<ControlFlow> {
         match &statement.stmt {
-            Stmt::Let { name, value, ty } => {
-                let (val, _) = self.eval_expr(value)?;
-                if let Some(ty) = ty {
-                    let inferred_ty = match &val {
-                        InterpretValue::I8(_) => Type::Numeric(NumericType::I8),
-                        InterpretValue::I16(_) => Type::Numeric(NumericType::I16),
-                        InterpretValue::I32(_) => Type::Numeric(NumericType::I32),
-                        InterpretValue::I64(_) => Type::Numeric(NumericType::I64),
-                        InterpretValue::ISize(_) => Type::Numeric(NumericType::ISize),
-                        InterpretValue::U8(_) => Type::Numeric(NumericType::U8),
-                        InterpretValue::U16(_) => Type::Numeric(NumericType::U16),
-                        InterpretValue::U32(_) => Type::Numeric(NumericType::U32),
-                        InterpretValue::U64(_) => Type::Numeric(NumericType::U64),
-                        InterpretValue::USize(_) => Type::Numeric(NumericType::USize),
-                        InterpretValue::F32(_) => Type::Numeric(NumericType::F32),
-                        InterpretValue::F64(_) => Type::Numeric(NumericType::F64),
-                        InterpretValue::Boolean(_) => Type::Boolean,
-                        InterpretValue::String(_) => Type::Named("String".to_string()),
-                        InterpretValue::Pointer(_, t) => Type::Pointer(Box::new(t.clone())),
-                        InterpretValue::Void => Type::Void,
-                    };
+            Stmt::Static { name, value, .. } => {
+                let val = value!(self.eval_expr(value)?);
+                self.env.set(name.clone(), val)?;
+                Ok(ControlFlow::None)
+            }
+            Stmt::Let {
+                name, value, ty, ..
+            } => {
+                let val = value!(self.eval_expr(value)?);
+                // generic bodies run as they are, without the type arguments
+                if let Some(ty) = ty
+                    && !ty.is_generic()
+                {
+                    // the signature is checked by the semantic analyzer
+                    let inferred_ty = val.ty().unwrap_or_else(|| ty.clone());
                     anyhow::ensure!(
                         &inferred_ty == ty,
                         "Type mismatch: expected {ty:?}, got {inferred_ty:?}",
@@ -740,14 +1679,13 @@ impl Interpreter {
                 Ok(ControlFlow::None)
             }
             Stmt::Extern { name, params, .. } => {
-                self.env.define_function(
-                    name.clone(),
-                    params.clone(),
-                    Box::new(Statement {
-                        stmt: Stmt::Scope { statements: vec![] },
-                        location: Location::default(),
-                    }),
-                )?;
+                self.env.define_extern(name.clone(), params.clone())?;
+                Ok(ControlFlow::None)
+            }
+            Stmt::Impl { methods, .. } => {
+                for method in methods {
+                    self.exec_stmt(method)?;
+                }
                 Ok(ControlFlow::None)
             }
 
@@ -772,7 +1710,7 @@ impl Interpreter {
             }
             Stmt::While { condition, body } => {
                 loop {
-                    let (cond, _) = self.eval_expr(condition)?;
+                    let cond = value!(self.eval_expr(condition)?);
                     if matches!(cond, InterpretValue::Boolean(false)) {
                         break;
                     }
@@ -790,7 +1728,7 @@ impl Interpreter {
                 then_branch,
                 else_branch,
             } => {
-                let (cond, _) = self.eval_expr(condition)?;
+                let cond = value!(self.eval_expr(condition)?);
                 if matches!(cond, InterpretValue::Boolean(true)) {
                     self.env.push_scope();
                     let mut result = ControlFlow::None;
@@ -825,8 +1763,7 @@ impl Interpreter {
             }
             Stmt::Return { value } => {
                 let val = if let Some(expr) = value {
-                    let (v, _) = self.eval_expr(expr)?;
-                    v
+                    value!(self.eval_expr(expr)?)
                 } else {
                     InterpretValue::Void
                 };
@@ -834,7 +1771,9 @@ impl Interpreter {
             }
             Stmt::Break => Ok(ControlFlow::Break),
             Stmt::Continue => Ok(ControlFlow::Continue),
-            Stmt::Semicolon => Ok(ControlFlow::None),
+            // uses of constants are folded away by the semantic analyzer
+            // enums only exist for the semantic analyzer
+            Stmt::Semicolon | Stmt::Const { .. } | Stmt::Enum { .. } => Ok(ControlFlow::None),
         }
     }
 
@@ -845,230 +1784,664 @@ impl Interpreter {
                 ControlFlow::None,
             )),
             Expr::Variable(name) => Ok((self.env.get(name)?, ControlFlow::None)),
+            Expr::Null { ty } => {
+                let ty = match ty {
+                    Type::Pointer(inner) => inner.as_ref().clone(),
+                    _ => Type::Void,
+                };
+                Ok((InterpretValue::Pointer(0, ty), ControlFlow::None))
+            }
             Expr::Binary {
                 left,
                 operator,
                 right,
+            } => self.eval_binary(left, *operator, right),
+            Expr::Unary { operator, operand } => self.eval_unary(*operator, operand),
+            Expr::Assignment { target, value } => self.eval_assignment(target, value),
+            Expr::FunctionCall {
+                name,
+                arguments,
+                type_arguments,
+                ..
+            } if matches!(name.as_str(), "alloc" | "alloc_array" | "free") => {
+                self.call_heap_builtin(name, type_arguments, arguments)
+            }
+            Expr::FunctionCall {
+                name, arguments, ..
+            } => self.call_func(name, arguments),
+            Expr::MethodCall { method, .. } => anyhow::bail!("Unresolved method call: {method}"),
+            Expr::Call { callee, arguments } => self.eval_call(callee, arguments),
+            Expr::Lambda {
+                params,
+                body,
+                value,
+                captures,
+                ..
             } => {
-                let (left_val, _) = self.eval_expr(left)?;
-                let (right_val, _) = self.eval_expr(right)?;
-                let result = match operator {
-                    Operator::Plus => left_val.add(&right_val),
-                    Operator::Minus => left_val.sub(&right_val),
-                    Operator::Asterisk => left_val.mul(&right_val),
-                    Operator::Slash => left_val.div(&right_val),
-                    Operator::Percent => left_val.rem(&right_val),
-                    Operator::Equals => left_val.eq(&right_val),
-                    Operator::NotEquals => left_val.neq(&right_val),
-                    Operator::Greater => left_val.gt(&right_val),
-                    Operator::Less => left_val.lt(&right_val),
-                    Operator::GreaterEquals => left_val.gte(&right_val),
-                    Operator::LessEquals => left_val.lte(&right_val),
-                    Operator::LogicalAnd => left_val.and(&right_val),
-                    Operator::LogicalOr => left_val.or(&right_val),
-                    Operator::Ampersand => left_val.bitand(&right_val),
-                    Operator::Pipe => left_val.bitor(&right_val),
-                    _ => anyhow::bail!("Unknown binary operator '{:?}'", operator),
-                }?;
-                Ok((result, ControlFlow::None))
-            }
-            Expr::Unary { operator, operand } => {
-                let (operand_val, _) = self.eval_expr(operand)?;
-                let result = match operator {
-                    Operator::Minus => operand_val.neg(),
-                    Operator::Exclem => operand_val.not(),
-                    Operator::Asterisk => operand_val._deref(),
-                    Operator::Ampersand => operand_val._ref(),
-                    _ => anyhow::bail!("Unknown unary operator '{:?}'", operator),
-                }?;
-                Ok((result, ControlFlow::None))
-            }
-            Expr::Assignment { target, value } => {
-                let (val, _) = self.eval_expr(value)?;
-                let target = if let Expr::Variable(name) = target.as_ref() {
-                    name.clone()
-                } else if let Expr::Unary { operator, operand } = target.as_ref() {
-                    if *operator == Operator::Asterisk {
-                        let (op, _) = self.eval_expr(operand)?;
-                        if let InterpretValue::Pointer(ptr, ty) = &op {
-                            unsafe {
-                                match ty {
-                                    // ! TODO: FIX ME
-                                    Type::Boolean => {
-                                        *(*ptr as *mut bool) = match val {
-                                            InterpretValue::Boolean(b) => b,
-                                            _ => anyhow::bail!("Type mismatch in assignment"),
-                                        }
-                                    }
-                                    Type::Numeric(n) => match n {
-                                        NumericType::I8 => {
-                                            *(*ptr as *mut i8) = match val {
-                                                InterpretValue::I8(v) => v,
-                                                _ => anyhow::bail!("Type mismatch in assignment"),
-                                            }
-                                        }
-                                        NumericType::I16 => {
-                                            *(*ptr as *mut i16) = match val {
-                                                InterpretValue::I16(v) => v,
-                                                _ => anyhow::bail!("Type mismatch in assignment"),
-                                            }
-                                        }
-                                        NumericType::I32 => {
-                                            *(*ptr as *mut i32) = match val {
-                                                InterpretValue::I32(v) => v,
-                                                _ => anyhow::bail!("Type mismatch in assignment"),
-                                            }
-                                        }
-                                        NumericType::I64 => {
-                                            *(*ptr as *mut i64) = match val {
-                                                InterpretValue::I64(v) => v,
-                                                _ => anyhow::bail!("Type mismatch in assignment"),
-                                            }
-                                        }
-                                        NumericType::ISize => {
-                                            *(*ptr as *mut isize) = match val {
-                                                InterpretValue::ISize(v) => v,
-                                                _ => anyhow::bail!("Type mismatch in assignment"),
-                                            }
-                                        }
-                                        NumericType::U8 => {
-                                            *(*ptr as *mut u8) = match val {
-                                                InterpretValue::U8(v) => v,
-                                                _ => anyhow::bail!("Type mismatch in assignment"),
-                                            }
-                                        }
-                                        NumericType::U16 => {
-                                            *(*ptr as *mut u16) = match val {
-                                                InterpretValue::U16(v) => v,
-                                                _ => anyhow::bail!("Type mismatch in assignment"),
-                                            }
-                                        }
-                                        NumericType::U32 => {
-                                            *(*ptr as *mut u32) = match val {
-                                                InterpretValue::U32(v) => v,
-                                                _ => anyhow::bail!("Type mismatch in assignment"),
-                                            }
-                                        }
-                                        NumericType::U64 => {
-                                            *(*ptr as *mut u64) = match val {
-                                                InterpretValue::U64(v) => v,
-                                                _ => anyhow::bail!("Type mismatch in assignment"),
-                                            }
-                                        }
-                                        NumericType::USize => {
-                                            *(*ptr as *mut usize) = match val {
-                                                InterpretValue::USize(v) => v,
-                                                _ => anyhow::bail!("Type mismatch in assignment"),
-                                            }
-                                        }
-                                        NumericType::F32 => {
-                                            *(*ptr as *mut f32) = match val {
-                                                InterpretValue::F32(v) => v,
-                                                _ => anyhow::bail!("Type mismatch in assignment"),
-                                            }
-                                        }
-                                        NumericType::F64 => {
-                                            *(*ptr as *mut f64) = match val {
-                                                InterpretValue::F64(v) => v,
-                                                _ => anyhow::bail!("Type mismatch in assignment"),
-                                            }
-                                        }
-                                    },
-                                    Type::Named(n) => match n.as_str() {
-                                        "String" => {
-                                            *(*ptr as *mut String) = match val {
-                                                InterpretValue::String(v) => v,
-                                                _ => anyhow::bail!("Type mismatch in assignment"),
-                                            }
-                                        }
-                                        _ => anyhow::bail!(
-                                            "Dereference is not supported for given value type"
-                                        ),
-                                    },
-                                    _ => anyhow::bail!(
-                                        "Dereference is not supported for given value type"
-                                    ),
-                                }
-                            }
-                        }
-                        return Ok((op, ControlFlow::None));
-                    } else {
-                        anyhow::bail!("Invalid assignment target");
-                    }
+                let captures = captures
+                    .iter()
+                    .map(|x| Ok((x.name.clone(), self.env.get(&x.name)?)))
+                    .collect::<anyhow::Result<_>>()?;
+                let closure = Closure {
+                    params: params.clone(),
+                    body: body.clone(),
+                    value: value.as_deref().cloned(),
+                    captures,
+                };
+                Ok((
+                    InterpretValue::Closure(Arc::new(closure)),
+                    ControlFlow::None,
+                ))
+            }
+            Expr::Interpolated { parts } => self.eval_interpolated(parts),
+            Expr::If {
+                condition,
+                then_branch,
+                then_value,
+                else_branch,
+                else_value,
+            } => {
+                let cond = value!(self.eval_expr(condition)?);
+                let (branch, value) = if matches!(cond, InterpretValue::Boolean(true)) {
+                    (then_branch, then_value)
                 } else {
-                    anyhow::bail!("Invalid assignment target");
+                    (else_branch, else_value)
                 };
-                self.env.update(target, val)?;
+                self.env.push_scope();
+                let result = self.eval_block(branch, value);
+                self.env.pop_scope();
+                result
+            }
+            Expr::Variant {
+                enum_name,
+                variant,
+                arguments,
+            } => {
+                let fields = value!(self.eval_values(arguments)?);
+                let value = InterpretValue::Enum {
+                    enum_name: enum_name.clone(),
+                    variant: variant.clone(),
+                    fields,
+                };
+                Ok((value, ControlFlow::None))
+            }
+            Expr::Match { scrutinee, arms } => self.eval_match(scrutinee, arms),
+        }
+    }
+
+    fn eval_binary(
+        &mut self,
+        left: &Expr,
+        operator: Operator,
+        right: &Expr,
+    ) -> anyhow::Result<(InterpretValue, ControlFlow)> {
+        let left_val = value!(self.eval_expr(left)?);
+        if let (Operator::LogicalAnd, InterpretValue::Boolean(false))
+        | (Operator::LogicalOr, InterpretValue::Boolean(true)) = (operator, &left_val)
+        {
+            return Ok((left_val, ControlFlow::None));
+        }
+        let right_val = value!(self.eval_expr(right)?);
+        let result = left_val.binary_op(operator, &right_val)?;
+        Ok((result, ControlFlow::None))
+    }
+
+    fn eval_unary(
+        &mut self,
+        operator: Operator,
+        operand: &Expr,
+    ) -> anyhow::Result<(InterpretValue, ControlFlow)> {
+        if operator == Operator::Ampersand {
+            return self.eval_address(operand);
+        }
+        let operand_val = value!(self.eval_expr(operand)?);
+        let result = match operand_val {
+            InterpretValue::HeapPointer { block, offset, .. } if operator == Operator::Asterisk => {
+                self.heap.read(&block, offset)?
+            }
+            _ => operand_val.unary_op(operator)?,
+        };
+        Ok((result, ControlFlow::None))
+    }
+
+    fn eval_assignment(
+        &mut self,
+        target: &Expr,
+        value: &Expr,
+    ) -> anyhow::Result<(InterpretValue, ControlFlow)> {
+        let val = value!(self.eval_expr(value)?);
+        match target {
+            Expr::Variable(name) => {
+                self.env.update(name.clone(), val)?;
+                Ok((InterpretValue::Void, ControlFlow::None))
+            }
+            Expr::Unary {
+                operator: Operator::Asterisk,
+                operand,
+            } => {
+                let op = value!(self.eval_expr(operand)?);
+                self.assign_through(&op, val)?;
+                Ok((op, ControlFlow::None))
+            }
+            _ => anyhow::bail!("Invalid assignment target"),
+        }
+    }
+
+    fn eval_call(
+        &mut self,
+        callee: &Expr,
+        arguments: &[Expr],
+    ) -> anyhow::Result<(InterpretValue, ControlFlow)> {
+        let callee = value!(self.eval_expr(callee)?);
+        let args = value!(self.eval_values(arguments)?);
+        let value = match callee {
+            InterpretValue::Function(name) => self.call_with_values(&name, args)?,
+            InterpretValue::Closure(closure) => self.call_closure(&closure, args)?,
+            value => anyhow::bail!("Cannot call {value:?}"),
+        };
+        Ok((value, ControlFlow::None))
+    }
+
+    fn eval_interpolated(
+        &mut self,
+        parts: &[FormatPart],
+    ) -> anyhow::Result<(InterpretValue, ControlFlow)> {
+        let mut output = String::new();
+        for part in parts {
+            match part {
+                FormatPart::Text(text) => output.push_str(text),
+                FormatPart::Expr { expr, spec } => {
+                    let value = value!(self.eval_expr(expr)?);
+                    output.push_str(&value.format(spec)?);
+                }
+            }
+        }
+        Ok((InterpretValue::String(output), ControlFlow::None))
+    }
+
+    fn eval_match(
+        &mut self,
+        scrutinee: &Expr,
+        arms: &[MatchArm],
+    ) -> anyhow::Result<(InterpretValue, ControlFlow)> {
+        let value = value!(self.eval_expr(scrutinee)?);
+        for arm in arms {
+            let mut bindings = Vec::new();
+            if !match_pattern(&arm.pattern, &value, &mut bindings)? {
+                continue;
+            }
+            self.env.push_scope();
+            let result = self.eval_arm(arm, bindings);
+            self.env.pop_scope();
+            return result;
+        }
+        anyhow::bail!("No match arm matched {}", value.as_string())
+    }
+
+    fn eval_arm(
+        &mut self,
+        arm: &MatchArm,
+        bindings: Vec<(String, InterpretValue)>,
+    ) -> anyhow::Result<(InterpretValue, ControlFlow)> {
+        for (name, value) in bindings {
+            self.env.set(name, value)?;
+        }
+        match &arm.value {
+            Some(value) => self.eval_block(&arm.body, value),
+            None => {
+                for stmt in &arm.body {
+                    match self.exec_stmt(stmt)? {
+                        ControlFlow::None => {}
+                        flow => return Ok((InterpretValue::Void, flow)),
+                    }
+                }
                 Ok((InterpretValue::Void, ControlFlow::None))
             }
-            Expr::FunctionCall { name, arguments } => self.call_func(name, arguments),
         }
     }
 
+    fn eval_block(
+        &mut self,
+        statements: &[Statement],
+        value: &Expr,
+    ) -> anyhow::Result<(InterpretValue, ControlFlow)> {
+        for stmt in statements {
+            match self.exec_stmt(stmt)? {
+                ControlFlow::None => {}
+                flow => return Ok((InterpretValue::Void, flow)),
+            }
+        }
+        self.eval_expr(value)
+    }
+
+    /// Stores `val` where `pointer` points, for `*p = val`.
+    fn assign_through(
+        &mut self,
+        pointer: &InterpretValue,
+        val: InterpretValue,
+    ) -> anyhow::Result<()> {
+        match pointer {
+            InterpretValue::HeapPointer { block, offset, .. } => {
+                self.heap.write(block, *offset, val)
+            }
+            InterpretValue::Pointer(0, _) => anyhow::bail!("Null pointer dereference"),
+            _ => anyhow::bail!("Dereference is not supported for given value type"),
+        }
+    }
+
+    fn call_heap_builtin(
+        &mut self,
+        name: &str,
+        type_arguments: &[Type],
+        arguments: &[Expr],
+    ) -> anyhow::Result<(InterpretValue, ControlFlow)> {
+        let args = value!(self.eval_values(arguments)?);
+        let len = match (name, args.as_slice()) {
+            ("alloc", []) => 1,
+            ("alloc_array", [len]) if len.is_integer() => {
+                let len = len.as_integer();
+                anyhow::ensure!(len >= 0, "Cannot allocate an array of {} values", len);
+                len as usize
+            }
+            ("free", [InterpretValue::HeapPointer { block, offset, .. }]) => {
+                self.heap.free(block, *offset)?;
+                return Ok((InterpretValue::Void, ControlFlow::None));
+            }
+            ("free", [InterpretValue::Pointer(0, _)]) => {
+                return Ok((InterpretValue::Void, ControlFlow::None));
+            }
+            ("free", [value]) => anyhow::bail!("Cannot free {value:?}, it wasn't allocated"),
+            _ => anyhow::bail!("Invalid arguments to '{}': {:?}", name, args),
+        };
+        if let Some(max_memory) = self.limits.max_memory
+            && self.env.allocated()
+                + self.heap.allocated()
+                + len.saturating_mul(std::mem::size_of::<InterpretValue>())
+                > max_memory
+        {
+            anyhow::bail!(LimitExceeded::Memory(max_memory));
+        }
+        let allocation =
+            self.allocation(type_arguments.first().cloned().unwrap_or(Type::Void), len);
+        Ok((self.heap.alloc(allocation)?, ControlFlow::None))
+    }
+
+    /// Describes `len` values of `ty` allocated by the running statement.
+    fn allocation(&self, ty: Type, len: usize) -> Allocation {
+        let (function, location) = match self.frames.last() {
+            Some(frame) => (frame.function.clone(), frame.location),
+            None => ("<script>".to_string(), Location::default()),
+        };
+        Allocation {
+            ty,
+            len,
+            function,
+            location,
+        }
+    }
+
+    /// `&operand`: a pointer to the block of a variable, or to a new one
+    /// holding the value of any other expression.
+    fn eval_address(&mut self, operand: &Expr) -> anyhow::Result<(InterpretValue, ControlFlow)> {
+        let value = match operand {
+            // `&*p` is `p`
+            Expr::Unary {
+                operator: Operator::Asterisk,
+                operand,
+            } => return self.eval_expr(operand),
+            Expr::Variable(name) => self.env.get(name)?,
+            _ => value!(self.eval_expr(operand)?),
+        };
+        let ty = value.ty().unwrap_or(Type::Void);
+        let allocation = self.allocation(ty.clone(), 1);
+        let block = match operand {
+            Expr::Variable(name) => self
+                .env
+                .address(name, |value| self.heap.variable(value, allocation))?,
+            _ => self.heap.variable(value, allocation),
+        };
+        Ok((
+            InterpretValue::HeapPointer {
+                block,
+                offset: 0,
+                ty,
+            },
+            ControlFlow::None,
+        ))
+    }
+
     pub fn call_func(
         &mut self,
         name: &str,
         arguments: &[Expr],
     ) -> anyhow::Result<(InterpretValue, ControlFlow)> {
+        let args = value!(self.eval_values(arguments)?);
+        Ok((self.call_with_values(name, args)?, ControlFlow::None))
+    }
+
+    /// Evaluates `exprs` in order, stopping at the first one left by a
+    /// `return`, `break` or `continue`.
+    fn eval_values(
+        &mut self,
+        exprs: &[Expr],
+    ) -> anyhow::Result<(Vec<InterpretValue>, ControlFlow)> {
+        let mut values = Vec::with_capacity(exprs.len());
+        for expr in exprs {
+            match self.eval_expr(expr)? {
+                (value, ControlFlow::None) => values.push(value),
+                (_, flow) => return Ok((values, flow)),
+            }
+        }
+        Ok((values, ControlFlow::None))
+    }
+
+    /// Calls `name` from the host, with a fresh step and time budget. Calls
+    /// made by the script share the budget of the statement running them.
+    pub fn call(
+        &mut self,
+        name: &str,
+        args: Vec<InterpretValue>,
+    ) -> anyhow::Result<InterpretValue> {
+        self.start_budget();
+        self.call_with_values(name, args)
+    }
+
+    pub fn call_with_values(
+        &mut self,
+        name: &str,
+        args: Vec<InterpretValue>,
+    ) -> anyhow::Result<InterpretValue> {
+        self.call_frame(name, |this| this.call_function(name, args))
+    }
+
+    fn call_closure(
+        &mut self,
+        closure: &Closure,
+        args: Vec<InterpretValue>,
+    ) -> anyhow::Result<InterpretValue> {
+        self.call_frame("<closure>", |this| this.run_closure(closure, args))
+    }
+
+    /// Runs `call` in a new frame, within the call depth limit.
+    fn call_frame(
+        &mut self,
+        name: &str,
+        call: impl FnOnce(&mut Self) -> anyhow::Result<InterpretValue>,
+    ) -> anyhow::Result<InterpretValue> {
+        if let Some(max_call_depth) = self.limits.max_call_depth
+            && self.call_depth >= max_call_depth
+        {
+            anyhow::bail!(LimitExceeded::CallDepth(max_call_depth));
+        }
+
+        let call_site = self.frames.last().map(|frame| frame.location);
+        self.frames.push(Frame {
+            function: name.to_string(),
+            call_site,
+            location: call_site.unwrap_or_default(),
+        });
+        self.call_depth += 1;
+        let result = call(self);
+        self.call_depth -= 1;
+        self.frames.pop();
+        result
+    }
+
+    fn call_function(
+        &mut self,
+        name: &str,
+        args: Vec<InterpretValue>,
+    ) -> anyhow::Result<InterpretValue> {
         let function = self.env.get_function(name)?.clone();
 
         match function {
             Function::Native { func } => {
-                let mut args = Vec::new();
-                for arg_expr in arguments {
-                    let (arg_value, _) = self.eval_expr(arg_expr)?;
-                    args.push(arg_value);
-                }
-                let result = func.call(args)?;
-                Ok((result, ControlFlow::None))
+                anyhow::ensure!(
+                    self.limits.policy.allows(name),
+                    "Call to native function '{}' is not allowed",
+                    name
+                );
+                func.call(args)
+            }
+            Function::Extern { .. } => {
+                anyhow::ensure!(
+                    self.limits.policy.allows(name),
+                    "Call to extern function '{}' is not allowed",
+                    name
+                );
+                Ok(InterpretValue::Void)
             }
             Function::Interpreted { params, body } => {
-                if arguments.len() != params.len() {
+                if args.len() != params.len() {
                     anyhow::bail!(
                         "Function '{}' expected {} arguments but got {}",
                         name,
                         params.len(),
-                        arguments.len()
+                        args.len()
                     );
                 }
-                self.env.push_scope();
-                for (param, arg_expr) in params.iter().zip(arguments.iter()) {
-                    let (arg_value, _) = self.eval_expr(arg_expr)?;
+                // hosts pass chars as `u8`, only ASCII fits in a char
+                let args = params
+                    .iter()
+                    .zip(args)
+                    .map(|(param, value)| match (&param.ty, value) {
+                        (Type::Named(ty), InterpretValue::U8(c)) if ty == "char" => {
+                            anyhow::ensure!(
+                                c.is_ascii(),
+                                "Argument {} of '{}' isn't an ASCII char",
+                                c,
+                                name
+                            );
+                            Ok(InterpretValue::Char(c))
+                        }
+                        (_, value) => Ok(value),
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                self.env.enter_call();
+                for (param, arg_value) in params.iter().zip(args) {
                     self.env.set(param.name.clone(), arg_value)?;
                 }
                 let control_flow = match &body.stmt {
                     Stmt::Scope { statements } => {
-                        let mut result = ControlFlow::None;
+                        let mut result = Ok(ControlFlow::None);
                         for stmt in statements {
-                            result = self.exec_stmt(stmt)?;
+                            result = self.exec_stmt(stmt);
                             match result {
-                                ControlFlow::Return(_)
-                                | ControlFlow::Break
-                                | ControlFlow::Continue => break,
-                                ControlFlow::None => {}
+                                Ok(ControlFlow::Return(_))
+                                | Ok(ControlFlow::Break)
+                                | Ok(ControlFlow::Continue)
+                                | Err(_) => break,
+                                Ok(ControlFlow::None) => {}
                             }
                         }
                         result
                     }
-                    _ => self.exec_stmt(&body)?,
+                    _ => self.exec_stmt(&body),
                 };
 
-                self.env.pop_scope();
-                match control_flow {
-                    ControlFlow::Return(val) => Ok((val, ControlFlow::None)),
-                    _ => Ok((InterpretValue::Void, ControlFlow::None)),
+                self.env.leave_call();
+                match control_flow? {
+                    ControlFlow::Return(val) => Ok(val),
+                    _ => Ok(InterpretValue::Void),
                 }
             }
         }
     }
 
+    fn run_closure(
+        &mut self,
+        closure: &Closure,
+        args: Vec<InterpretValue>,
+    ) -> anyhow::Result<InterpretValue> {
+        anyhow::ensure!(
+            args.len() == closure.params.len(),
+            "Closure expected {} arguments but got {}",
+            closure.params.len(),
+            args.len()
+        );
+        self.env.enter_call();
+        let result = (|| {
+            for (name, value) in &closure.captures {
+                self.env.set(name.clone(), value.clone())?;
+            }
+            for (param, arg_value) in closure.params.iter().zip(args) {
+                self.env.set(param.name.clone(), arg_value)?;
+            }
+            for stmt in &closure.body {
+                match self.exec_stmt(stmt)? {
+                    ControlFlow::Return(value) => return Ok(value),
+                    ControlFlow::None => {}
+                    _ => return Ok(InterpretValue::Void),
+                }
+            }
+            match &closure.value {
+                Some(value) => match self.eval_expr(value)? {
+                    (_, ControlFlow::Return(value)) | (value, _) => Ok(value),
+                },
+                None => Ok(InterpretValue::Void),
+            }
+        })();
+        self.env.leave_call();
+        result
+    }
+
+    pub fn define_native<F>(&mut self, name: &str, func: F) -> anyhow::Result<()>
+    where
+        F: Fn(Vec<InterpretValue>) -> anyhow::Result<InterpretValue> + Send + Sync + 'static,
+    {
+        self.env.define_rust_function(name.to_string(), func)
+    }
+
+    pub fn replace_native<F>(&mut self, name: &str, func: F)
+    where
+        F: Fn(Vec<InterpretValue>) -> anyhow::Result<InterpretValue> + Send + Sync + 'static,
+    {
+        self.env.functions.insert(
+            name.to_string(),
+            Function::Native {
+                func: Arc::new(func),
+            },
+        );
+    }
+
+    pub fn get_global(&self, name: &str) -> anyhow::Result<InterpretValue> {
+        self.env.scopes[0]
+            .get(name)
+            .map(Slot::value)
+            .ok_or_else(|| anyhow::anyhow!("Global '{}' not found", name))
+    }
+
+    pub fn set_global(&mut self, name: &str, value: InterpretValue) {
+        self.env.scopes[0].insert(name.to_string(), Slot::Value(value));
+    }
+
     pub fn interpret(&mut self, statements: &[Statement]) -> anyhow::Result<ControlFlow> {
+        self.start_budget();
+        self.frames.push(Frame {
+            function: "<top-level>".to_string(),
+            call_site: None,
+            location: Location::default(),
+        });
         let mut control_flow = ControlFlow::None;
         for stmt in statements {
-            control_flow = self
-                .exec_stmt(stmt)
-                .map_err(|e| anyhow::anyhow!("{} at {:?}", e, stmt.location))?;
+            control_flow = match self.exec_stmt(stmt) {
+                Ok(flow) => flow,
+                Err(e) => {
+                    self.frames.pop();
+                    return Err(e);
+                }
+            };
         }
+        self.frames.pop();
         Ok(control_flow)
     }
 }
+
+/// Rewrites the exponent of Rust's `{:e}` output the way C prints it, with a
+/// sign and at least two digits.
+fn c_exponent(formatted: &str) -> String {
+    let (mantissa, exponent) = formatted.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    let sign = if exponent < 0 { '-' } else { '+' };
+    format!("{mantissa}e{sign}{:02}", exponent.abs())
+}
+
+fn overflow() -> anyhow::Error {
+    anyhow::anyhow!("Arithmetic overflow")
+}
+
+/// C's `%g`: six significant digits, trailing zeros removed, exponential
+/// notation for very large or small values.
+pub(crate) fn c_general(value: f64) -> String {
+    const PRECISION: i32 = 6;
+    if value == 0.0 {
+        return if value.is_sign_negative() { "-0" } else { "0" }.to_string();
+    }
+
+    let strip = |x: String| {
+        if x.contains('.') {
+            x.trim_end_matches('0').trim_end_matches('.').to_string()
+        } else {
+            x
+        }
+    };
+    let scientific = format!("{:.*e}", PRECISION as usize - 1, value);
+    let exponent: i32 = scientific.split_once('e').unwrap().1.parse().unwrap();
+    if (-4..PRECISION).contains(&exponent) {
+        strip(format!("{:.*}", (PRECISION - 1 - exponent) as usize, value))
+    } else {
+        let (mantissa, _) = scientific.split_once('e').unwrap();
+        c_exponent(&format!("{}e{exponent}", strip(mantissa.to_string())))
+    }
+}
+
+/// Tests `value` against `pattern`, collecting the variables it binds.
+fn match_pattern(
+    pattern: &Pattern,
+    value: &InterpretValue,
+    bindings: &mut Vec<(String, InterpretValue)>,
+) -> anyhow::Result<bool> {
+    Ok(match pattern {
+        Pattern::Wildcard => true,
+        Pattern::Binding(name) => {
+            bindings.push((name.clone(), value.clone()));
+            true
+        }
+        Pattern::Literal(literal) => matches!(
+            value.eq(&InterpretValue::from_literal(literal.clone())?)?,
+            InterpretValue::Boolean(true)
+        ),
+        Pattern::Range {
+            start,
+            end,
+            inclusive,
+        } => {
+            let start = InterpretValue::from_literal(start.clone())?;
+            let end = InterpretValue::from_literal(end.clone())?;
+            let below_end = if *inclusive {
+                value.lte(&end)?
+            } else {
+                value.lt(&end)?
+            };
+            matches!(value.gte(&start)?, InterpretValue::Boolean(true))
+                && matches!(below_end, InterpretValue::Boolean(true))
+        }
+        Pattern::Variant {
+            variant, fields, ..
+        } => match value {
+            InterpretValue::Enum {
+                variant: value_variant,
+                fields: values,
+                ..
+            } if value_variant == variant => {
+                for (field, value) in fields.iter().zip(values) {
+                    if !match_pattern(field, value, bindings)? {
+                        return Ok(false);
+                    }
+                }
+                true
+            }
+            _ => false,
+        },
+    })
+}
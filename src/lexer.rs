@@ -1,5 +1,6 @@
 use crate::token::{
-    Delimiter, Keyword, Literal, Location, NumericType, Operator, Token, TokenType,
+    Delimiter, FormatSpec, Keyword, Literal, Location, NumericLiteral, NumericType, NumericValue,
+    Operator, StringPart, Token, TokenType,
 };
 
 pub struct Lexer {
@@ -36,30 +37,84 @@ impl Lexer {
 
     fn numeric_literal(&mut self, ch: char, chars: &[char]) -> anyhow::Result<()> {
         let start_loc = self.current_loc;
+        // `ch` is already consumed
+        let start = self.current_loc.index - 1;
+        let negative = ch == '-';
+        let first = if negative {
+            // A '-' directly followed by a digit, the digit isn't consumed yet.
+            let first = chars[self.current_loc.index];
+            self.advance(1);
+            first
+        } else {
+            ch
+        };
+
+        let radix = match (first, chars.get(self.current_loc.index)) {
+            ('0', Some('x' | 'X')) => 16,
+            ('0', Some('o' | 'O')) => 8,
+            ('0', Some('b' | 'B')) => 2,
+            _ => 10,
+        };
+
         let mut literal = String::new();
-        literal.push(ch);
+        if negative {
+            literal.push('-');
+        }
+        let digits_start = literal.len();
+        if radix == 10 {
+            literal.push(first);
+        } else {
+            self.advance(1);
+        }
 
         let mut has_dot = false;
+        let mut has_exponent = false;
 
         while let Some(&c) = chars.get(self.current_loc.index) {
             match c {
-                '0'..='9' => {
+                c if c.is_digit(radix) => {
                     self.advance(1);
                     literal.push(c);
                 }
                 '_' => {
                     self.advance(1);
                 }
-                '.' => {
+                // `1..5` is a range, not a float
+                '.' if radix == 10 && chars.get(self.current_loc.index + 1) != Some(&'.') => {
                     anyhow::ensure!(!has_dot, "Invalid numeric literal: multiple decimal points");
+                    anyhow::ensure!(
+                        !has_exponent,
+                        "Invalid numeric literal: decimal point in exponent"
+                    );
                     has_dot = true;
                     self.advance(1);
                     literal.push('.');
                 }
+                'e' | 'E' if radix == 10 => {
+                    anyhow::ensure!(!has_exponent, "Invalid numeric literal: multiple exponents");
+                    has_exponent = true;
+                    self.advance(1);
+                    literal.push('e');
+                    if let Some(&sign @ ('+' | '-')) = chars.get(self.current_loc.index) {
+                        self.advance(1);
+                        literal.push(sign);
+                    }
+                    anyhow::ensure!(
+                        chars
+                            .get(self.current_loc.index)
+                            .is_some_and(|x| x.is_ascii_digit()),
+                        "Invalid numeric literal: missing exponent digits"
+                    );
+                }
                 _ => break,
             }
         }
 
+        anyhow::ensure!(
+            literal.len() > digits_start,
+            "Invalid numeric literal: missing digits after base prefix"
+        );
+
         let mut suffix = String::new();
 
         while let Some(&c) = chars.get(self.current_loc.index) {
@@ -79,38 +134,261 @@ impl Lexer {
             }
         }
 
-        let numeric_type = if suffix.is_empty() {
-            if has_dot {
-                NumericType::F64
-            } else {
-                NumericType::I32
-            }
+        if let Some(&c) = chars.get(self.current_loc.index) {
+            anyhow::ensure!(
+                !c.is_ascii_alphanumeric(),
+                "Invalid digit '{c}' in numeric literal"
+            );
+        }
+
+        // errors show the literal as written, with its base prefix
+        let text: String = chars[start..self.current_loc.index].iter().collect();
+        let suffix = if suffix.is_empty() {
+            None
+        } else {
+            Some(NumericType::from_suffix(&suffix)?)
+        };
+        let is_float = match suffix {
+            Some(ty) => !ty.is_integer(),
+            None => has_dot || has_exponent,
+        };
+
+        let value = if is_float {
+            anyhow::ensure!(
+                radix == 10,
+                "Invalid numeric literal: float literal cannot have a base prefix"
+            );
+            let value: f64 = literal.parse()?;
+            let finite = match suffix {
+                Some(NumericType::F32) => (value as f32).is_finite(),
+                _ => value.is_finite(),
+            };
+            anyhow::ensure!(
+                finite,
+                "Numeric literal '{text}' is out of range for {:?}",
+                suffix.unwrap_or(NumericType::F64)
+            );
+            NumericValue::Float(value)
         } else {
-            match suffix.as_str() {
-                "i8" => NumericType::I8,
-                "i16" => NumericType::I16,
-                "i32" => NumericType::I32,
-                "i64" => NumericType::I64,
-                "is" => NumericType::ISize,
-                "u8" => NumericType::U8,
-                "u16" => NumericType::U16,
-                "u32" => NumericType::U32,
-                "u64" => NumericType::U64,
-                "us" => NumericType::USize,
-                "f32" => NumericType::F32,
-                "f64" => NumericType::F64,
-                _ => anyhow::bail!("Unknown numeric type suffix '{}'", suffix),
+            anyhow::ensure!(
+                !has_dot && !has_exponent,
+                "Invalid numeric literal: integer literal cannot contain '.' or an exponent",
+            );
+            // unsuffixed literals are checked again once their type is inferred
+            let (min, max) = match suffix {
+                Some(ty) => ty.integer_range().unwrap(),
+                None => (i64::MIN as i128, u64::MAX as i128),
+            };
+            let value = u128::from_str_radix(&literal[digits_start..], radix)
+                .ok()
+                .and_then(|x| i128::try_from(x).ok())
+                .map(|x| if negative { -x } else { x })
+                .filter(|x| (min..=max).contains(x))
+                .ok_or_else(|| match suffix {
+                    Some(ty) => {
+                        anyhow::anyhow!("Numeric literal '{text}' is out of range for {ty:?}")
+                    }
+                    None => anyhow::anyhow!("Numeric literal '{text}' is too large"),
+                })?;
+            NumericValue::Integer(value)
+        };
+
+        self.tokens.push(Token::new(
+            TokenType::Literal(Literal::Numeric(NumericLiteral { value, suffix })),
+            start_loc,
+        ));
+        Ok(())
+    }
+
+    fn consume(&mut self, c: char) {
+        self.advance(1);
+        match c {
+            '\n' => {
+                self.current_loc.line += 1;
+                self.current_loc.column = 0;
+            }
+            '\t' => {
+                self.current_loc.column += 3;
+            }
+            _ => {}
+        }
+    }
+
+    fn escape(&mut self, chars: &[char]) -> anyhow::Result<char> {
+        let mut index = self.current_loc.index;
+        let c = escape_sequence(chars, &mut index)?;
+        self.advance(index - self.current_loc.index);
+        Ok(c)
+    }
+
+    fn string_literal(&mut self, chars: &[char]) -> anyhow::Result<()> {
+        let start_loc = self.current_loc;
+        if chars[self.current_loc.index..].starts_with(&['"', '"']) {
+            self.advance(2);
+            return self.block_string(chars, start_loc);
+        }
+
+        // Quotes only end the string outside of interpolations, so that
+        // `"{f("x")}"` works.
+        let mut raw = Vec::new();
+        let mut depth = 0;
+        let mut in_string = false;
+        loop {
+            match chars.get(self.current_loc.index) {
+                Some('"') if depth == 0 => {
+                    self.advance(1);
+                    break;
+                }
+                Some('"') => {
+                    in_string = !in_string;
+                    self.advance(1);
+                    raw.push('"');
+                }
+                Some('\\') => {
+                    self.advance(1);
+                    raw.push('\\');
+                    if let Some(&c) = chars.get(self.current_loc.index) {
+                        self.consume(c);
+                        raw.push(c);
+                    }
+                }
+                Some('{') if depth == 0 && chars.get(self.current_loc.index + 1) == Some(&'{') => {
+                    self.advance(2);
+                    raw.extend(['{', '{']);
+                }
+                Some(&c @ ('{' | '}')) if !in_string => {
+                    if c == '{' {
+                        depth += 1;
+                    } else if depth > 0 {
+                        depth -= 1;
+                    }
+                    self.advance(1);
+                    raw.push(c);
+                }
+                Some(&c) => {
+                    self.consume(c);
+                    raw.push(c);
+                }
+                None => anyhow::bail!(
+                    "Unterminated string literal opened at line {}, column {}",
+                    start_loc.line,
+                    start_loc.column
+                ),
+            }
+        }
+
+        self.tokens
+            .push(Token::new(string_token(&raw, start_loc)?, self.current_loc));
+        Ok(())
+    }
+
+    /// A `"""` string. When the opening quotes end their line, the string is a
+    /// block: that line break is dropped, as is the last one if the closing
+    /// quotes are on their own line, and the indentation common to all lines
+    /// is stripped.
+    fn block_string(&mut self, chars: &[char], start_loc: Location) -> anyhow::Result<()> {
+        let mut raw = String::new();
+        loop {
+            match chars.get(self.current_loc.index) {
+                Some('"') if chars[self.current_loc.index..].starts_with(&['"'; 3]) => {
+                    self.advance(3);
+                    break;
+                }
+                Some('\\') => {
+                    self.advance(1);
+                    raw.push('\\');
+                    if let Some(&c) = chars.get(self.current_loc.index) {
+                        self.consume(c);
+                        raw.push(c);
+                    }
+                }
+                Some(&c) => {
+                    self.consume(c);
+                    raw.push(c);
+                }
+                None => anyhow::bail!(
+                    "Unterminated string literal opened at line {}, column {}",
+                    start_loc.line,
+                    start_loc.column
+                ),
+            }
+        }
+
+        let raw = raw.replace("\r\n", "\n");
+        let text = match raw.strip_prefix('\n') {
+            Some(block) => {
+                let mut lines: Vec<&str> = block.split('\n').collect();
+                let closing = lines.pop().unwrap();
+                let closing_alone = closing.trim().is_empty();
+                if !closing_alone {
+                    lines.push(closing);
+                }
+
+                let indent = lines
+                    .iter()
+                    .filter(|x| !x.trim().is_empty())
+                    .chain(closing_alone.then_some(&closing))
+                    .map(|x| x.len() - x.trim_start().len())
+                    .min()
+                    .unwrap_or(0);
+
+                lines
+                    .iter()
+                    .map(|x| x.get(indent..).unwrap_or(""))
+                    .collect::<Vec<_>>()
+                    .join("\n")
             }
+            None => raw,
         };
 
+        let text = text.chars().collect::<Vec<_>>();
+        self.tokens.push(Token::new(
+            string_token(&text, start_loc)?,
+            self.current_loc,
+        ));
+        Ok(())
+    }
+
+    /// `r"..."` or `r#"..."#` without escape processing. The string ends at a
+    /// `"` followed by as many `#`s as it was opened with.
+    fn raw_string(&mut self, chars: &[char]) -> anyhow::Result<()> {
+        let start_loc = self.current_loc;
+        let mut hashes = 0;
+        while chars.get(self.current_loc.index) == Some(&'#') {
+            self.advance(1);
+            hashes += 1;
+        }
         anyhow::ensure!(
-            !has_dot || matches!(numeric_type, NumericType::F32 | NumericType::F64),
-            "Invalid numeric literal: integer literal cannot contain '.'",
+            chars.get(self.current_loc.index) == Some(&'"'),
+            "Expected '\"' to start a raw string literal"
         );
+        self.advance(1);
+
+        let closing: Vec<char> = std::iter::once('"')
+            .chain(std::iter::repeat_n('#', hashes))
+            .collect();
+        let mut s = String::new();
+        loop {
+            match chars.get(self.current_loc.index) {
+                Some('"') if chars[self.current_loc.index..].starts_with(&closing) => {
+                    self.advance(closing.len());
+                    break;
+                }
+                Some(&c) => {
+                    self.consume(c);
+                    s.push(c);
+                }
+                None => anyhow::bail!(
+                    "Unterminated raw string literal opened at line {}, column {}",
+                    start_loc.line,
+                    start_loc.column
+                ),
+            }
+        }
 
         self.tokens.push(Token::new(
-            TokenType::Literal(Literal::Numeric(literal + "_" + &suffix)),
-            start_loc,
+            TokenType::Literal(Literal::String(s)),
+            self.current_loc,
         ));
         Ok(())
     }
@@ -166,9 +444,22 @@ impl Lexer {
                         && let Some(&next) = chars.get(self.current_loc.index)
                         && next == '.'
                     {
+                        self.advance(1);
+                        let delimiter = if chars.get(self.current_loc.index) == Some(&'=') {
+                            self.advance(1);
+                            Delimiter::RangeInclusive
+                        } else {
+                            Delimiter::Variadic
+                        };
+                        self.tokens.push(Token::new(
+                            TokenType::Delimiter(delimiter),
+                            self.current_loc,
+                        ));
+                        continue;
+                    } else if ch == ':' && chars.get(self.current_loc.index) == Some(&':') {
                         self.advance(1);
                         self.tokens.push(Token::new(
-                            TokenType::Delimiter(Delimiter::Variadic),
+                            TokenType::Delimiter(Delimiter::DoubleColon),
                             self.current_loc,
                         ));
                         continue;
@@ -194,7 +485,14 @@ impl Lexer {
                     ));
                 }
 
-                '+' | '-' | '*' | '/' | '%' | '!' | '>' | '<' | '&' | '|' => {
+                '~' => {
+                    self.tokens.push(Token::new(
+                        TokenType::Operator(Operator::Tilde),
+                        self.current_loc,
+                    ));
+                }
+
+                '+' | '-' | '*' | '/' | '%' | '!' | '>' | '<' | '&' | '|' | '^' => {
                     if let Some(&next) = chars.get(self.current_loc.index) {
                         let operator = match next {
                             '=' => {
@@ -210,6 +508,7 @@ impl Lexer {
                                     '<' => Operator::LessEquals,
                                     '&' => Operator::BitAndAssign,
                                     '|' => Operator::BitOrAssign,
+                                    '^' => Operator::BitXorAssign,
                                     _ => unreachable!(),
                                 }
                             }
@@ -270,6 +569,19 @@ impl Lexer {
                                     }
                                 }
                             }
+                            '<' | '>' if ch == next => {
+                                self.advance(1);
+                                let assign = chars.get(self.current_loc.index) == Some(&'=');
+                                if assign {
+                                    self.advance(1);
+                                }
+                                match (ch, assign) {
+                                    ('<', false) => Operator::ShiftLeft,
+                                    ('<', true) => Operator::ShlAssign,
+                                    ('>', false) => Operator::ShiftRight,
+                                    _ => Operator::ShrAssign,
+                                }
+                            }
                             x => match ch {
                                 '+' => Operator::Plus,
                                 '-' => {
@@ -288,6 +600,7 @@ impl Lexer {
                                 '<' => Operator::Less,
                                 '&' => Operator::Ampersand,
                                 '|' => Operator::Pipe,
+                                '^' => Operator::Caret,
                                 _ => unreachable!(),
                             },
                         };
@@ -304,43 +617,41 @@ impl Lexer {
                 }
 
                 '"' => {
-                    let mut s = String::new();
-
-                    while let Some(&c) = chars.get(self.current_loc.index) {
-                        self.advance(1);
-                        if c == '"' {
-                            break;
-                        }
-                        s.push(c);
-                    }
+                    self.string_literal(&chars)?;
+                }
 
-                    self.tokens.push(Token::new(
-                        TokenType::Literal(Literal::String(s)),
-                        self.current_loc,
-                    ));
+                'r' if matches!(chars.get(self.current_loc.index), Some('"' | '#')) => {
+                    self.raw_string(&chars)?;
                 }
 
                 '\'' => {
-                    let c = if let Some(&c) = chars.get(self.current_loc.index) {
-                        self.advance(1);
-                        c
-                    } else {
-                        anyhow::bail!("Unterminated character literal");
-                    };
-
-                    if let Some(&next) = chars.get(self.current_loc.index) {
-                        if next == '\'' {
+                    let c = match chars.get(self.current_loc.index) {
+                        Some('\\') => {
                             self.advance(1);
-                            self.tokens.push(Token::new(
-                                TokenType::Literal(Literal::Char(c)),
-                                self.current_loc,
-                            ));
-                        } else {
-                            anyhow::bail!("Unterminated character literal");
+                            self.escape(&chars)?
                         }
-                    } else {
-                        anyhow::bail!("Unterminated character literal");
-                    }
+                        Some(&c) if c != '\'' && c != '\n' => {
+                            self.advance(1);
+                            c
+                        }
+                        Some('\'') => anyhow::bail!("Empty character literal"),
+                        _ => anyhow::bail!("Unterminated character literal"),
+                    };
+
+                    anyhow::ensure!(
+                        chars.get(self.current_loc.index) == Some(&'\''),
+                        "Unterminated character literal"
+                    );
+                    self.advance(1);
+                    // a char is one byte, and strings are UTF-8
+                    anyhow::ensure!(
+                        c.is_ascii(),
+                        "Character literal {c:?} is out of range, a char is a single ASCII byte"
+                    );
+                    self.tokens.push(Token::new(
+                        TokenType::Literal(Literal::Char(c)),
+                        self.current_loc,
+                    ));
                 }
 
                 'a'..='z' | 'A'..='Z' | '_' => {
@@ -359,6 +670,12 @@ impl Lexer {
 
                     let token = match ident.as_str() {
                         "let" => TokenType::Keyword(Keyword::Let),
+                        "mut" => TokenType::Keyword(Keyword::Mut),
+                        "const" => TokenType::Keyword(Keyword::Const),
+                        "static" => TokenType::Keyword(Keyword::Static),
+                        "enum" => TokenType::Keyword(Keyword::Enum),
+                        "impl" => TokenType::Keyword(Keyword::Impl),
+                        "match" => TokenType::Keyword(Keyword::Match),
                         "func" => TokenType::Keyword(Keyword::Func),
                         "return" => TokenType::Keyword(Keyword::Return),
                         "if" => TokenType::Keyword(Keyword::If),
@@ -370,6 +687,7 @@ impl Lexer {
                         "extern" => TokenType::Keyword(Keyword::Extern),
                         "import" => TokenType::Keyword(Keyword::Import),
                         "as" => TokenType::Keyword(Keyword::As),
+                        "null" => TokenType::Keyword(Keyword::Null),
                         "true" | "false" => TokenType::Literal(Literal::Boolean(ident)),
                         _ => TokenType::Identifier(ident),
                     };
@@ -386,3 +704,159 @@ impl Lexer {
         Ok(())
     }
 }
+
+/// Decodes the escape sequence after a `\` at `chars[*index]`, moving `index`
+/// past it.
+fn escape_sequence(chars: &[char], index: &mut usize) -> anyhow::Result<char> {
+    let Some(&c) = chars.get(*index) else {
+        anyhow::bail!("Unterminated escape sequence");
+    };
+    *index += 1;
+
+    Ok(match c {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        '0' => '\0',
+        '\\' => '\\',
+        '"' => '"',
+        '\'' => '\'',
+        'x' => {
+            let digits: String = chars[*index..].iter().take(2).collect();
+            anyhow::ensure!(
+                digits.len() == 2 && digits.chars().all(|x| x.is_ascii_hexdigit()),
+                "Invalid escape sequence '\\x{digits}', expected two hex digits"
+            );
+            *index += 2;
+            // strings are UTF-8, a single byte above 0x7F isn't a character
+            let value = u8::from_str_radix(&digits, 16)?;
+            anyhow::ensure!(
+                value.is_ascii(),
+                "Escape sequence '\\x{digits}' is out of range, use '\\u{{{digits}}}' above \\x7F"
+            );
+            char::from(value)
+        }
+        'u' => {
+            anyhow::ensure!(
+                chars.get(*index) == Some(&'{'),
+                "Invalid escape sequence, expected '{{' after '\\u'"
+            );
+            *index += 1;
+
+            let mut digits = String::new();
+            loop {
+                match chars.get(*index) {
+                    Some('}') => {
+                        *index += 1;
+                        break;
+                    }
+                    Some(&c) if c.is_ascii_hexdigit() && digits.len() < 6 => {
+                        *index += 1;
+                        digits.push(c);
+                    }
+                    _ => anyhow::bail!("Invalid unicode escape '\\u{{{digits}'"),
+                }
+            }
+
+            u32::from_str_radix(&digits, 16)
+                .ok()
+                .and_then(char::from_u32)
+                .ok_or_else(|| anyhow::anyhow!("Invalid unicode escape '\\u{{{digits}}}'"))?
+        }
+        x => anyhow::bail!("Unknown escape sequence '\\{x}'"),
+    })
+}
+
+/// Decodes the contents of a string literal, splitting out `{expr:spec}`
+/// interpolations. `{{`, `}}` and `{}` are kept as text.
+fn string_token(text: &[char], location: Location) -> anyhow::Result<TokenType> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut index = 0;
+
+    while let Some(&c) = text.get(index) {
+        index += 1;
+        match (c, text.get(index)) {
+            ('\\', _) => current.push(escape_sequence(text, &mut index)?),
+            ('{', Some('{')) | ('}', Some('}')) => {
+                index += 1;
+                current.push(c);
+            }
+            ('{', Some('}')) => {
+                index += 1;
+                current.push_str("{}");
+            }
+            ('{', _) => {
+                let (source, spec) = interpolation(text, &mut index)?;
+                let mut lexer = Lexer::new(source);
+                lexer.tokenize()?;
+                anyhow::ensure!(!lexer.tokens.is_empty(), "Empty interpolation in string");
+
+                if !current.is_empty() {
+                    parts.push(StringPart::Text(std::mem::take(&mut current)));
+                }
+                parts.push(StringPart::Expr {
+                    tokens: lexer
+                        .tokens
+                        .into_iter()
+                        .map(|x| Token::new(x.ty, location))
+                        .collect(),
+                    spec: FormatSpec::parse(&spec)?,
+                });
+            }
+            _ => current.push(c),
+        }
+    }
+
+    if parts.is_empty() {
+        return Ok(TokenType::Literal(Literal::String(current)));
+    }
+    if !current.is_empty() {
+        parts.push(StringPart::Text(current));
+    }
+    Ok(TokenType::Interpolated(parts))
+}
+
+/// Reads an interpolation up to its closing `}`, returning the expression
+/// source and the format spec.
+fn interpolation(text: &[char], index: &mut usize) -> anyhow::Result<(String, String)> {
+    let mut source = String::new();
+    let mut spec = None::<String>;
+    let mut depth = 0;
+    let mut in_string = false;
+
+    loop {
+        let Some(&c) = text.get(*index) else {
+            anyhow::bail!("Unterminated interpolation in string, expected '}}'");
+        };
+        *index += 1;
+
+        match c {
+            '"' => in_string = !in_string,
+            '\\' if in_string => {
+                source.push(c);
+                if let Some(&next) = text.get(*index) {
+                    *index += 1;
+                    source.push(next);
+                }
+                continue;
+            }
+            _ if in_string => {}
+            '{' => depth += 1,
+            '}' if depth == 0 => break,
+            '}' => depth -= 1,
+            ':' if depth == 0 && spec.is_none() => {
+                spec = Some(String::new());
+                continue;
+            }
+            _ => {}
+        }
+
+        match &mut spec {
+            Some(spec) => spec.push(c),
+            None => source.push(c),
+        }
+    }
+
+    Ok((source, spec.unwrap_or_default()))
+}
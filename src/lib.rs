@@ -0,0 +1,12 @@
+pub mod codegen;
+pub mod debugger;
+pub mod engine;
+pub mod heap;
+pub mod interpreter;
+pub mod lexer;
+pub mod native_functions;
+pub mod parser;
+pub mod sema;
+pub mod token;
+
+pub use engine::{Engine, Program};
@@ -1,4 +1,8 @@
-use std::{path::Path, process::Command};
+use std::{
+    io::{BufReader, stdin, stdout},
+    path::Path,
+    process::Command,
+};
 
 use inkwell::{
     OptimizationLevel,
@@ -6,31 +10,77 @@ use inkwell::{
     targets::{CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine},
 };
 
-use crate::{codegen::CodeGen, lexer::Lexer, parser::Parser, sema::SymbolTable};
-
-mod codegen;
-mod interpreter;
-mod lexer;
-mod native_functions;
-mod parser;
-mod sema;
-mod token;
+use lang::{
+    Engine,
+    codegen::{self, CodeGen},
+    debugger::Debugger,
+    heap::Memory,
+    interpreter::{Exit, InterpretValue},
+    lexer::Lexer,
+    parser::Parser,
+    sema::SymbolTable,
+};
 
 #[cfg(test)]
 mod tests;
 
 fn main() -> anyhow::Result<()> {
     let args = std::env::args().collect::<Vec<_>>();
-    if args.len() != 2 {
-        anyhow::bail!("Usage: {} <source_file>", args[0]);
+    let (debug, rest) = match &args[1..] {
+        [command, rest @ ..] if command == "debug" => (true, rest),
+        rest => (false, rest),
+    };
+    let usage = || {
+        anyhow::anyhow!(
+            "Usage: {} [debug] [--managed] [--overflow-checks] <source_file>",
+            args[0]
+        )
+    };
+    let (name, flags) = rest.split_last().ok_or_else(usage)?;
+    let mut memory = Memory::Manual;
+    let mut overflow_checks = false;
+    for flag in flags {
+        match flag.as_str() {
+            "--managed" => memory = Memory::Managed,
+            "--overflow-checks" => overflow_checks = true,
+            _ => return Err(usage()),
+        }
+    }
+    if debug {
+        // the interpreter always checks for overflow
+        debug_file(name, memory)?;
+    } else {
+        run_file(name, memory, overflow_checks)?;
     }
 
-    run_file(&args[1])?;
+    Ok(())
+}
+
+fn debug_file(name: &str, memory: Memory) -> anyhow::Result<()> {
+    let mut engine = Engine::new();
+    engine.set_memory(memory);
+    let program = engine.compile_file(name)?;
+    engine.set_debug_hook(Box::new(Debugger::new(
+        BufReader::new(stdin()),
+        stdout(),
+        &std::fs::read_to_string(name)?,
+    )));
+
+    if engine.run(&program)?.is_none() && engine.has_function("main") {
+        match engine.call::<InterpretValue>("main", ()) {
+            Err(e) if !e.is::<Exit>() => return Err(e),
+            _ => {}
+        }
+    }
+
+    for leak in engine.leaks() {
+        eprintln!("warning: leaked {leak}");
+    }
 
     Ok(())
 }
 
-fn run_file(name: &str) -> anyhow::Result<()> {
+fn run_file(name: &str, memory: Memory, overflow_checks: bool) -> anyhow::Result<()> {
     let mut lexer = Lexer::new(std::fs::read_to_string(name)?);
     lexer
         .tokenize()
@@ -44,12 +94,16 @@ fn run_file(name: &str) -> anyhow::Result<()> {
     // dbg!(&parser.global_scope);
 
     let mut sym_table = SymbolTable::new();
+    sym_table.set_memory(memory);
     sym_table
-        .build(&parser.global_scope)
+        .build(&mut parser.global_scope)
         .map_err(|e| anyhow::anyhow!("Semantic: {e}"))?;
 
     let context = Context::create();
     let mut codegen = CodeGen::new(&context);
+    codegen.set_memory(memory);
+    codegen.set_overflow_checks(overflow_checks);
+    codegen.set_source_name(name);
     let module = codegen
         .generate(&parser.global_scope)
         .map_err(|e| anyhow::anyhow!("CodeGen: {e}"))?;
@@ -88,20 +142,20 @@ fn run_file(name: &str) -> anyhow::Result<()> {
         )
         .unwrap();
 
-    Command::new("clang")
-        .args([
-            build_path
-                .join(name)
-                .with_extension("o")
-                .to_string_lossy()
-                .as_ref(),
-            "-o",
-            build_path
-                .join(name)
-                .with_extension("")
-                .to_string_lossy()
-                .as_ref(),
-        ])
+    let runtime_path = build_path.join(name).with_extension("runtime.c");
+    std::fs::write(&runtime_path, codegen::RUNTIME)?;
+
+    let mut clang = Command::new("clang");
+    clang.arg(build_path.join(name).with_extension("o"));
+    clang.arg(&runtime_path);
+    if memory == Memory::Managed {
+        let gc_path = build_path.join(name).with_extension("gc.c");
+        std::fs::write(&gc_path, codegen::GC_RUNTIME)?;
+        clang.arg(&gc_path).arg("-DLANG_MANAGED");
+    }
+    clang
+        .arg("-o")
+        .arg(build_path.join(name).with_extension(""))
         .status()
         .map_err(|e| anyhow::anyhow!("Failed to execute clang: {}", e))?;
 
@@ -1,30 +1,32 @@
-use crate::interpreter::InterpretValue;
+use crate::interpreter::{Exit, InterpretValue, c_general};
 
 pub fn print(args: Vec<InterpretValue>) -> anyhow::Result<InterpretValue> {
+    println!("{}", format_print(&args));
+    Ok(InterpretValue::Void)
+}
+
+pub fn format_print(args: &[InterpretValue]) -> String {
     match args.len() {
-        0 => {
-            println!();
-        }
-        1 => {
-            println!("{}", args[0].as_string());
-        }
+        0 => String::new(),
+        1 => args[0].as_string(),
         _ => match args[0] {
             InterpretValue::String(ref first) => {
                 let mut output = first.clone();
                 for arg in &args[1..] {
                     output = output.replacen("{}", &arg.as_string(), 1);
                 }
-                println!("{}", output);
+                output
             }
             _ => {
+                let mut output = String::new();
                 for arg in args {
-                    print!("{} ", arg.as_string());
+                    output.push_str(&arg.as_string());
+                    output.push(' ');
                 }
-                println!();
+                output
             }
         },
     }
-    Ok(InterpretValue::Void)
 }
 
 pub fn exit(args: Vec<InterpretValue>) -> anyhow::Result<InterpretValue> {
@@ -33,5 +35,109 @@ pub fn exit(args: Vec<InterpretValue>) -> anyhow::Result<InterpretValue> {
     } else {
         args[0].as_integer() as i32
     };
-    std::process::exit(code);
+    Err(Exit(code).into())
+}
+
+fn string_arg<'a>(name: &str, args: &'a [InterpretValue], index: usize) -> anyhow::Result<&'a str> {
+    match args.get(index) {
+        Some(InterpretValue::String(s)) => Ok(s),
+        x => anyhow::bail!("'{name}' expected a String argument, got {x:?}"),
+    }
+}
+
+pub fn len(args: Vec<InterpretValue>) -> anyhow::Result<InterpretValue> {
+    Ok(InterpretValue::USize(string_arg("len", &args, 0)?.len()))
+}
+
+pub fn slice(args: Vec<InterpretValue>) -> anyhow::Result<InterpretValue> {
+    let s = string_arg("slice", &args, 0)?;
+    anyhow::ensure!(args.len() == 3, "'slice' expected 3 arguments");
+    let (start, end) = (args[1].as_integer(), args[2].as_integer());
+    anyhow::ensure!(
+        0 <= start && start <= end && end as usize <= s.len(),
+        "Slice {start}..{end} out of range for String of length {}",
+        s.len()
+    );
+    match s.get(start as usize..end as usize) {
+        Some(slice) => Ok(InterpretValue::String(slice.to_string())),
+        None => anyhow::bail!("Slice {start}..{end} is not on a character boundary"),
+    }
+}
+
+/// `push(&s, tail)` appends `tail` to the String `s`, through the pointer.
+pub fn push(args: Vec<InterpretValue>) -> anyhow::Result<InterpretValue> {
+    let tail = string_arg("push", &args, 1)?;
+    let Some(InterpretValue::HeapPointer { block, offset, .. }) = args.first() else {
+        anyhow::bail!(
+            "'push' expected a pointer to a String, got {:?}",
+            args.first()
+        );
+    };
+    let value = match block.read(*offset)? {
+        InterpretValue::String(s) => s + tail,
+        x => anyhow::bail!("'push' expected a pointer to a String, got one to {x:?}"),
+    };
+    block.write(*offset, InterpretValue::String(value))?;
+    Ok(InterpretValue::Void)
+}
+
+pub fn to_string(args: Vec<InterpretValue>) -> anyhow::Result<InterpretValue> {
+    Ok(InterpretValue::String(match args.first() {
+        Some(InterpretValue::F32(v)) => c_general(*v as f64),
+        Some(InterpretValue::F64(v)) => c_general(*v),
+        Some(v) => v.as_string(),
+        None => anyhow::bail!("'to_string' expected 1 argument"),
+    }))
+}
+
+pub fn parse_int(args: Vec<InterpretValue>) -> anyhow::Result<InterpretValue> {
+    let s = string_arg("parse_int", &args, 0)?;
+    match s.parse() {
+        Ok(v) => Ok(InterpretValue::I64(v)),
+        Err(_) => anyhow::bail!("Invalid integer '{s}'"),
+    }
+}
+
+pub fn parse_float(args: Vec<InterpretValue>) -> anyhow::Result<InterpretValue> {
+    let s = string_arg("parse_float", &args, 0)?;
+    match s.parse() {
+        Ok(v) => Ok(InterpretValue::F64(v)),
+        Err(_) => anyhow::bail!("Invalid float '{s}'"),
+    }
+}
+
+/// Strings are already usable by host functions, so this is the identity.
+pub fn c_str(args: Vec<InterpretValue>) -> anyhow::Result<InterpretValue> {
+    Ok(InterpretValue::String(
+        string_arg("c_str", &args, 0)?.to_string(),
+    ))
+}
+
+fn integer_builtin(name: &str, args: &[InterpretValue]) -> anyhow::Result<InterpretValue> {
+    anyhow::ensure!(args.len() == 2, "'{name}' expected 2 arguments");
+    args[0].integer_builtin(name, &args[1])
+}
+
+pub fn wrapping_add(args: Vec<InterpretValue>) -> anyhow::Result<InterpretValue> {
+    integer_builtin("wrapping_add", &args)
+}
+
+pub fn wrapping_sub(args: Vec<InterpretValue>) -> anyhow::Result<InterpretValue> {
+    integer_builtin("wrapping_sub", &args)
+}
+
+pub fn wrapping_mul(args: Vec<InterpretValue>) -> anyhow::Result<InterpretValue> {
+    integer_builtin("wrapping_mul", &args)
+}
+
+pub fn saturating_add(args: Vec<InterpretValue>) -> anyhow::Result<InterpretValue> {
+    integer_builtin("saturating_add", &args)
+}
+
+pub fn saturating_sub(args: Vec<InterpretValue>) -> anyhow::Result<InterpretValue> {
+    integer_builtin("saturating_sub", &args)
+}
+
+pub fn saturating_mul(args: Vec<InterpretValue>) -> anyhow::Result<InterpretValue> {
+    integer_builtin("saturating_mul", &args)
 }
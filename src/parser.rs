@@ -1,5 +1,8 @@
+use std::collections::HashMap;
+
 use crate::token::{
-    Delimiter, Keyword, Literal, Location, NumericType, Operator, Token, TokenType,
+    Delimiter, FormatSpec, Keyword, Literal, Location, NumericType, NumericValue, Operator,
+    StringPart, Token, TokenType,
 };
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -7,11 +10,34 @@ pub enum Stmt {
     Expr(Expr),
     Let {
         name: String,
+        mutable: bool,
         ty: Option<Type>,
         value: Expr,
     },
+    Const {
+        name: String,
+        ty: Type,
+        value: Expr,
+    },
+    Static {
+        name: String,
+        mutable: bool,
+        ty: Type,
+        value: Expr,
+    },
+    Enum {
+        name: String,
+        variants: Vec<Variant>,
+    },
+    /// `impl Shape { ... }`. The methods are functions named like
+    /// `Shape::area`, their first parameter is `self` if they take one.
+    Impl {
+        name: String,
+        methods: Vec<Statement>,
+    },
     Func {
         name: String,
+        type_params: Vec<String>,
         params: Vec<Param>,
         ty: Type,
         body: Box<Statement>,
@@ -62,12 +88,101 @@ pub enum Expr {
     },
     FunctionCall {
         name: String,
+        /// The types a generic function is called with, inferred by the
+        /// semantic analyzer.
+        type_arguments: Vec<Type>,
+        arguments: Vec<Expr>,
+    },
+    /// A call through a function value, like `compare(a, b)` where `compare`
+    /// is a variable.
+    Call {
+        callee: Box<Expr>,
         arguments: Vec<Expr>,
     },
+    /// `shape.area()`, rewritten by the semantic analyzer to a call of the
+    /// method of the receiver's type.
+    MethodCall {
+        receiver: Box<Expr>,
+        method: String,
+        arguments: Vec<Expr>,
+    },
+    /// `|i32 x| => i32 { x + offset }`. A lambda without a return type has a
+    /// plain block as its body and no value.
+    Lambda {
+        params: Vec<Param>,
+        ty: Type,
+        body: Vec<Statement>,
+        value: Option<Box<Expr>>,
+        /// The local variables the body uses, copied into the closure when
+        /// it's created. Filled in by the semantic analyzer.
+        captures: Vec<Param>,
+    },
     Assignment {
         target: Box<Expr>,
         value: Box<Expr>,
     },
+    /// `null`, a pointer of the type the context expects, inferred by the
+    /// semantic analyzer like the type of a numeric literal.
+    Null {
+        ty: Type,
+    },
+    If {
+        condition: Box<Expr>,
+        then_branch: Vec<Statement>,
+        then_value: Box<Expr>,
+        else_branch: Vec<Statement>,
+        else_value: Box<Expr>,
+    },
+    Interpolated {
+        parts: Vec<FormatPart>,
+    },
+    Variant {
+        enum_name: String,
+        variant: String,
+        arguments: Vec<Expr>,
+    },
+    Match {
+        scrutinee: Box<Expr>,
+        arms: Vec<MatchArm>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variant {
+    pub name: String,
+    pub fields: Vec<Type>,
+}
+
+/// One `pattern => value` arm. Arms of a `match` statement have no value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub body: Vec<Statement>,
+    pub value: Option<Expr>,
+    pub location: Location,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pattern {
+    Wildcard,
+    Binding(String),
+    Literal(Literal),
+    Range {
+        start: Literal,
+        end: Literal,
+        inclusive: bool,
+    },
+    Variant {
+        enum_name: String,
+        variant: String,
+        fields: Vec<Pattern>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormatPart {
+    Text(String),
+    Expr { expr: Expr, spec: FormatSpec },
 }
 
 #[allow(unused)]
@@ -82,11 +197,110 @@ pub enum Type {
     Named(String),
     Numeric(NumericType),
     Pointer(Box<Type>),
+    /// A type parameter of the generic function being declared.
+    Param(String),
+    /// A pointer to a function, `func(i32, i32) => i32`.
+    Function {
+        params: Vec<Type>,
+        ty: Box<Type>,
+    },
+    /// A lambda with the values it captured, `|i32, i32| => i32`.
+    Closure {
+        params: Vec<Type>,
+        ty: Box<Type>,
+    },
     Boolean,
     Variadic,
     Void,
 }
 
+impl Type {
+    /// Replaces the type parameters in `bindings`.
+    pub fn substitute(&self, bindings: &HashMap<String, Type>) -> Type {
+        match self {
+            Type::Param(name) => bindings.get(name).cloned().unwrap_or_else(|| self.clone()),
+            Type::Pointer(inner) => Type::Pointer(Box::new(inner.substitute(bindings))),
+            Type::Function { params, ty } => Type::Function {
+                params: params.iter().map(|x| x.substitute(bindings)).collect(),
+                ty: Box::new(ty.substitute(bindings)),
+            },
+            Type::Closure { params, ty } => Type::Closure {
+                params: params.iter().map(|x| x.substitute(bindings)).collect(),
+                ty: Box::new(ty.substitute(bindings)),
+            },
+            ty => ty.clone(),
+        }
+    }
+
+    /// Whether the type mentions a type parameter.
+    pub fn is_generic(&self) -> bool {
+        match self {
+            Type::Param(_) => true,
+            Type::Pointer(inner) => inner.is_generic(),
+            Type::Function { params, ty } | Type::Closure { params, ty } => {
+                params.iter().any(Type::is_generic) || ty.is_generic()
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the type mentions the type parameter `name`.
+    pub fn mentions(&self, name: &str) -> bool {
+        match self {
+            Type::Param(param) => param == name,
+            Type::Pointer(inner) => inner.mentions(name),
+            Type::Function { params, ty } | Type::Closure { params, ty } => {
+                params.iter().any(|x| x.mentions(name)) || ty.mentions(name)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Named(name) | Type::Param(name) => write!(f, "{name}"),
+            Type::Numeric(numeric) => write!(
+                f,
+                "{}",
+                match numeric {
+                    NumericType::I8 => "i8",
+                    NumericType::I16 => "i16",
+                    NumericType::I32 => "i32",
+                    NumericType::I64 => "i64",
+                    NumericType::ISize => "is",
+                    NumericType::U8 => "u8",
+                    NumericType::U16 => "u16",
+                    NumericType::U32 => "u32",
+                    NumericType::U64 => "u64",
+                    NumericType::USize => "us",
+                    NumericType::F32 => "f32",
+                    NumericType::F64 => "f64",
+                }
+            ),
+            Type::Pointer(inner) => write!(f, "*{inner}"),
+            Type::Function { params, ty } => {
+                let params: Vec<String> = params.iter().map(|x| x.to_string()).collect();
+                match ty.as_ref() {
+                    Type::Void => write!(f, "func({})", params.join(", ")),
+                    ty => write!(f, "func({}) => {ty}", params.join(", ")),
+                }
+            }
+            Type::Closure { params, ty } => {
+                let params: Vec<String> = params.iter().map(|x| x.to_string()).collect();
+                match ty.as_ref() {
+                    Type::Void => write!(f, "|{}|", params.join(", ")),
+                    ty => write!(f, "|{}| => {ty}", params.join(", ")),
+                }
+            }
+            Type::Boolean => write!(f, "bool"),
+            Type::Variadic => write!(f, ".."),
+            Type::Void => write!(f, "void"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Param {
     pub name: String,
@@ -97,6 +311,10 @@ pub struct Parser {
     pub tokens: Vec<Token>,
     pub current_index: usize,
     pub global_scope: Vec<Statement>,
+    /// The type parameters of the function being parsed.
+    type_params: Vec<String>,
+    /// The type whose `impl` block is being parsed.
+    self_type: Option<String>,
 }
 
 impl Parser {
@@ -105,6 +323,8 @@ impl Parser {
             tokens,
             current_index: 0,
             global_scope: Vec::new(),
+            type_params: Vec::new(),
+            self_type: None,
         }
     }
 
@@ -147,6 +367,24 @@ impl Parser {
         self.parse_assignment()
     }
 
+    /// Parses the tokens of a `{expr}` in a string literal.
+    fn parse_interpolation(mut tokens: Vec<Token>) -> anyhow::Result<Expr> {
+        let location = tokens[0].location;
+        tokens.push(Token::new(
+            TokenType::Delimiter(Delimiter::RBrace),
+            location,
+        ));
+
+        let mut parser = Parser::new(tokens);
+        let expr = parser.parse_expr()?;
+        parser.expect_delim(Delimiter::RBrace)?;
+        anyhow::ensure!(
+            parser.is_at_end(),
+            "Unexpected tokens after interpolated expression at {location:?}"
+        );
+        Ok(expr)
+    }
+
     fn parse_expr_stmt(&mut self) -> anyhow::Result<Stmt> {
         let expr = self.parse_expr()?;
         self.expect_delim(Delimiter::Semicolon)?;
@@ -172,7 +410,10 @@ impl Parser {
                 | Operator::DivAssign
                 | Operator::ModAssign
                 | Operator::BitAndAssign
-                | Operator::BitOrAssign),
+                | Operator::BitOrAssign
+                | Operator::BitXorAssign
+                | Operator::ShlAssign
+                | Operator::ShrAssign),
             ) => {
                 self.advance()?;
                 let right = self.parse_assignment()?;
@@ -185,6 +426,9 @@ impl Parser {
                     Operator::ModAssign => Operator::Percent,
                     Operator::BitAndAssign => Operator::Ampersand,
                     Operator::BitOrAssign => Operator::Pipe,
+                    Operator::BitXorAssign => Operator::Caret,
+                    Operator::ShlAssign => Operator::ShiftLeft,
+                    Operator::ShrAssign => Operator::ShiftRight,
                     _ => unreachable!(),
                 };
 
@@ -233,6 +477,7 @@ impl Parser {
         if let TokenType::Operator(
             operator @ (Operator::Minus
             | Operator::Exclem
+            | Operator::Tilde
             | Operator::Asterisk
             | Operator::Ampersand),
         ) = self.peek()?.ty
@@ -246,20 +491,97 @@ impl Parser {
             });
         }
 
-        self.parse_primary()
+        let mut expr = self.parse_primary()?;
+        loop {
+            match self.peek()?.ty {
+                TokenType::Delimiter(Delimiter::LParen) => {
+                    expr = Expr::Call {
+                        callee: Box::new(expr),
+                        arguments: self.parse_arguments()?,
+                    };
+                }
+                TokenType::Delimiter(Delimiter::LBracket) => {
+                    // `p[i]` is `*(p + i)`
+                    self.advance()?;
+                    let index = self.parse_expr()?;
+                    self.expect_delim(Delimiter::RBracket)?;
+                    expr = Expr::Unary {
+                        operator: Operator::Asterisk,
+                        operand: Box::new(Expr::Binary {
+                            left: Box::new(expr),
+                            operator: Operator::Plus,
+                            right: Box::new(index),
+                        }),
+                    };
+                }
+                TokenType::Delimiter(Delimiter::Dot) => {
+                    self.advance()?;
+                    let method = match self.advance()?.ty.clone() {
+                        TokenType::Identifier(method) => method,
+                        t => anyhow::bail!("Expected method name, found {:?}", t),
+                    };
+                    expr = Expr::MethodCall {
+                        receiver: Box::new(expr),
+                        method,
+                        arguments: self.parse_arguments()?,
+                    };
+                }
+                _ => return Ok(expr),
+            }
+        }
     }
 
     fn parse_primary(&mut self) -> anyhow::Result<Expr> {
         match self.advance()?.ty.clone() {
             TokenType::Literal(lit) => Ok(Expr::Literal(lit)),
+            TokenType::Interpolated(parts) => Ok(Expr::Interpolated {
+                parts: parts
+                    .into_iter()
+                    .map(|part| {
+                        Ok(match part {
+                            StringPart::Text(text) => FormatPart::Text(text),
+                            StringPart::Expr { tokens, spec } => FormatPart::Expr {
+                                expr: Parser::parse_interpolation(tokens)?,
+                                spec,
+                            },
+                        })
+                    })
+                    .collect::<anyhow::Result<_>>()?,
+            }),
 
-            TokenType::Identifier(name) => {
-                if matches!(self.peek()?.ty, TokenType::Delimiter(Delimiter::LParen)) {
-                    self.parse_call(name)
-                } else {
-                    Ok(Expr::Variable(name))
+            TokenType::Identifier(name) => match self.peek()?.ty {
+                TokenType::Delimiter(Delimiter::LParen) => self.parse_call(name),
+                TokenType::Operator(Operator::Less) => match self.parse_type_arguments() {
+                    Some(type_arguments) => Ok(Expr::FunctionCall {
+                        name,
+                        type_arguments,
+                        arguments: self.parse_arguments()?,
+                    }),
+                    None => Ok(Expr::Variable(name)),
+                },
+                TokenType::Delimiter(Delimiter::DoubleColon) => {
+                    self.advance()?;
+                    let variant = match self.advance()?.ty.clone() {
+                        TokenType::Identifier(variant) => variant,
+                        t => anyhow::bail!("Expected variant name, found {:?}", t),
+                    };
+                    let arguments =
+                        if matches!(self.peek()?.ty, TokenType::Delimiter(Delimiter::LParen)) {
+                            match self.parse_call(variant.clone())? {
+                                Expr::FunctionCall { arguments, .. } => arguments,
+                                _ => unreachable!(),
+                            }
+                        } else {
+                            Vec::new()
+                        };
+                    Ok(Expr::Variant {
+                        enum_name: name,
+                        variant,
+                        arguments,
+                    })
                 }
-            }
+                _ => Ok(Expr::Variable(name)),
+            },
 
             TokenType::Delimiter(Delimiter::LParen) => {
                 let expr = self.parse_expr()?;
@@ -267,11 +589,400 @@ impl Parser {
                 Ok(expr)
             }
 
+            TokenType::Keyword(Keyword::If) => self.parse_if_expr(),
+            TokenType::Keyword(Keyword::Match) => self.parse_match(false),
+            TokenType::Keyword(Keyword::Null) => Ok(Expr::Null {
+                ty: Type::Pointer(Box::new(Type::Void)),
+            }),
+            TokenType::Operator(Operator::Pipe) => self.parse_lambda(),
+            TokenType::Operator(Operator::LogicalOr) => self.parse_lambda_rest(Vec::new()),
+
             t => anyhow::bail!("Unexpected token in expression: {:?}", t),
         }
     }
 
+    /// Parses a lambda after its opening `|`.
+    fn parse_lambda(&mut self) -> anyhow::Result<Expr> {
+        let mut params = Vec::new();
+        if !matches!(self.peek()?.ty, TokenType::Operator(Operator::Pipe)) {
+            loop {
+                params.push(self.parse_param()?);
+                if matches!(self.peek()?.ty, TokenType::Delimiter(Delimiter::Comma)) {
+                    self.advance()?;
+                } else {
+                    break;
+                }
+            }
+        }
+        match self.advance()?.ty {
+            TokenType::Operator(Operator::Pipe) => self.parse_lambda_rest(params),
+            ref t => anyhow::bail!("Expected '|' after lambda parameters, found {:?}", t),
+        }
+    }
+
+    fn parse_lambda_rest(&mut self, params: Vec<Param>) -> anyhow::Result<Expr> {
+        let ty = if matches!(self.peek()?.ty, TokenType::Delimiter(Delimiter::Arrow)) {
+            self.advance()?;
+            self.parse_type()?
+        } else {
+            Type::Void
+        };
+
+        let (body, value) = if ty == Type::Void {
+            match self.parse_scope()?.stmt {
+                Stmt::Scope { statements } => (statements, None),
+                _ => unreachable!(),
+            }
+        } else {
+            let (body, value) = self.parse_value_block()?;
+            (body, Some(Box::new(value)))
+        };
+        Ok(Expr::Lambda {
+            params,
+            ty,
+            body,
+            value,
+            captures: Vec::new(),
+        })
+    }
+
+    fn parse_if_expr(&mut self) -> anyhow::Result<Expr> {
+        let condition = self.parse_expr()?;
+        let (then_branch, then_value) = self.parse_value_block()?;
+
+        anyhow::ensure!(
+            matches!(self.peek()?.ty, TokenType::Keyword(Keyword::Else)),
+            "Expected 'else' branch in if expression"
+        );
+        self.advance()?;
+
+        let (else_branch, else_value) =
+            if matches!(self.peek()?.ty, TokenType::Keyword(Keyword::If)) {
+                self.advance()?;
+                (Vec::new(), self.parse_if_expr()?)
+            } else {
+                self.parse_value_block()?
+            };
+
+        Ok(Expr::If {
+            condition: Box::new(condition),
+            then_branch,
+            then_value: Box::new(then_value),
+            else_branch,
+            else_value: Box::new(else_value),
+        })
+    }
+
+    /// Parses the part of a `match` after the keyword. The arms of a statement
+    /// don't produce values, their expressions become statements.
+    fn parse_match(&mut self, statement: bool) -> anyhow::Result<Expr> {
+        let scrutinee = self.parse_expr()?;
+        self.expect_delim(Delimiter::LBrace)?;
+
+        let mut arms = Vec::new();
+        while !matches!(self.peek()?.ty, TokenType::Delimiter(Delimiter::RBrace)) {
+            let location = self.peek()?.location;
+            let pattern = self.parse_pattern()?;
+            self.expect_delim(Delimiter::Arrow)?;
+
+            let is_block = matches!(self.peek()?.ty, TokenType::Delimiter(Delimiter::LBrace));
+            let (body, value) = match (is_block, statement) {
+                (true, true) => match self.parse_scope()?.stmt {
+                    Stmt::Scope { statements } => (statements, None),
+                    _ => unreachable!(),
+                },
+                (true, false) => {
+                    let (body, value) = self.parse_value_block()?;
+                    (body, Some(value))
+                }
+                (false, true) => {
+                    let stmt_location = self.peek()?.location;
+                    let stmt = if matches!(self.peek()?.ty, TokenType::Keyword(Keyword::Return)) {
+                        self.advance()?;
+                        let value = match self.peek()?.ty {
+                            TokenType::Delimiter(Delimiter::Comma | Delimiter::RBrace) => None,
+                            _ => Some(self.parse_expr()?),
+                        };
+                        Stmt::Return { value }
+                    } else {
+                        Stmt::Expr(self.parse_expr()?)
+                    };
+                    (
+                        vec![Statement {
+                            stmt,
+                            location: stmt_location,
+                        }],
+                        None,
+                    )
+                }
+                (false, false) => (Vec::new(), Some(self.parse_expr()?)),
+            };
+            arms.push(MatchArm {
+                pattern,
+                body,
+                value,
+                location,
+            });
+
+            match self.peek()?.ty {
+                TokenType::Delimiter(Delimiter::Comma) => {
+                    self.advance()?;
+                }
+                TokenType::Delimiter(Delimiter::RBrace) => {}
+                _ if is_block => {}
+                _ => anyhow::bail!("Expected ',' after match arm"),
+            }
+        }
+        self.expect_delim(Delimiter::RBrace)?;
+
+        Ok(Expr::Match {
+            scrutinee: Box::new(scrutinee),
+            arms,
+        })
+    }
+
+    fn parse_pattern(&mut self) -> anyhow::Result<Pattern> {
+        match self.advance()?.ty.clone() {
+            TokenType::Identifier(name) if name == "_" => Ok(Pattern::Wildcard),
+            TokenType::Identifier(enum_name)
+                if matches!(
+                    self.peek()?.ty,
+                    TokenType::Delimiter(Delimiter::DoubleColon)
+                ) =>
+            {
+                self.advance()?;
+                let variant = match self.advance()?.ty.clone() {
+                    TokenType::Identifier(variant) => variant,
+                    t => anyhow::bail!("Expected variant name, found {:?}", t),
+                };
+
+                let mut fields = Vec::new();
+                if matches!(self.peek()?.ty, TokenType::Delimiter(Delimiter::LParen)) {
+                    self.advance()?;
+                    while !matches!(self.peek()?.ty, TokenType::Delimiter(Delimiter::RParen)) {
+                        fields.push(self.parse_pattern()?);
+                        if matches!(self.peek()?.ty, TokenType::Delimiter(Delimiter::Comma)) {
+                            self.advance()?;
+                        } else {
+                            break;
+                        }
+                    }
+                    self.expect_delim(Delimiter::RParen)?;
+                }
+
+                Ok(Pattern::Variant {
+                    enum_name,
+                    variant,
+                    fields,
+                })
+            }
+            TokenType::Identifier(name) => Ok(Pattern::Binding(name)),
+            TokenType::Literal(_) | TokenType::Operator(Operator::Minus) => {
+                self.current_index -= 1;
+                let start = self.parse_pattern_literal()?;
+                let inclusive = match self.peek()?.ty {
+                    TokenType::Delimiter(Delimiter::Variadic) => false,
+                    TokenType::Delimiter(Delimiter::RangeInclusive) => true,
+                    _ => return Ok(Pattern::Literal(start)),
+                };
+                self.advance()?;
+                let end = self.parse_pattern_literal()?;
+                Ok(Pattern::Range {
+                    start,
+                    end,
+                    inclusive,
+                })
+            }
+            t => anyhow::bail!("Expected pattern, found {:?}", t),
+        }
+    }
+
+    /// A literal in a pattern, numbers may be negative.
+    fn parse_pattern_literal(&mut self) -> anyhow::Result<Literal> {
+        match self.advance()?.ty.clone() {
+            TokenType::Literal(literal) => Ok(literal),
+            TokenType::Operator(Operator::Minus) => match self.advance()?.ty.clone() {
+                TokenType::Literal(Literal::Numeric(mut literal)) => {
+                    literal.value = match literal.value {
+                        NumericValue::Integer(value) => NumericValue::Integer(-value),
+                        NumericValue::Float(value) => NumericValue::Float(-value),
+                    };
+                    Ok(Literal::Numeric(literal))
+                }
+                t => anyhow::bail!("Expected number after '-' in pattern, found {:?}", t),
+            },
+            t => anyhow::bail!("Expected literal in pattern, found {:?}", t),
+        }
+    }
+
+    fn parse_enum(&mut self) -> anyhow::Result<Stmt> {
+        self.advance()?; // enum
+
+        let name = match self.advance()?.ty.clone() {
+            TokenType::Identifier(name) => name,
+            t => anyhow::bail!("Expected enum name, found {:?}", t),
+        };
+
+        self.expect_delim(Delimiter::LBrace)?;
+        let mut variants = Vec::new();
+        while !matches!(self.peek()?.ty, TokenType::Delimiter(Delimiter::RBrace)) {
+            let variant = match self.advance()?.ty.clone() {
+                TokenType::Identifier(variant) => variant,
+                t => anyhow::bail!("Expected variant name, found {:?}", t),
+            };
+
+            let mut fields = Vec::new();
+            if matches!(self.peek()?.ty, TokenType::Delimiter(Delimiter::LParen)) {
+                self.advance()?;
+                while !matches!(self.peek()?.ty, TokenType::Delimiter(Delimiter::RParen)) {
+                    fields.push(self.parse_type()?);
+                    if matches!(self.peek()?.ty, TokenType::Delimiter(Delimiter::Comma)) {
+                        self.advance()?;
+                    } else {
+                        break;
+                    }
+                }
+                self.expect_delim(Delimiter::RParen)?;
+            }
+            variants.push(Variant {
+                name: variant,
+                fields,
+            });
+
+            if matches!(self.peek()?.ty, TokenType::Delimiter(Delimiter::Comma)) {
+                self.advance()?;
+            } else {
+                break;
+            }
+        }
+        self.expect_delim(Delimiter::RBrace)?;
+
+        Ok(Stmt::Enum { name, variants })
+    }
+
+    fn parse_impl(&mut self) -> anyhow::Result<Stmt> {
+        self.advance()?; // impl
+
+        let name = match self.advance()?.ty.clone() {
+            TokenType::Identifier(name) => name,
+            t => anyhow::bail!("Expected type name, found {:?}", t),
+        };
+
+        self.expect_delim(Delimiter::LBrace)?;
+        let mut methods = Vec::new();
+        while !matches!(self.peek()?.ty, TokenType::Delimiter(Delimiter::RBrace)) {
+            let location = self.peek()?.location;
+            anyhow::ensure!(
+                matches!(self.peek()?.ty, TokenType::Keyword(Keyword::Func)),
+                "Expected method in impl block for '{}' at {:?}",
+                name,
+                location
+            );
+            self.self_type = Some(name.clone());
+            let mut stmt = self.parse_function()?;
+            if let Stmt::Func { name: method, .. } = &mut stmt {
+                *method = format!("{name}::{method}");
+            }
+            methods.push(Statement { stmt, location });
+        }
+        self.expect_delim(Delimiter::RBrace)?;
+
+        Ok(Stmt::Impl { name, methods })
+    }
+
+    /// Parses the `self` or `*self` a method starts with, if it has one.
+    /// Functions nested in the method body don't belong to the type.
+    fn parse_self_param(&mut self) -> anyhow::Result<Option<Param>> {
+        let Some(self_type) = self.self_type.take() else {
+            return Ok(None);
+        };
+        let is_self = |x: Option<&Token>| matches!(x.map(|x| &x.ty), Some(TokenType::Identifier(name)) if name == "self");
+        let ty = Type::Named(self_type);
+        let ty = if is_self(self.tokens.get(self.current_index)) {
+            ty
+        } else if matches!(self.peek()?.ty, TokenType::Operator(Operator::Asterisk))
+            && is_self(self.tokens.get(self.current_index + 1))
+        {
+            self.advance()?;
+            Type::Pointer(Box::new(ty))
+        } else {
+            return Ok(None);
+        };
+        self.advance()?; // self
+        Ok(Some(Param {
+            name: "self".to_string(),
+            ty,
+        }))
+    }
+
+    /// Parses a `{ statements... value }` block ending with an expression
+    /// without a semicolon.
+    fn parse_value_block(&mut self) -> anyhow::Result<(Vec<Statement>, Expr)> {
+        self.expect_delim(Delimiter::LBrace)?;
+        let mut statements = Vec::new();
+
+        loop {
+            let location = self.peek()?.location;
+            let stmt = match self.peek()?.ty {
+                TokenType::Delimiter(Delimiter::RBrace) => {
+                    anyhow::bail!("Expected a value at the end of the block")
+                }
+                TokenType::Keyword(_)
+                | TokenType::Delimiter(Delimiter::Semicolon | Delimiter::LBrace) => {
+                    self.parse_stmt()?
+                }
+                _ => {
+                    let expr = self.parse_expr()?;
+                    if matches!(self.peek()?.ty, TokenType::Delimiter(Delimiter::RBrace)) {
+                        self.advance()?;
+                        return Ok((statements, expr));
+                    }
+                    self.expect_delim(Delimiter::Semicolon)?;
+                    Stmt::Expr(expr)
+                }
+            };
+            statements.push(Statement { stmt, location });
+        }
+    }
+
     fn parse_call(&mut self, name: String) -> anyhow::Result<Expr> {
+        Ok(Expr::FunctionCall {
+            name,
+            type_arguments: Vec::new(),
+            arguments: self.parse_arguments()?,
+        })
+    }
+
+    /// Parses the `<i32>` of a call like `alloc<i32>()`. Anything not followed
+    /// by the `(` of a call is a comparison, and nothing is consumed.
+    fn parse_type_arguments(&mut self) -> Option<Vec<Type>> {
+        let start = self.current_index;
+        let mut type_arguments = Vec::new();
+        self.current_index += 1; // <
+        loop {
+            match self.parse_type() {
+                Ok(ty) => type_arguments.push(ty),
+                Err(_) => break,
+            }
+            match self.tokens.get(self.current_index).map(|x| &x.ty) {
+                Some(TokenType::Delimiter(Delimiter::Comma)) => self.current_index += 1,
+                Some(TokenType::Operator(Operator::Greater))
+                    if matches!(
+                        self.tokens.get(self.current_index + 1).map(|x| &x.ty),
+                        Some(TokenType::Delimiter(Delimiter::LParen))
+                    ) =>
+                {
+                    self.current_index += 1;
+                    return Some(type_arguments);
+                }
+                _ => break,
+            }
+        }
+        self.current_index = start;
+        None
+    }
+
+    fn parse_arguments(&mut self) -> anyhow::Result<Vec<Expr>> {
         self.expect_delim(Delimiter::LParen)?;
         let mut args = Vec::new();
 
@@ -287,10 +998,7 @@ impl Parser {
         }
 
         self.expect_delim(Delimiter::RParen)?;
-        Ok(Expr::FunctionCall {
-            name,
-            arguments: args,
-        })
+        Ok(args)
     }
 
     fn parse_stmt(&mut self) -> anyhow::Result<Stmt> {
@@ -300,6 +1008,21 @@ impl Parser {
                 Ok(Stmt::Semicolon)
             }
             TokenType::Keyword(Keyword::Let) => self.parse_let(),
+            TokenType::Keyword(Keyword::Const) => self.parse_const(),
+            TokenType::Keyword(Keyword::Static) => self.parse_static(),
+            TokenType::Keyword(Keyword::Enum) => self.parse_enum(),
+            TokenType::Keyword(Keyword::Impl) => self.parse_impl(),
+            TokenType::Keyword(Keyword::Match) => {
+                self.advance()?; // match
+                let expr = self.parse_match(true)?;
+                if matches!(
+                    self.peek().map(|x| &x.ty),
+                    Ok(TokenType::Delimiter(Delimiter::Semicolon))
+                ) {
+                    self.advance()?;
+                }
+                Ok(Stmt::Expr(expr))
+            }
             TokenType::Keyword(Keyword::Func) => self.parse_function(),
             TokenType::Keyword(Keyword::While) => self.parse_while(),
             TokenType::Keyword(Keyword::Return) => self.parse_return(),
@@ -363,6 +1086,16 @@ impl Parser {
 
                 let else_branch = if matches!(self.peek()?.ty, TokenType::Keyword(Keyword::Else)) {
                     self.advance()?;
+                    if matches!(self.peek()?.ty, TokenType::Keyword(Keyword::If)) {
+                        // `else if` is sugar for an else branch holding another if
+                        let location = self.peek()?.location;
+                        let stmt = self.parse_stmt()?;
+                        return Ok(Stmt::If {
+                            condition: Box::new(condition),
+                            then_branch,
+                            else_branch: Some(vec![Statement { stmt, location }]),
+                        });
+                    }
                     match self.parse_scope()? {
                         Statement {
                             stmt: Stmt::Scope { statements },
@@ -443,8 +1176,65 @@ impl Parser {
                 "f32" => Type::Numeric(NumericType::F32),
                 "f64" => Type::Numeric(NumericType::F64),
                 "bool" => Type::Boolean,
+                _ if self.type_params.contains(&name) => Type::Param(name),
                 _ => Type::Named(name),
             }),
+            TokenType::Keyword(Keyword::Func) => {
+                self.expect_delim(Delimiter::LParen)?;
+                let mut params = Vec::new();
+                if !matches!(self.peek()?.ty, TokenType::Delimiter(Delimiter::RParen)) {
+                    loop {
+                        params.push(self.parse_type()?);
+                        if matches!(self.peek()?.ty, TokenType::Delimiter(Delimiter::Comma)) {
+                            self.advance()?;
+                        } else {
+                            break;
+                        }
+                    }
+                }
+                self.expect_delim(Delimiter::RParen)?;
+
+                let ty = if matches!(self.peek()?.ty, TokenType::Delimiter(Delimiter::Arrow)) {
+                    self.advance()?;
+                    self.parse_type()?
+                } else {
+                    Type::Void
+                };
+                Ok(Type::Function {
+                    params,
+                    ty: Box::new(ty),
+                })
+            }
+            TokenType::Operator(operator @ (Operator::Pipe | Operator::LogicalOr)) => {
+                let mut params = Vec::new();
+                if operator == Operator::Pipe {
+                    while !matches!(self.peek()?.ty, TokenType::Operator(Operator::Pipe)) {
+                        params.push(self.parse_type()?);
+                        if matches!(self.peek()?.ty, TokenType::Delimiter(Delimiter::Comma)) {
+                            self.advance()?;
+                        } else {
+                            break;
+                        }
+                    }
+                    match self.advance()?.ty {
+                        TokenType::Operator(Operator::Pipe) => {}
+                        ref t => {
+                            anyhow::bail!("Expected '|' after closure parameters, found {:?}", t)
+                        }
+                    }
+                }
+
+                let ty = if matches!(self.peek()?.ty, TokenType::Delimiter(Delimiter::Arrow)) {
+                    self.advance()?;
+                    self.parse_type()?
+                } else {
+                    Type::Void
+                };
+                Ok(Type::Closure {
+                    params,
+                    ty: Box::new(ty),
+                })
+            }
             TokenType::Delimiter(Delimiter::Variadic) => Ok(Type::Variadic),
             t => anyhow::bail!("Expected type, found {:?}", t),
         }
@@ -453,6 +1243,11 @@ impl Parser {
     fn parse_let(&mut self) -> anyhow::Result<Stmt> {
         self.advance()?; // let
 
+        let mutable = matches!(self.peek()?.ty, TokenType::Keyword(Keyword::Mut));
+        if mutable {
+            self.advance()?;
+        }
+
         let name = match self.advance()?.ty.clone() {
             TokenType::Identifier(name) => name,
             t => anyhow::bail!("Expected identifier after let, found {:?}", t),
@@ -474,13 +1269,77 @@ impl Parser {
         let value = self.parse_expr()?;
         self.expect_delim(Delimiter::Semicolon)?;
 
-        Ok(Stmt::Let { name, ty, value })
+        Ok(Stmt::Let {
+            name,
+            mutable,
+            ty,
+            value,
+        })
+    }
+
+    fn parse_const(&mut self) -> anyhow::Result<Stmt> {
+        self.advance()?; // const
+
+        let name = match self.advance()?.ty.clone() {
+            TokenType::Identifier(name) => name,
+            t => anyhow::bail!("Expected identifier after const, found {:?}", t),
+        };
+
+        self.expect_delim(Delimiter::Colon)?;
+        let ty = self.parse_type()?;
+
+        anyhow::ensure!(
+            matches!(self.peek()?.ty, TokenType::Operator(Operator::Assign)),
+            "Expected '=' in const declaration"
+        );
+        self.advance()?;
+
+        let value = self.parse_expr()?;
+        self.expect_delim(Delimiter::Semicolon)?;
+
+        Ok(Stmt::Const { name, ty, value })
+    }
+
+    fn parse_static(&mut self) -> anyhow::Result<Stmt> {
+        self.advance()?; // static
+
+        let mutable = matches!(self.peek()?.ty, TokenType::Keyword(Keyword::Mut));
+        if mutable {
+            self.advance()?;
+        }
+
+        let name = match self.advance()?.ty.clone() {
+            TokenType::Identifier(name) => name,
+            t => anyhow::bail!("Expected identifier after static, found {:?}", t),
+        };
+
+        self.expect_delim(Delimiter::Colon)?;
+        let ty = self.parse_type()?;
+
+        anyhow::ensure!(
+            matches!(self.peek()?.ty, TokenType::Operator(Operator::Assign)),
+            "Expected '=' in static declaration"
+        );
+        self.advance()?;
+
+        let value = self.parse_expr()?;
+        self.expect_delim(Delimiter::Semicolon)?;
+
+        Ok(Stmt::Static {
+            name,
+            mutable,
+            ty,
+            value,
+        })
     }
 
     fn parse_param(&mut self) -> anyhow::Result<Param> {
         let ty = self.parse_type()?;
 
         let name = match self.advance()?.ty.clone() {
+            TokenType::Identifier(name) if name == "self" => {
+                anyhow::bail!("'self' can only be the first parameter of a method")
+            }
             TokenType::Identifier(name) => name,
             t => anyhow::bail!("Expected parameter name, found {:?}", t),
         };
@@ -496,9 +1355,48 @@ impl Parser {
             t => anyhow::bail!("Expected function name, found {:?}", t),
         };
 
+        let mut type_params = Vec::new();
+        if matches!(self.peek()?.ty, TokenType::Operator(Operator::Less)) {
+            self.advance()?;
+            loop {
+                match self.advance()?.ty.clone() {
+                    TokenType::Identifier(param) if !type_params.contains(&param) => {
+                        type_params.push(param)
+                    }
+                    TokenType::Identifier(param) => {
+                        anyhow::bail!("Duplicate type parameter '{}' in '{}'", param, name)
+                    }
+                    t => anyhow::bail!("Expected type parameter, found {:?}", t),
+                }
+                match self.advance()?.ty {
+                    TokenType::Delimiter(Delimiter::Comma) => {}
+                    TokenType::Operator(Operator::Greater) => break,
+                    ref t => {
+                        anyhow::bail!("Expected ',' or '>' after type parameter, found {:?}", t)
+                    }
+                }
+            }
+        }
+        let outer_type_params = std::mem::replace(&mut self.type_params, type_params.clone());
+        let result = self.parse_function_rest(name, type_params);
+        self.type_params = outer_type_params;
+        result
+    }
+
+    fn parse_function_rest(
+        &mut self,
+        name: String,
+        type_params: Vec<String>,
+    ) -> anyhow::Result<Stmt> {
         self.expect_delim(Delimiter::LParen)?;
 
         let mut params = Vec::new();
+        if let Some(param) = self.parse_self_param()? {
+            params.push(param);
+            if matches!(self.peek()?.ty, TokenType::Delimiter(Delimiter::Comma)) {
+                self.advance()?;
+            }
+        }
         if !matches!(self.peek()?.ty, TokenType::Delimiter(Delimiter::RParen)) {
             loop {
                 params.push(self.parse_param()?);
@@ -523,6 +1421,7 @@ impl Parser {
 
         Ok(Stmt::Func {
             name,
+            type_params,
             params,
             ty,
             body,
@@ -1,13 +1,18 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crate::{
-    parser::{Expr, Expression, Statement, Stmt, Type},
-    token::{Literal, Location, NumericType, Operator},
+    heap::Memory,
+    interpreter::InterpretValue,
+    parser::{
+        Expr, Expression, FormatPart, MatchArm, Param, Pattern, Statement, Stmt, Type, Variant,
+    },
+    token::{FormatKind, FormatSpec, Literal, Location, NumericType, NumericValue, Operator},
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SymbolKind {
     Variable,
+    Constant,
     Function,
     Parameter,
 }
@@ -16,19 +21,33 @@ pub enum SymbolKind {
 pub struct Symbol {
     name: String,
     kind: SymbolKind,
+    mutable: bool,
     ty: Type,
     location: Location,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Scope {
     symbols: HashMap<String, Symbol>,
+    /// The folded literal of every constant in `symbols`.
+    constants: HashMap<String, Expr>,
+    /// For the closures in `symbols` that captured local variables, the depth
+    /// of the function whose stack holds the captured values.
+    bounds: HashMap<String, usize>,
+}
+
+impl Default for Scope {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Scope {
     pub fn new() -> Self {
         Scope {
             symbols: HashMap::new(),
+            constants: HashMap::new(),
+            bounds: HashMap::new(),
         }
     }
 
@@ -49,18 +68,78 @@ impl Scope {
     }
 }
 
-#[derive(Debug)]
+/// Something a generic body does with a value of a type parameter, which the
+/// type arguments of every call have to support.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Requirement {
+    Operator(Operator),
+    Format(FormatSpec),
+    /// Passed to one of the [`INTEGER_BUILTINS`].
+    Integer,
+}
+
+#[derive(Debug, Clone)]
+struct Generic {
+    type_params: Vec<String>,
+    requirements: Vec<(String, Requirement)>,
+}
+
+/// A lambda whose body is being built.
+#[derive(Debug, Clone)]
+struct LambdaFrame {
+    /// The index of the scope of its parameters, the variables in the scopes
+    /// below it (except the globals) are captured.
+    scope: usize,
+    captures: Vec<Param>,
+}
+
+#[derive(Debug, Clone)]
 pub struct SymbolTable {
     scopes: Vec<Scope>,
+    function_params: HashMap<String, Vec<Type>>,
+    externs: HashSet<String>,
+    enums: HashMap<String, Vec<Variant>>,
+    /// The mangled names of the methods taking `self`, like `Shape::area`.
+    methods: HashSet<String>,
+    generics: HashMap<String, Generic>,
+    /// What the body of the generic function being built does with its type
+    /// parameters.
+    requirements: Option<Vec<(String, Requirement)>>,
+    lambdas: Vec<LambdaFrame>,
+    /// How many function and lambda bodies the statement being built is in.
+    depth: usize,
+    return_type: Option<Type>,
+    memory: Memory,
+}
+
+impl Default for SymbolTable {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SymbolTable {
     pub fn new() -> Self {
         SymbolTable {
             scopes: vec![Scope::new()],
+            function_params: HashMap::new(),
+            externs: HashSet::new(),
+            enums: HashMap::new(),
+            methods: HashSet::new(),
+            generics: HashMap::new(),
+            requirements: None,
+            lambdas: Vec::new(),
+            depth: 0,
+            return_type: None,
+            memory: Memory::Manual,
         }
     }
 
+    /// With managed memory `free` is rejected, the runtime frees allocations.
+    pub fn set_memory(&mut self, memory: Memory) {
+        self.memory = memory;
+    }
+
     fn push_scope(&mut self) {
         self.scopes.push(Scope::new());
     }
@@ -79,6 +158,34 @@ impl SymbolTable {
         Ok(())
     }
 
+    pub fn declare_function(
+        &mut self,
+        name: &str,
+        params: Vec<Type>,
+        ty: Type,
+    ) -> anyhow::Result<()> {
+        self.function_params.insert(name.to_string(), params);
+        self.declare(Symbol {
+            name: name.to_string(),
+            kind: SymbolKind::Function,
+            mutable: false,
+            ty,
+            location: Location::default(),
+        })
+    }
+
+    /// Declares a mutable global, letting scripts pass values back to the
+    /// host.
+    pub fn declare_variable(&mut self, name: &str, ty: Type) -> anyhow::Result<()> {
+        self.declare(Symbol {
+            name: name.to_string(),
+            kind: SymbolKind::Variable,
+            mutable: true,
+            ty,
+            location: Location::default(),
+        })
+    }
+
     fn lookup(&self, name: &str) -> Option<&Symbol> {
         for scope in self.scopes.iter().rev() {
             if let Some(sym) = scope.get(name) {
@@ -88,30 +195,201 @@ impl SymbolTable {
         None
     }
 
-    fn build_stmt(&mut self, statement: &Statement) -> anyhow::Result<()> {
-        match &statement.stmt {
-            Stmt::Let { name, ty, value } => {
-                self.build_expr(&Expression {
+    /// The index of the scope declaring `name`.
+    fn lookup_scope(&self, name: &str) -> Option<usize> {
+        self.scopes.iter().rposition(|x| x.get(name).is_some())
+    }
+
+    fn lookup_bound(&self, name: &str) -> Option<usize> {
+        let scope = self.lookup_scope(name)?;
+        self.scopes[scope].bounds.get(name).copied()
+    }
+
+    fn lookup_constant(&self, name: &str) -> Option<&Expr> {
+        let scope = self.scopes.iter().rev().find(|x| x.get(name).is_some())?;
+        scope.constants.get(name)
+    }
+
+    fn variant(
+        &self,
+        enum_name: &str,
+        variant: &str,
+        location: Location,
+    ) -> anyhow::Result<&Variant> {
+        let variants = self.enums.get(enum_name).ok_or_else(|| {
+            anyhow::anyhow!("Use of undeclared enum '{}' at {:?}", enum_name, location)
+        })?;
+        variants.iter().find(|x| x.name == variant).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Enum '{}' has no variant '{}' at {:?}",
+                enum_name,
+                variant,
+                location
+            )
+        })
+    }
+
+    fn build_stmt(&mut self, statement: &mut Statement) -> anyhow::Result<()> {
+        let location = statement.location;
+        match &mut statement.stmt {
+            Stmt::Let {
+                name,
+                mutable,
+                ty,
+                value,
+            } => {
+                self.infer_expr(value, ty.as_ref(), location)?;
+                let expression = Expression {
                     expr: value.clone(),
                     location: statement.location,
-                })?;
-                let ty = if let Some(ty) = ty {
-                    ty.clone()
-                } else {
-                    self.expr_type(&Expression {
-                        expr: value.clone(),
-                        location: statement.location,
-                    })?
                 };
+                self.build_expr(&expression)?;
+                let value_type = self.expr_type(&expression)?;
+                let ty = match ty {
+                    Some(ty) => {
+                        anyhow::ensure!(
+                            *ty == value_type,
+                            "Type mismatch in let statement at {:?}: declared as {}, assigned {}",
+                            location,
+                            ty,
+                            value_type
+                        );
+                        ty.clone()
+                    }
+                    None => value_type,
+                };
+                self.fold_expr(value, location)?;
                 self.declare(Symbol {
                     name: name.clone(),
                     kind: SymbolKind::Variable,
+                    mutable: *mutable,
                     ty,
                     location: statement.location,
                 })?;
+                if let (Some(bound), Some(scope)) =
+                    (self.closure_bound(value), self.scopes.last_mut())
+                {
+                    scope.bounds.insert(name.clone(), bound);
+                }
+            }
+            Stmt::Const { name, ty, value } => {
+                self.build_initializer(name, SymbolKind::Constant, ty, value, location)?;
+                self.declare(Symbol {
+                    name: name.clone(),
+                    kind: SymbolKind::Constant,
+                    mutable: false,
+                    ty: ty.clone(),
+                    location,
+                })?;
+                if let Some(scope) = self.scopes.last_mut() {
+                    scope.constants.insert(name.clone(), value.clone());
+                }
+            }
+            Stmt::Static {
+                name,
+                mutable,
+                ty,
+                value,
+            } => {
+                anyhow::ensure!(
+                    self.scopes.len() == 1,
+                    "Static '{}' at {:?} must be declared at file scope",
+                    name,
+                    location
+                );
+                self.build_initializer(name, SymbolKind::Variable, ty, value, location)?;
+                self.declare(Symbol {
+                    name: name.clone(),
+                    kind: SymbolKind::Variable,
+                    mutable: *mutable,
+                    ty: ty.clone(),
+                    location,
+                })?;
+            }
+            Stmt::Enum { name, variants } => {
+                anyhow::ensure!(
+                    self.scopes.len() == 1,
+                    "Enum '{}' at {:?} must be declared at file scope",
+                    name,
+                    location
+                );
+                anyhow::ensure!(
+                    !self.enums.contains_key(name) && name != "String" && name != "char",
+                    "Type '{}' redeclaration at {:?}",
+                    name,
+                    location
+                );
+                for (index, variant) in variants.iter().enumerate() {
+                    anyhow::ensure!(
+                        variants[..index].iter().all(|x| x.name != variant.name),
+                        "Variant '{}::{}' redeclaration at {:?}",
+                        name,
+                        variant.name,
+                        location
+                    );
+                    for field in &variant.fields {
+                        // the escape checks don't follow values into variants
+                        anyhow::ensure!(
+                            !matches!(field, Type::Closure { .. }),
+                            "Closures can't be stored in variant '{}::{}' at {:?}",
+                            name,
+                            variant.name,
+                            location
+                        );
+                        if let Type::Named(field) = field {
+                            anyhow::ensure!(
+                                field == "String"
+                                    || field == "char"
+                                    || self.enums.contains_key(field),
+                                "Unknown type '{}' in variant '{}::{}' at {:?}",
+                                field,
+                                name,
+                                variant.name,
+                                location
+                            );
+                        }
+                    }
+                }
+                self.enums.insert(name.clone(), variants.clone());
+            }
+            Stmt::Impl { name, methods } => {
+                anyhow::ensure!(
+                    self.scopes.len() == 1,
+                    "Impl block for '{}' at {:?} must be at file scope",
+                    name,
+                    location
+                );
+                let Some(variants) = self.enums.get(name).cloned() else {
+                    anyhow::bail!(
+                        "Cannot implement methods for unknown type '{}' at {:?}",
+                        name,
+                        location
+                    );
+                };
+                for method in methods {
+                    if let Stmt::Func {
+                        name: method_name,
+                        params,
+                        ..
+                    } = &method.stmt
+                    {
+                        let short_name = &method_name[name.len() + 2..];
+                        anyhow::ensure!(
+                            variants.iter().all(|x| x.name != short_name),
+                            "Method '{}' at {:?} has the name of a variant",
+                            method_name,
+                            method.location
+                        );
+                        if params.first().is_some_and(|x| x.name == "self") {
+                            self.methods.insert(method_name.clone());
+                        }
+                    }
+                    self.build_stmt(method)?;
+                }
             }
             Stmt::Func {
                 name,
+                type_params,
                 params,
                 ty,
                 body,
@@ -119,25 +397,65 @@ impl SymbolTable {
                 self.declare(Symbol {
                     name: name.clone(),
                     kind: SymbolKind::Function,
+                    mutable: false,
                     ty: ty.clone(),
                     location: statement.location,
                 })?;
-                self.push_scope();
-                for param in params {
-                    self.declare(Symbol {
-                        name: param.name.clone(),
-                        kind: SymbolKind::Parameter,
-                        ty: param.ty.clone(),
-                        location: statement.location,
-                    })?;
+                self.function_params
+                    .insert(name.clone(), params.iter().map(|x| x.ty.clone()).collect());
+                let generic = !type_params.is_empty();
+                if generic {
+                    anyhow::ensure!(
+                        self.scopes.len() == 1,
+                        "Generic function '{}' at {:?} must be declared at file scope",
+                        name,
+                        statement.location
+                    );
+                    self.generics.insert(
+                        name.clone(),
+                        Generic {
+                            type_params: type_params.clone(),
+                            requirements: Vec::new(),
+                        },
+                    );
                 }
-                self.build_stmt(body)?;
+                let outer_requirements =
+                    std::mem::replace(&mut self.requirements, generic.then(Vec::new));
+                let outer_return_type = self.return_type.replace(ty.clone());
+                self.depth += 1;
+                self.push_scope();
+                let result = self
+                    .declare_params(params, statement.location)
+                    .and_then(|_| self.build_stmt(body));
                 self.pop_scope();
+                self.depth -= 1;
+                self.return_type = outer_return_type;
+                let requirements = std::mem::replace(&mut self.requirements, outer_requirements);
+                result?;
+                if let (Some(generic), Some(requirements)) =
+                    (self.generics.get_mut(name), requirements)
+                {
+                    generic.requirements = requirements;
+                }
             }
-            Stmt::Extern { name, ty, .. } => {
+            Stmt::Extern { name, params, ty } => {
+                anyhow::ensure!(
+                    params
+                        .iter()
+                        .map(|x| &x.ty)
+                        .chain([&*ty])
+                        .all(|x| !matches!(x, Type::Closure { .. })),
+                    "Extern function '{}' at {:?} can't take or return closures, use a function",
+                    name,
+                    location
+                );
+                self.externs.insert(name.clone());
+                self.function_params
+                    .insert(name.clone(), params.iter().map(|x| x.ty.clone()).collect());
                 self.declare(Symbol {
                     name: name.clone(),
                     kind: SymbolKind::Function,
+                    mutable: false,
                     ty: ty.clone(),
                     location: statement.location,
                 })?;
@@ -150,16 +468,22 @@ impl SymbolTable {
                 self.pop_scope();
             }
             Stmt::Expr(expr) => {
-                self.build_expr(&Expression {
+                self.infer_expr(expr, None, location)?;
+                let expression = Expression {
                     expr: expr.clone(),
                     location: statement.location,
-                })?;
+                };
+                self.build_expr(&expression)?;
+                self.expr_type(&expression)?;
+                self.fold_expr(expr, location)?;
             }
             Stmt::While { condition, body } => {
+                self.infer_expr(condition, Some(&Type::Boolean), location)?;
                 self.build_expr(&Expression {
                     expr: condition.clone(),
                     location: statement.location,
                 })?;
+                self.fold_expr(condition, location)?;
                 self.build_stmt(body)?;
             }
             Stmt::If {
@@ -167,10 +491,12 @@ impl SymbolTable {
                 then_branch,
                 else_branch,
             } => {
+                self.infer_expr(condition, Some(&Type::Boolean), location)?;
                 self.build_expr(&Expression {
                     expr: *condition.clone(),
                     location: statement.location,
                 })?;
+                self.fold_expr(condition, location)?;
 
                 self.push_scope();
                 for stmt in then_branch {
@@ -187,18 +513,835 @@ impl SymbolTable {
                 }
             }
             Stmt::Return { value: Some(expr) } => {
-                self.build_expr(&Expression {
+                let return_type = self.return_type.clone();
+                self.infer_expr(expr, return_type.as_ref(), location)?;
+                let expression = Expression {
                     expr: expr.clone(),
                     location: statement.location,
-                })?;
+                };
+                self.build_expr(&expression)?;
+                let value_type = self.expr_type(&expression)?;
+                if let Some(return_type) = return_type {
+                    anyhow::ensure!(
+                        value_type == return_type,
+                        "Type mismatch in return at {:?}: returns {}, found {}",
+                        location,
+                        return_type,
+                        value_type
+                    );
+                }
+                self.fold_expr(expr, location)?;
+                self.check_escape(expr, location)?;
             }
             Stmt::Return { value: None } | Stmt::Break | Stmt::Continue | Stmt::Semicolon => {}
         }
         Ok(())
     }
 
+    /// Checks the initializer of a constant or static, which has to fold to a
+    /// literal of type `ty`.
+    fn build_initializer(
+        &mut self,
+        name: &str,
+        kind: SymbolKind,
+        ty: &Type,
+        value: &mut Expr,
+        location: Location,
+    ) -> anyhow::Result<()> {
+        self.infer_expr(value, Some(ty), location)?;
+        let expression = Expression {
+            expr: value.clone(),
+            location,
+        };
+        self.build_expr(&expression)?;
+        let value_type = self.expr_type(&expression)?;
+        anyhow::ensure!(
+            value_type == *ty,
+            "Type mismatch in declaration of {:?} '{}' at {:?}: declared as {:?}, assigned {:?}",
+            kind,
+            name,
+            location,
+            ty,
+            value_type
+        );
+        self.fold(value, location, true)?;
+        anyhow::ensure!(
+            matches!(value, Expr::Literal(_)),
+            "Value of {:?} '{}' at {:?} is not known at compile time",
+            kind,
+            name,
+            location
+        );
+        Ok(())
+    }
+
+    /// Gives unsuffixed numeric literals in `expr` the type they are used as,
+    /// falling back to `i32` and `f64`. Afterwards every numeric literal has a
+    /// suffix, so the backends don't need to know the context.
+    fn infer_expr(
+        &mut self,
+        expr: &mut Expr,
+        expected: Option<&Type>,
+        location: Location,
+    ) -> anyhow::Result<()> {
+        // `f(x)` where `f` is a variable holding a function
+        if let Expr::FunctionCall {
+            name, arguments, ..
+        } = expr
+            && self
+                .lookup(name)
+                .is_some_and(|x| x.kind != SymbolKind::Function)
+        {
+            let callee = Box::new(Expr::Variable(name.clone()));
+            let arguments = std::mem::take(arguments);
+            *expr = Expr::Call { callee, arguments };
+        }
+        // `Shape::new(x)` calls a function of the type rather than a variant
+        if let Expr::Variant {
+            enum_name,
+            variant,
+            arguments,
+        } = expr
+            && self
+                .enums
+                .get(enum_name)
+                .is_some_and(|x| x.iter().all(|x| x.name != *variant))
+            && self.lookup(&format!("{enum_name}::{variant}")).is_some()
+        {
+            *expr = Expr::FunctionCall {
+                name: format!("{enum_name}::{variant}"),
+                type_arguments: Vec::new(),
+                arguments: std::mem::take(arguments),
+            };
+        }
+        if let Expr::MethodCall {
+            receiver,
+            method,
+            arguments,
+        } = expr
+        {
+            self.infer_expr(receiver, None, location)?;
+            *expr = self.resolve_method(receiver, method, std::mem::take(arguments), location)?;
+        }
+
+        match expr {
+            Expr::Literal(Literal::Numeric(literal)) => {
+                let ty = match expected {
+                    Some(Type::Numeric(ty)) => *ty,
+                    _ => literal.ty(),
+                };
+                literal
+                    .infer(ty)
+                    .map_err(|e| anyhow::anyhow!("{e} at {location:?}"))?;
+            }
+            Expr::Literal(_) | Expr::Variable(_) => {}
+            Expr::Null { ty } => {
+                if let Some(expected @ Type::Pointer(_)) = expected {
+                    *ty = expected.clone();
+                }
+            }
+            Expr::MethodCall { .. } => unreachable!(),
+            Expr::Binary {
+                left,
+                right,
+                operator,
+            } => {
+                let operand_expected = match operator {
+                    Operator::Exclem | Operator::LogicalAnd | Operator::LogicalOr => {
+                        Some(Type::Boolean)
+                    }
+                    Operator::Equals
+                    | Operator::NotEquals
+                    | Operator::Less
+                    | Operator::LessEquals
+                    | Operator::Greater
+                    | Operator::GreaterEquals => None,
+                    _ => expected.cloned(),
+                };
+                // the operand with a fixed type decides the type of the other one
+                let (first, second) = if is_flexible(left) && !is_flexible(right) {
+                    (right, left)
+                } else {
+                    (left, right)
+                };
+                self.infer_expr(first, operand_expected.as_ref(), location)?;
+                let first_type = self.expr_type(&Expression {
+                    expr: *first.clone(),
+                    location,
+                })?;
+                self.infer_expr(second, Some(&first_type), location)?;
+            }
+            Expr::Unary { operand, operator } => {
+                let expected = match (operator, expected) {
+                    (Operator::Exclem, _) => Some(Type::Boolean),
+                    (Operator::Ampersand, Some(Type::Pointer(inner))) => Some(*inner.clone()),
+                    (Operator::Minus | Operator::Tilde, expected) => expected.cloned(),
+                    _ => None,
+                };
+                self.infer_expr(operand, expected.as_ref(), location)?;
+            }
+            Expr::FunctionCall {
+                name,
+                type_arguments,
+                arguments,
+            } => {
+                anyhow::ensure!(
+                    name != "free" || self.memory == Memory::Manual,
+                    "'free' at {:?} isn't available with managed memory",
+                    location
+                );
+                let params = self.function_params.get(name).cloned().unwrap_or_default();
+                let Some(generic) = self.generics.get(name).cloned() else {
+                    anyhow::ensure!(
+                        type_arguments.is_empty(),
+                        "Function '{}' at {:?} doesn't take type arguments",
+                        name,
+                        location
+                    );
+                    for (index, arg) in arguments.iter_mut().enumerate() {
+                        self.infer_expr(arg, params.get(index), location)?;
+                    }
+                    return Ok(());
+                };
+
+                anyhow::ensure!(
+                    type_arguments.len() <= generic.type_params.len(),
+                    "'{}' at {:?} takes {} type arguments but {} were given",
+                    name,
+                    location,
+                    generic.type_params.len(),
+                    type_arguments.len()
+                );
+                // the type arguments given and the arguments with a fixed type
+                // decide the type arguments, then the literals take the types
+                // decided for them
+                let mut bindings: HashMap<_, _> = generic
+                    .type_params
+                    .iter()
+                    .cloned()
+                    .zip(type_arguments.iter().cloned())
+                    .collect();
+                for flexible in [false, true] {
+                    if flexible
+                        && let (Some(expected), Some(function)) = (expected, self.lookup(name))
+                        && *expected != Type::Variadic
+                    {
+                        // a conflict shows up as a type mismatch at the use
+                        let _ = unify(&function.ty, expected, &mut bindings);
+                    }
+                    for (arg, param) in arguments.iter_mut().zip(&params) {
+                        if is_flexible(arg) != flexible {
+                            continue;
+                        }
+                        // a type parameter of `name` that isn't bound yet
+                        // means nothing where the argument is, it may even
+                        // share the name of one of the caller
+                        let expected = generic
+                            .type_params
+                            .iter()
+                            .all(|x| bindings.contains_key(x) || !param.mentions(x))
+                            .then(|| param.substitute(&bindings));
+                        self.infer_expr(arg, expected.as_ref(), location)?;
+                        let arg_type = self.expr_type(&Expression {
+                            expr: arg.clone(),
+                            location,
+                        })?;
+                        unify(param, &arg_type, &mut bindings).map_err(|e| {
+                            anyhow::anyhow!("{e} in call to '{name}' at {location:?}")
+                        })?;
+                    }
+                }
+                *type_arguments = generic
+                    .type_params
+                    .iter()
+                    .map(|param| {
+                        bindings.get(param).cloned().ok_or_else(|| {
+                            anyhow::anyhow!(
+                                "Cannot infer type parameter '{}' of '{}' at {:?}",
+                                param,
+                                name,
+                                location
+                            )
+                        })
+                    })
+                    .collect::<anyhow::Result<_>>()?;
+                self.check_requirements(name, &generic, type_arguments, location)?;
+            }
+            Expr::Call { callee, arguments } => {
+                self.infer_expr(callee, None, location)?;
+                let params = match self.expr_type(&Expression {
+                    expr: *callee.clone(),
+                    location,
+                })? {
+                    Type::Function { params, .. } | Type::Closure { params, .. } => params,
+                    _ => Vec::new(),
+                };
+                for (index, arg) in arguments.iter_mut().enumerate() {
+                    self.infer_expr(arg, params.get(index), location)?;
+                }
+            }
+            Expr::Lambda {
+                params,
+                ty,
+                body,
+                value,
+                captures,
+            } => {
+                *captures = self.build_lambda(params, ty, body, value.as_deref_mut(), location)?;
+            }
+            Expr::Assignment { target, value } => {
+                self.infer_expr(target, None, location)?;
+                let target_type = self.expr_type(&Expression {
+                    expr: *target.clone(),
+                    location,
+                })?;
+                self.infer_expr(value, Some(&target_type), location)?;
+            }
+            Expr::If {
+                condition,
+                then_branch,
+                then_value,
+                else_branch,
+                else_value,
+            } => {
+                self.infer_expr(condition, Some(&Type::Boolean), location)?;
+                let mut branches = [(then_branch, then_value), (else_branch, else_value)];
+                if is_flexible(branches[0].1) && !is_flexible(branches[1].1) {
+                    branches.reverse();
+                }
+                let mut expected = expected.cloned();
+                for (branch, value) in branches {
+                    self.push_scope();
+                    let result = self.infer_branch(branch, value, expected.as_ref(), location);
+                    self.pop_scope();
+                    expected = Some(result?);
+                }
+            }
+            Expr::Interpolated { parts } => {
+                for part in parts {
+                    if let FormatPart::Expr { expr, .. } = part {
+                        self.infer_expr(expr, None, location)?;
+                    }
+                }
+            }
+            Expr::Variant {
+                enum_name,
+                variant,
+                arguments,
+            } => {
+                let fields = self
+                    .variant(enum_name, variant, location)
+                    .map(|x| x.fields.clone())
+                    .unwrap_or_default();
+                for (index, arg) in arguments.iter_mut().enumerate() {
+                    self.infer_expr(arg, fields.get(index), location)?;
+                }
+            }
+            Expr::Match { scrutinee, arms } => {
+                self.infer_expr(scrutinee, None, location)?;
+                let ty = self.expr_type(&Expression {
+                    expr: *scrutinee.clone(),
+                    location,
+                })?;
+                // like the branches of an if, arms with a fixed type go first
+                let mut order: Vec<usize> = (0..arms.len()).collect();
+                order.sort_by_key(|&index| arms[index].value.as_ref().is_some_and(is_flexible));
+                let mut expected = expected.cloned();
+                for index in order {
+                    let arm = &mut arms[index];
+                    self.infer_pattern(&mut arm.pattern, &ty);
+                    self.push_scope();
+                    let result = self.infer_arm(arm, &ty, expected.as_ref());
+                    self.pop_scope();
+                    if let Some(ty) = result? {
+                        expected = Some(ty);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn infer_arm(
+        &mut self,
+        arm: &mut MatchArm,
+        ty: &Type,
+        expected: Option<&Type>,
+    ) -> anyhow::Result<Option<Type>> {
+        self.declare_bindings(&arm.pattern, ty, arm.location)?;
+        match &mut arm.value {
+            Some(value) => self
+                .infer_branch(&mut arm.body, value, expected, arm.location)
+                .map(Some),
+            None => {
+                for stmt in &mut arm.body {
+                    self.build_stmt(stmt)?;
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// Gives the numeric literals in `pattern` the type of the value they are
+    /// compared with. Mismatched types are reported by [`Self::pattern_bindings`].
+    fn infer_pattern(&self, pattern: &mut Pattern, ty: &Type) {
+        match (pattern, ty) {
+            (Pattern::Literal(Literal::Numeric(literal)), Type::Numeric(ty)) => {
+                let _ = literal.infer(*ty);
+            }
+            (
+                Pattern::Range {
+                    start: Literal::Numeric(start),
+                    end: Literal::Numeric(end),
+                    ..
+                },
+                Type::Numeric(ty),
+            ) => {
+                let _ = start.infer(*ty);
+                let _ = end.infer(*ty);
+            }
+            (
+                Pattern::Variant {
+                    enum_name,
+                    variant,
+                    fields,
+                },
+                _,
+            ) => {
+                let types = self
+                    .variant(enum_name, variant, Location::default())
+                    .map(|x| x.fields.clone())
+                    .unwrap_or_default();
+                for (field, ty) in fields.iter_mut().zip(&types) {
+                    self.infer_pattern(field, ty);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Checks that `pattern` can match a value of type `ty` and collects the
+    /// variables it binds.
+    fn pattern_bindings(
+        &mut self,
+        pattern: &Pattern,
+        ty: &Type,
+        location: Location,
+        bindings: &mut Vec<(String, Type)>,
+    ) -> anyhow::Result<()> {
+        match pattern {
+            Pattern::Wildcard => {}
+            Pattern::Binding(name) => {
+                anyhow::ensure!(
+                    bindings.iter().all(|(x, _)| x != name),
+                    "Variable '{}' is bound more than once in pattern at {:?}",
+                    name,
+                    location
+                );
+                bindings.push((name.clone(), ty.clone()));
+            }
+            Pattern::Literal(literal) => {
+                let literal_type = self.expr_type(&Expression {
+                    expr: Expr::Literal(literal.clone()),
+                    location,
+                })?;
+                anyhow::ensure!(
+                    literal_type == *ty,
+                    "Type mismatch in pattern at {:?}: matched value is {:?}, pattern is {:?}",
+                    location,
+                    ty,
+                    literal_type
+                );
+            }
+            Pattern::Range { start, end, .. } => {
+                for literal in [start, end] {
+                    self.pattern_bindings(
+                        &Pattern::Literal(literal.clone()),
+                        ty,
+                        location,
+                        bindings,
+                    )?;
+                }
+                match pattern_range(pattern) {
+                    Some((start, end)) if start <= end => {}
+                    Some(_) => anyhow::bail!("Range pattern at {:?} is empty", location),
+                    None => anyhow::bail!(
+                        "Range patterns require integer or char values at {:?}, found {:?}",
+                        location,
+                        ty
+                    ),
+                }
+            }
+            Pattern::Variant {
+                enum_name,
+                variant,
+                fields,
+            } => {
+                anyhow::ensure!(
+                    matches!(ty, Type::Named(name) if name == enum_name),
+                    "Type mismatch in pattern at {:?}: matched value is {:?}, pattern is {:?}",
+                    location,
+                    ty,
+                    Type::Named(enum_name.clone())
+                );
+                let types = self.variant(enum_name, variant, location)?.fields.clone();
+                anyhow::ensure!(
+                    fields.len() == types.len(),
+                    "Pattern for variant '{}::{}' at {:?} has {} fields, expected {}",
+                    enum_name,
+                    variant,
+                    location,
+                    fields.len(),
+                    types.len()
+                );
+                for (field, ty) in fields.iter().zip(&types) {
+                    self.pattern_bindings(field, ty, location, bindings)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn declare_bindings(
+        &mut self,
+        pattern: &Pattern,
+        ty: &Type,
+        location: Location,
+    ) -> anyhow::Result<()> {
+        let mut bindings = Vec::new();
+        self.pattern_bindings(pattern, ty, location, &mut bindings)?;
+        for (name, ty) in bindings {
+            self.declare(Symbol {
+                name,
+                kind: SymbolKind::Variable,
+                mutable: false,
+                ty,
+                location,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Whether a value matching `row` could get past every row of `rows`,
+    /// where `types` are the types of the columns. This is the usefulness
+    /// check of Maranget's "Warnings for pattern matching": an arm is
+    /// unreachable if it isn't useful after the arms above it, and a match is
+    /// exhaustive if a wildcard isn't useful after all of its arms.
+    fn useful(&self, rows: &[Vec<&Pattern>], row: &[&Pattern], types: &[Type]) -> bool {
+        let Some((head, rest)) = row.split_first() else {
+            return rows.is_empty();
+        };
+        let heads: Vec<&Pattern> = rows.iter().map(|x| x[0]).collect();
+        let constructors = match head {
+            Pattern::Wildcard | Pattern::Binding(_) => self.constructors(&types[0], &heads),
+            pattern => Some(self.pattern_constructors(pattern, &heads)),
+        };
+
+        let Some(constructors) = constructors else {
+            // too many values to list, only the rows matching anything can
+            // stop a wildcard
+            let rows: Vec<Vec<&Pattern>> = rows
+                .iter()
+                .filter(|x| matches!(x[0], Pattern::Wildcard | Pattern::Binding(_)))
+                .map(|x| x[1..].to_vec())
+                .collect();
+            return self.useful(&rows, rest, &types[1..]);
+        };
+        constructors.iter().any(|constructor| {
+            let mut field_types = match constructor {
+                Constructor::Variant(variant) => match &types[0] {
+                    Type::Named(enum_name) => self
+                        .variant(enum_name, variant, Location::default())
+                        .map(|x| x.fields.clone())
+                        .unwrap_or_default(),
+                    _ => Vec::new(),
+                },
+                _ => Vec::new(),
+            };
+            let arity = field_types.len();
+            field_types.extend_from_slice(&types[1..]);
+
+            let rows: Vec<Vec<&Pattern>> = rows
+                .iter()
+                .filter_map(|x| specialize(x, constructor, arity))
+                .collect();
+            match specialize(row, constructor, arity) {
+                Some(row) => self.useful(&rows, &row, &field_types),
+                None => false,
+            }
+        })
+    }
+
+    /// Every constructor of `ty`, or `None` if there are too many to list.
+    /// Integer ranges are split at the bounds of the patterns in `heads`.
+    fn constructors(&self, ty: &Type, heads: &[&Pattern]) -> Option<Vec<Constructor>> {
+        match ty {
+            Type::Boolean => Some(vec![
+                Constructor::Boolean(true),
+                Constructor::Boolean(false),
+            ]),
+            Type::Named(name) if self.enums.contains_key(name) => Some(
+                self.enums[name]
+                    .iter()
+                    .map(|x| Constructor::Variant(x.name.clone()))
+                    .collect(),
+            ),
+            _ => {
+                let (start, end) = value_range(ty)?;
+                Some(split_range(start, end, heads))
+            }
+        }
+    }
+
+    fn pattern_constructors(&self, pattern: &Pattern, heads: &[&Pattern]) -> Vec<Constructor> {
+        match pattern {
+            Pattern::Variant { variant, .. } => vec![Constructor::Variant(variant.clone())],
+            Pattern::Literal(Literal::Boolean(value)) => {
+                vec![Constructor::Boolean(value == "true")]
+            }
+            Pattern::Literal(literal) => match pattern_range(pattern) {
+                Some((start, end)) => split_range(start, end, heads),
+                None => vec![Constructor::Literal(literal.clone())],
+            },
+            _ => match pattern_range(pattern) {
+                Some((start, end)) => split_range(start, end, heads),
+                None => Vec::new(),
+            },
+        }
+    }
+
+    fn infer_branch(
+        &mut self,
+        stmts: &mut [Statement],
+        value: &mut Expr,
+        expected: Option<&Type>,
+        location: Location,
+    ) -> anyhow::Result<Type> {
+        for stmt in stmts {
+            self.build_stmt(stmt)?;
+        }
+        self.infer_expr(value, expected, location)?;
+        let ty = self.expr_type(&Expression {
+            expr: value.clone(),
+            location,
+        })?;
+        // folded here, where the variables of the branch are in scope
+        self.fold_expr(value, location)?;
+        Ok(ty)
+    }
+
+    /// Replaces constants with their values and evaluates operators whose
+    /// operands are all literals. Runs after type checking, the evaluation
+    /// errors are division by zero and out of range shifts. Arithmetic that
+    /// overflows is left to run, where it wraps or panics like the program
+    /// was built.
+    fn fold_expr(&self, expr: &mut Expr, location: Location) -> anyhow::Result<()> {
+        self.fold(expr, location, false)
+    }
+
+    /// Folds `expr` like [`Self::fold_expr`], with overflow an error if
+    /// `strict`, as the value of a `const` or `static` must be known.
+    fn fold(&self, expr: &mut Expr, location: Location, strict: bool) -> anyhow::Result<()> {
+        let evaluate = |value: anyhow::Result<InterpretValue>| {
+            value.map_err(|e| anyhow::anyhow!("{e} in constant expression at {location:?}"))
+        };
+        let folded = match expr {
+            Expr::Variable(name) => self.lookup_constant(name).cloned(),
+            Expr::Binary {
+                left,
+                right,
+                operator,
+            } => {
+                self.fold(left, location, strict)?;
+                // `false && x` and `true || x` never evaluate `x`
+                let decided = match (*operator, left.as_ref()) {
+                    (Operator::LogicalAnd, Expr::Literal(Literal::Boolean(b))) => b == "false",
+                    (Operator::LogicalOr, Expr::Literal(Literal::Boolean(b))) => b == "true",
+                    _ => false,
+                };
+                if decided {
+                    Some(left.as_ref().clone())
+                } else {
+                    self.fold(right, location, strict)?;
+                    match (left.as_ref(), right.as_ref()) {
+                        (Expr::Literal(left), Expr::Literal(right))
+                            if overflows(*operator, &[left, right]) =>
+                        {
+                            anyhow::ensure!(
+                                !strict,
+                                "Arithmetic overflow in constant expression at {location:?}"
+                            );
+                            None
+                        }
+                        (Expr::Literal(left), Expr::Literal(right)) => {
+                            let left = InterpretValue::from_literal(left.clone())?;
+                            let right = InterpretValue::from_literal(right.clone())?;
+                            evaluate(left.binary_op(*operator, &right))?
+                                .to_literal()
+                                .map(Expr::Literal)
+                        }
+                        _ => None,
+                    }
+                }
+            }
+            Expr::Unary {
+                operand,
+                operator: Operator::Ampersand,
+            } => {
+                if let Expr::Variable(name) = operand.as_ref() {
+                    anyhow::ensure!(
+                        self.lookup_constant(name).is_none(),
+                        "Cannot take the address of constant '{}' at {:?}",
+                        name,
+                        location
+                    );
+                }
+                None
+            }
+            Expr::Unary { operand, operator } => {
+                self.fold(operand, location, strict)?;
+                match (*operator, operand.as_ref()) {
+                    (Operator::Minus, Expr::Literal(lit)) if overflows(*operator, &[lit]) => {
+                        anyhow::ensure!(
+                            !strict,
+                            "Arithmetic overflow in constant expression at {location:?}"
+                        );
+                        None
+                    }
+                    (Operator::Minus | Operator::Exclem | Operator::Tilde, Expr::Literal(lit)) => {
+                        evaluate(InterpretValue::from_literal(lit.clone())?.unary_op(*operator))?
+                            .to_literal()
+                            .map(Expr::Literal)
+                    }
+                    _ => None,
+                }
+            }
+            Expr::FunctionCall { arguments, .. } => {
+                for arg in arguments {
+                    self.fold(arg, location, strict)?;
+                }
+                None
+            }
+            Expr::Call { callee, arguments }
+            | Expr::MethodCall {
+                receiver: callee,
+                arguments,
+                ..
+            } => {
+                self.fold(callee, location, strict)?;
+                for arg in arguments {
+                    self.fold(arg, location, strict)?;
+                }
+                None
+            }
+            // the body is folded by `build_lambda`
+            Expr::Lambda { .. } => None,
+            Expr::Assignment { target, value } => {
+                if let Expr::Variable(name) = target.as_ref() {
+                    anyhow::ensure!(
+                        self.lookup_constant(name).is_none(),
+                        "Cannot assign to constant '{}' at {:?}",
+                        name,
+                        location
+                    );
+                }
+                self.fold(value, location, strict)?;
+                None
+            }
+            // the branches are folded by `infer_branch`
+            Expr::If { condition, .. } => {
+                self.fold(condition, location, strict)?;
+                None
+            }
+            Expr::Interpolated { parts } => {
+                for part in parts {
+                    if let FormatPart::Expr { expr, .. } = part {
+                        self.fold(expr, location, strict)?;
+                    }
+                }
+                None
+            }
+            Expr::Variant { arguments, .. } => {
+                for arg in arguments {
+                    self.fold(arg, location, strict)?;
+                }
+                None
+            }
+            // the arms are folded by `infer_branch`
+            Expr::Match { scrutinee, .. } => {
+                self.fold(scrutinee, location, strict)?;
+                None
+            }
+            Expr::Literal(_) | Expr::Null { .. } => None,
+        };
+        if let Some(folded) = folded {
+            *expr = folded;
+        }
+        Ok(())
+    }
+
+    /// Checks that the variable `target` names, if any, can be written to.
+    /// `action` is what the write is, `"assign to"` or `"take the address of"`
+    /// as pointers may be written through.
+    fn check_mutable(&self, target: &Expr, action: &str, location: Location) -> anyhow::Result<()> {
+        let Expr::Variable(name) = target else {
+            return Ok(());
+        };
+        if let (Some(frame), Some(scope)) = (self.lambdas.last(), self.lookup_scope(name))
+            && scope > 0
+            && scope < frame.scope
+        {
+            anyhow::bail!(
+                "Cannot {} captured variable '{}' at {:?}, closures capture a copy",
+                action,
+                name,
+                location
+            );
+        }
+        match self.lookup(name) {
+            Some(Symbol {
+                kind: SymbolKind::Variable,
+                mutable: false,
+                ..
+            }) => anyhow::bail!(
+                "Cannot {} immutable variable '{}' at {:?}, consider `let mut {}`",
+                action,
+                name,
+                location,
+                name
+            ),
+            Some(Symbol {
+                kind: SymbolKind::Function,
+                ..
+            }) => anyhow::bail!("Cannot {} function '{}' at {:?}", action, name, location),
+            Some(Symbol {
+                kind: SymbolKind::Parameter,
+                ..
+            }) => anyhow::bail!(
+                "Cannot {} parameter '{}' at {:?}, consider copying it with `let mut {}`",
+                action,
+                name,
+                location,
+                name
+            ),
+            _ => Ok(()),
+        }
+    }
+
     fn build_expr(&mut self, expression: &Expression) -> anyhow::Result<()> {
         match &expression.expr {
+            Expr::MethodCall {
+                receiver,
+                method,
+                arguments,
+            } => {
+                let expr =
+                    self.resolve_method(receiver, method, arguments.clone(), expression.location)?;
+                self.build_expr(&Expression {
+                    expr,
+                    location: expression.location,
+                })?;
+            }
             Expr::Variable(name) => {
                 anyhow::ensure!(
                     self.lookup(name).is_some(),
@@ -206,8 +1349,13 @@ impl SymbolTable {
                     name,
                     expression.location
                 );
+                self.capture(name);
             }
-            Expr::Binary { left, right, .. } => {
+            Expr::Binary {
+                left,
+                right,
+                operator,
+            } => {
                 let lhs = &Expression {
                     expr: *left.clone(),
                     location: expression.location,
@@ -218,7 +1366,8 @@ impl SymbolTable {
                 };
                 let lhs_type = self.expr_type(lhs)?;
                 let rhs_type = self.expr_type(rhs)?;
-                if lhs_type != rhs_type {
+                // pointer offsets are checked by `expr_type`
+                if lhs_type != rhs_type && !matches!(lhs_type, Type::Pointer(_)) {
                     anyhow::bail!(
                         "Type mismatch in binary expression at {:?}: left is {:?}, right is {:?}",
                         expression.location,
@@ -226,54 +1375,432 @@ impl SymbolTable {
                         rhs_type
                     );
                 }
+                // checks the operator, also where the type isn't used
+                self.expr_type(expression)?;
+                if let Type::Param(param) = &lhs_type {
+                    self.require(param, Requirement::Operator(*operator));
+                }
                 self.build_expr(lhs)?;
                 self.build_expr(rhs)?;
             }
-            Expr::Unary { operand, .. } => {
-                self.build_expr(&Expression {
+            Expr::Unary { operand, operator } => {
+                let operand = &Expression {
                     expr: *operand.clone(),
                     location: expression.location,
-                })?;
+                };
+                self.build_expr(operand)?;
+                if *operator == Operator::Ampersand {
+                    self.check_mutable(&operand.expr, "take the address of", operand.location)?;
+                }
+                if matches!(operator, Operator::Minus | Operator::Tilde)
+                    && let Type::Param(param) = self.expr_type(operand)?
+                {
+                    self.require(&param, Requirement::Operator(*operator));
+                }
             }
-            Expr::FunctionCall { name, arguments } => {
+            Expr::FunctionCall {
+                name, arguments, ..
+            } => {
                 anyhow::ensure!(
                     self.lookup(name).is_some(),
                     "Use of undeclared function '{}' at {:?}",
                     name,
                     expression.location
                 );
-                for arg in arguments {
-                    self.build_expr(&Expression {
+                // generic calls were checked when their type arguments were
+                // inferred, builtins check their own arguments
+                let params = match self.generics.contains_key(name) {
+                    true => None,
+                    false => self.function_params.get(name).cloned(),
+                };
+                let fixed = params.as_ref().map(|params| {
+                    let variadic = params.last() == Some(&Type::Variadic);
+                    anyhow::ensure!(
+                        arguments.len() == params.len() - variadic as usize
+                            || variadic && arguments.len() >= params.len() - 1,
+                        "'{}' called at {:?} takes {} arguments but {} were given",
+                        name,
+                        expression.location,
+                        params.len() - variadic as usize,
+                        arguments.len()
+                    );
+                    Ok(&params[..params.len() - variadic as usize])
+                });
+                let fixed = fixed.transpose()?;
+                for (index, arg) in arguments.iter().enumerate() {
+                    let arg = Expression {
+                        expr: arg.clone(),
+                        location: expression.location,
+                    };
+                    self.build_expr(&arg)?;
+                    // a closure carries its captures, it can't stand in for a
+                    // plain function pointer and the other way round
+                    let Some(param) = fixed.and_then(|x| x.get(index)) else {
+                        continue;
+                    };
+                    // C functions get string literals as NUL terminated `*i8`s
+                    if self.externs.contains(name)
+                        && matches!(arg.expr, Expr::Literal(Literal::String(_)))
+                    {
+                        continue;
+                    }
+                    let arg_type = self.expr_type(&arg)?;
+                    anyhow::ensure!(
+                        arg_type == *param,
+                        "Type mismatch in call to '{}' at {:?}: expected {}, found {}",
+                        name,
+                        expression.location,
+                        param,
+                        arg_type
+                    );
+                }
+            }
+            Expr::Call { callee, arguments } => {
+                let callee = &Expression {
+                    expr: *callee.clone(),
+                    location: expression.location,
+                };
+                self.build_expr(callee)?;
+                let params = match self.expr_type(callee)? {
+                    Type::Function { params, .. } | Type::Closure { params, .. } => params,
+                    ty => anyhow::bail!(
+                        "Cannot call a value of type {} at {:?}",
+                        ty,
+                        expression.location
+                    ),
+                };
+                let variadic = params.last() == Some(&Type::Variadic);
+                let fixed = &params[..params.len() - variadic as usize];
+                anyhow::ensure!(
+                    arguments.len() == fixed.len() || variadic && arguments.len() > fixed.len(),
+                    "Function value called at {:?} takes {} arguments but {} were given",
+                    expression.location,
+                    fixed.len(),
+                    arguments.len()
+                );
+                for (index, arg) in arguments.iter().enumerate() {
+                    let arg = Expression {
+                        expr: arg.clone(),
+                        location: expression.location,
+                    };
+                    self.build_expr(&arg)?;
+                    let arg_type = self.expr_type(&arg)?;
+                    if let Some(param) = fixed.get(index) {
+                        anyhow::ensure!(
+                            arg_type == *param,
+                            "Type mismatch in call at {:?}: expected {}, found {}",
+                            expression.location,
+                            param,
+                            arg_type
+                        );
+                    }
+                }
+            }
+            Expr::Lambda { captures, .. } => {
+                // the body was built by `infer_expr`, the lambdas around this
+                // one need what it captured too
+                for capture in captures {
+                    self.capture(&capture.name);
+                }
+            }
+            Expr::Assignment { target, value } => {
+                self.check_mutable(target, "assign to", expression.location)?;
+                let target_bound = match target.as_ref() {
+                    Expr::Variable(name) => match self.lookup_scope(name) {
+                        Some(0) | None => None,
+                        _ => self.lookup_bound(name).or(Some(self.depth)),
+                    },
+                    _ => None,
+                };
+                if let Some(bound) = self.closure_bound(value)
+                    && target_bound.is_none_or(|x| x < bound)
+                {
+                    anyhow::bail!(
+                        "Closure assigned at {:?} captures local variables that don't live as long as the target",
+                        expression.location
+                    );
+                }
+                self.build_expr(&Expression {
+                    expr: *target.clone(),
+                    location: expression.location,
+                })?;
+                self.build_expr(&Expression {
+                    expr: *value.clone(),
+                    location: expression.location,
+                })?;
+            }
+            Expr::If {
+                condition,
+                then_branch,
+                then_value,
+                else_branch,
+                else_value,
+            } => {
+                self.build_expr(&Expression {
+                    expr: *condition.clone(),
+                    location: expression.location,
+                })?;
+
+                for (branch, value) in [(then_branch, then_value), (else_branch, else_value)] {
+                    self.push_scope();
+                    for stmt in branch {
+                        self.build_stmt(&mut stmt.clone())?;
+                    }
+                    self.build_expr(&Expression {
+                        expr: *value.clone(),
+                        location: expression.location,
+                    })?;
+                    self.pop_scope();
+                }
+            }
+            Expr::Interpolated { parts } => {
+                for part in parts {
+                    if let FormatPart::Expr { expr, spec } = part {
+                        let part = &Expression {
+                            expr: expr.clone(),
+                            location: expression.location,
+                        };
+                        self.build_expr(part)?;
+                        if let Type::Param(param) = self.expr_type(part)? {
+                            self.require(&param, Requirement::Format(*spec));
+                        }
+                    }
+                }
+            }
+            Expr::Variant {
+                enum_name,
+                variant,
+                arguments,
+            } => {
+                let fields = self
+                    .variant(enum_name, variant, expression.location)?
+                    .fields
+                    .clone();
+                anyhow::ensure!(
+                    arguments.len() == fields.len(),
+                    "Variant '{}::{}' at {:?} takes {} values but {} were given",
+                    enum_name,
+                    variant,
+                    expression.location,
+                    fields.len(),
+                    arguments.len()
+                );
+                for (arg, field) in arguments.iter().zip(&fields) {
+                    let arg = Expression {
                         expr: arg.clone(),
                         location: expression.location,
-                    })?;
+                    };
+                    self.build_expr(&arg)?;
+                    let arg_type = self.expr_type(&arg)?;
+                    anyhow::ensure!(
+                        arg_type == *field,
+                        "Type mismatch in variant '{}::{}' at {:?}: expected {:?}, found {:?}",
+                        enum_name,
+                        variant,
+                        expression.location,
+                        field,
+                        arg_type
+                    );
                 }
             }
-            Expr::Assignment { target, value } => {
-                self.build_expr(&Expression {
-                    expr: *target.clone(),
-                    location: expression.location,
-                })?;
-                self.build_expr(&Expression {
-                    expr: *value.clone(),
+            Expr::Match { scrutinee, arms } => {
+                let scrutinee = Expression {
+                    expr: *scrutinee.clone(),
                     location: expression.location,
-                })?;
+                };
+                self.build_expr(&scrutinee)?;
+                let ty = self.expr_type(&scrutinee)?;
+
+                let mut rows = Vec::new();
+                for (index, arm) in arms.iter().enumerate() {
+                    self.push_scope();
+                    let result = self.build_arm(arm, &ty);
+                    self.pop_scope();
+                    result?;
+
+                    if let Pattern::Literal(literal) = &arm.pattern
+                        && let Some(first) = arms[..index].iter().find(|x| x.pattern == arm.pattern)
+                    {
+                        anyhow::bail!(
+                            "Duplicate case {} in match at {:?}, already handled by the arm at {:?}",
+                            describe_literal(literal),
+                            arm.location,
+                            first.location
+                        );
+                    }
+
+                    anyhow::ensure!(
+                        self.useful(&rows, &[&arm.pattern], std::slice::from_ref(&ty)),
+                        "Unreachable match arm at {:?}",
+                        arm.location
+                    );
+                    rows.push(vec![&arm.pattern]);
+                }
+                anyhow::ensure!(
+                    !self.useful(&rows, &[&Pattern::Wildcard], std::slice::from_ref(&ty)),
+                    "Match on {:?} at {:?} is not exhaustive, consider adding a `_` arm",
+                    ty,
+                    expression.location
+                );
+            }
+            Expr::Literal(_) | Expr::Null { .. } => {}
+        }
+        Ok(())
+    }
+
+    /// Records that the generic body being built needs `requirement` from the
+    /// type argument of `param`.
+    fn require(&mut self, param: &str, requirement: Requirement) {
+        if let Some(requirements) = &mut self.requirements {
+            let requirement = (param.to_string(), requirement);
+            if !requirements.contains(&requirement) {
+                requirements.push(requirement);
             }
-            Expr::Literal(_) => {}
+        }
+    }
+
+    /// Checks that the type arguments of a call to the generic function `name`
+    /// support what its body does with them. Type parameters of the caller
+    /// pass the requirements on to its own callers.
+    fn check_requirements(
+        &mut self,
+        name: &str,
+        generic: &Generic,
+        type_arguments: &[Type],
+        location: Location,
+    ) -> anyhow::Result<()> {
+        for (param, requirement) in &generic.requirements {
+            let Some(index) = generic.type_params.iter().position(|x| x == param) else {
+                continue;
+            };
+            let ty = &type_arguments[index];
+            if let Type::Param(outer) = ty {
+                self.require(outer, *requirement);
+                continue;
+            }
+            match requirement {
+                Requirement::Operator(operator) => anyhow::ensure!(
+                    self.supports(ty, *operator),
+                    "Operator {:?} is not supported for {} in call to '{}' at {:?}, which uses it on {}",
+                    operator,
+                    ty,
+                    name,
+                    location,
+                    param
+                ),
+                Requirement::Format(spec) => check_format(ty, spec)
+                    .map_err(|e| anyhow::anyhow!("{e} in call to '{name}' at {location:?}"))?,
+                Requirement::Integer => anyhow::ensure!(
+                    matches!(ty, Type::Numeric(x) if x.is_integer()),
+                    "'{}' at {:?} takes integers, found {} for {}",
+                    name,
+                    location,
+                    ty,
+                    param
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether [`Self::expr_type`] accepts `operator` on operands of type `ty`.
+    fn supports(&self, ty: &Type, operator: Operator) -> bool {
+        let comparison = matches!(
+            operator,
+            Operator::Equals
+                | Operator::NotEquals
+                | Operator::Less
+                | Operator::LessEquals
+                | Operator::Greater
+                | Operator::GreaterEquals
+        );
+        let arithmetic = matches!(
+            operator,
+            Operator::Plus
+                | Operator::Minus
+                | Operator::Asterisk
+                | Operator::Slash
+                | Operator::Percent
+        );
+        let bitwise = matches!(
+            operator,
+            Operator::Ampersand
+                | Operator::Pipe
+                | Operator::Caret
+                | Operator::ShiftLeft
+                | Operator::ShiftRight
+                | Operator::Tilde
+        );
+        match ty {
+            Type::Numeric(n) if n.is_integer() => comparison || arithmetic || bitwise,
+            Type::Numeric(_) => comparison || arithmetic,
+            Type::Boolean => matches!(
+                operator,
+                Operator::Equals
+                    | Operator::NotEquals
+                    | Operator::Exclem
+                    | Operator::LogicalAnd
+                    | Operator::LogicalOr
+            ),
+            Type::Named(name) if name == "char" => comparison,
+            Type::Named(name) if name == "String" => matches!(
+                operator,
+                Operator::Plus | Operator::Equals | Operator::NotEquals
+            ),
+            Type::Pointer(_) => comparison,
+            _ => false,
+        }
+    }
+
+    /// Fails unless `operator` works on operands of type `ty`. Type
+    /// parameters are checked for each call by the requirements.
+    fn check_operator(
+        &self,
+        ty: &Type,
+        operator: Operator,
+        location: Location,
+    ) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            matches!(ty, Type::Param(_)) || self.supports(ty, operator),
+            "Operator {:?} is not supported for {} operands at {:?}",
+            operator,
+            ty,
+            location
+        );
+        Ok(())
+    }
+
+    fn build_arm(&mut self, arm: &MatchArm, ty: &Type) -> anyhow::Result<()> {
+        self.declare_bindings(&arm.pattern, ty, arm.location)?;
+        for stmt in &arm.body {
+            self.build_stmt(&mut stmt.clone())?;
+        }
+        if let Some(value) = &arm.value {
+            self.build_expr(&Expression {
+                expr: value.clone(),
+                location: arm.location,
+            })?;
         }
         Ok(())
     }
 
-    fn expr_type(&self, expression: &Expression) -> anyhow::Result<Type> {
+    fn expr_type(&mut self, expression: &Expression) -> anyhow::Result<Type> {
         match &expression.expr {
+            Expr::MethodCall {
+                receiver,
+                method,
+                arguments,
+            } => {
+                let expr =
+                    self.resolve_method(receiver, method, arguments.clone(), expression.location)?;
+                self.expr_type(&Expression {
+                    expr,
+                    location: expression.location,
+                })
+            }
+            Expr::Null { ty } => Ok(ty.clone()),
             Expr::Literal(lit) => match lit {
-                Literal::Numeric(literal) => Ok(Type::Numeric(
-                    NumericType::from_literal(literal)?.unwrap_or(if literal.contains(".") {
-                        NumericType::F64
-                    } else {
-                        NumericType::I32
-                    }),
-                )),
+                Literal::Numeric(literal) => Ok(Type::Numeric(literal.ty())),
                 Literal::Char(_) => Ok(Type::Named("char".to_string())),
                 Literal::String(_) => Ok(Type::Named("String".to_string())),
                 Literal::Boolean(_) => Ok(Type::Boolean),
@@ -287,6 +1814,9 @@ impl SymbolTable {
                     )
                 })?;
 
+                if sym.kind == SymbolKind::Function {
+                    return self.function_type(name, expression.location);
+                }
                 Ok(sym.ty.clone())
             }
             Expr::Binary {
@@ -303,6 +1833,19 @@ impl SymbolTable {
                     location: expression.location,
                 })?;
 
+                // `p + n` and `p - n` move the pointer by `n` elements
+                if let Type::Pointer(_) = left_type
+                    && matches!(operator, Operator::Plus | Operator::Minus)
+                {
+                    anyhow::ensure!(
+                        matches!(&right_type, Type::Numeric(n) if n.is_integer()),
+                        "Pointer offset at {:?} must be an integer, found {}",
+                        expression.location,
+                        right_type
+                    );
+                    return Ok(left_type);
+                }
+
                 if left_type != right_type {
                     anyhow::bail!(
                         "Type mismatch in binary expression at {:?}: left is {:?}, right is {:?}",
@@ -312,6 +1855,35 @@ impl SymbolTable {
                     );
                 }
 
+                if matches!(&left_type, Type::Named(name) if name == "String")
+                    && !matches!(
+                        operator,
+                        Operator::Plus | Operator::Equals | Operator::NotEquals
+                    )
+                {
+                    anyhow::bail!(
+                        "Operator {:?} is not supported for String operands at {:?}",
+                        operator,
+                        expression.location
+                    );
+                }
+
+                if matches!(&left_type, Type::Named(name) if self.enums.contains_key(name)) {
+                    anyhow::bail!(
+                        "Operator {:?} is not supported for enum operands at {:?}, use match",
+                        operator,
+                        expression.location
+                    );
+                }
+
+                if matches!(left_type, Type::Function { .. } | Type::Closure { .. }) {
+                    anyhow::bail!(
+                        "Operator {:?} is not supported for function operands at {:?}",
+                        operator,
+                        expression.location
+                    );
+                }
+
                 match operator {
                     Operator::Exclem | Operator::LogicalAnd | Operator::LogicalOr => {
                         if left_type != Type::Boolean {
@@ -328,8 +1900,28 @@ impl SymbolTable {
                     | Operator::Less
                     | Operator::LessEquals
                     | Operator::Greater
-                    | Operator::GreaterEquals => Ok(Type::Boolean),
-                    _ => Ok(left_type),
+                    | Operator::GreaterEquals => {
+                        self.check_operator(&left_type, *operator, expression.location)?;
+                        Ok(Type::Boolean)
+                    }
+                    Operator::Ampersand
+                    | Operator::Pipe
+                    | Operator::Caret
+                    | Operator::ShiftLeft
+                    | Operator::ShiftRight => match left_type {
+                        Type::Numeric(n) if n.is_integer() => Ok(left_type),
+                        // checked for each call by the requirements
+                        Type::Param(_) => Ok(left_type),
+                        _ => anyhow::bail!(
+                            "Bitwise operators require integer operands at {:?}, found {:?}",
+                            expression.location,
+                            left_type
+                        ),
+                    },
+                    _ => {
+                        self.check_operator(&left_type, *operator, expression.location)?;
+                        Ok(left_type)
+                    }
                 }
             }
             Expr::Unary { operand, operator } => {
@@ -339,14 +1931,30 @@ impl SymbolTable {
                 })?;
                 Ok(match operator {
                     Operator::Ampersand => Type::Pointer(Box::new(op_type)),
+                    Operator::Tilde => match op_type {
+                        Type::Numeric(n) if n.is_integer() => op_type,
+                        Type::Param(_) => op_type,
+                        _ => anyhow::bail!(
+                            "Bitwise operators require integer operands at {:?}, found {:?}",
+                            expression.location,
+                            op_type
+                        ),
+                    },
                     Operator::Asterisk => match op_type {
+                        Type::Pointer(x) if *x == Type::Void => anyhow::bail!(
+                            "Dereferencing a pointer to void at {:?}",
+                            expression.location
+                        ),
                         Type::Pointer(x) => *x,
                         _ => anyhow::bail!(
                             "Dereferencing a non-pointer type at {:?}",
                             expression.location
                         ),
                     },
-                    _ => op_type,
+                    _ => {
+                        self.check_operator(&op_type, *operator, expression.location)?;
+                        op_type
+                    }
                 })
             }
             Expr::Assignment { target, value } => {
@@ -370,7 +1978,11 @@ impl SymbolTable {
 
                 Ok(target_type)
             }
-            Expr::FunctionCall { name, .. } => {
+            Expr::FunctionCall {
+                name,
+                type_arguments,
+                ..
+            } => {
                 let sym = self.lookup(name).ok_or_else(|| {
                     anyhow::anyhow!(
                         "Call to undeclared function '{}' at {:?}",
@@ -379,12 +1991,386 @@ impl SymbolTable {
                     )
                 })?;
 
-                Ok(sym.ty.clone())
+                match self.generics.get(name) {
+                    Some(generic) => {
+                        let bindings = generic
+                            .type_params
+                            .iter()
+                            .cloned()
+                            .zip(type_arguments.iter().cloned())
+                            .collect();
+                        Ok(sym.ty.substitute(&bindings))
+                    }
+                    None => Ok(sym.ty.clone()),
+                }
+            }
+            Expr::Call { callee, .. } => {
+                match self.expr_type(&Expression {
+                    expr: *callee.clone(),
+                    location: expression.location,
+                })? {
+                    Type::Function { ty, .. } | Type::Closure { ty, .. } => Ok(*ty),
+                    ty => anyhow::bail!(
+                        "Cannot call a value of type {} at {:?}",
+                        ty,
+                        expression.location
+                    ),
+                }
+            }
+            Expr::Lambda { params, ty, .. } => Ok(Type::Closure {
+                params: params.iter().map(|x| x.ty.clone()).collect(),
+                ty: Box::new(ty.clone()),
+            }),
+            Expr::If {
+                condition,
+                then_branch,
+                then_value,
+                else_branch,
+                else_value,
+            } => {
+                let cond_type = self.expr_type(&Expression {
+                    expr: *condition.clone(),
+                    location: expression.location,
+                })?;
+                if cond_type != Type::Boolean {
+                    anyhow::bail!(
+                        "Condition in if expression must be boolean at {:?}, found {:?}",
+                        expression.location,
+                        cond_type
+                    );
+                }
+
+                let then_type = self.branch_type(then_branch, then_value, expression.location)?;
+                let else_type = self.branch_type(else_branch, else_value, expression.location)?;
+                if then_type != else_type {
+                    anyhow::bail!(
+                        "Type mismatch in if expression branches at {:?}: then is {:?}, else is {:?}",
+                        expression.location,
+                        then_type,
+                        else_type
+                    );
+                }
+
+                Ok(then_type)
+            }
+            Expr::Interpolated { parts } => {
+                for part in parts {
+                    if let FormatPart::Expr { expr, spec } = part {
+                        let ty = self.expr_type(&Expression {
+                            expr: expr.clone(),
+                            location: expression.location,
+                        })?;
+                        check_format(&ty, spec).map_err(|e| {
+                            anyhow::anyhow!("{e} in interpolation at {:?}", expression.location)
+                        })?;
+                    }
+                }
+                Ok(Type::Named("String".to_string()))
+            }
+            Expr::Variant {
+                enum_name, variant, ..
+            } => {
+                self.variant(enum_name, variant, expression.location)?;
+                Ok(Type::Named(enum_name.clone()))
+            }
+            Expr::Match { scrutinee, arms } => {
+                let ty = self.expr_type(&Expression {
+                    expr: *scrutinee.clone(),
+                    location: expression.location,
+                })?;
+
+                let mut match_type: Option<Type> = None;
+                for arm in arms {
+                    let arm_type = match &arm.value {
+                        Some(value) => {
+                            self.push_scope();
+                            let arm_type = self
+                                .declare_bindings(&arm.pattern, &ty, arm.location)
+                                .and_then(|_| self.branch_type(&arm.body, value, arm.location));
+                            self.pop_scope();
+                            arm_type?
+                        }
+                        None => Type::Void,
+                    };
+                    if let Some(match_type) = &match_type
+                        && *match_type != arm_type
+                    {
+                        anyhow::bail!(
+                            "Type mismatch in match arms at {:?}: first arm is {:?}, this arm is {:?}",
+                            arm.location,
+                            match_type,
+                            arm_type
+                        );
+                    }
+                    match_type = Some(arm_type);
+                }
+
+                Ok(match_type.unwrap_or(Type::Void))
+            }
+        }
+    }
+
+    fn declare_params(&mut self, params: &[Param], location: Location) -> anyhow::Result<()> {
+        for param in params {
+            self.declare(Symbol {
+                name: param.name.clone(),
+                kind: SymbolKind::Parameter,
+                mutable: false,
+                ty: param.ty.clone(),
+                location,
+            })?;
+            // a closure passed in lives as long as the caller
+            let bound = self.depth - 1;
+            if matches!(param.ty, Type::Closure { .. })
+                && let Some(scope) = self.scopes.last_mut()
+            {
+                scope.bounds.insert(param.name.clone(), bound);
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the body of a lambda and returns the variables it captures.
+    fn build_lambda(
+        &mut self,
+        params: &[Param],
+        ty: &Type,
+        body: &mut [Statement],
+        value: Option<&mut Expr>,
+        location: Location,
+    ) -> anyhow::Result<Vec<Param>> {
+        self.lambdas.push(LambdaFrame {
+            scope: self.scopes.len(),
+            captures: Vec::new(),
+        });
+        let outer_return_type = self.return_type.replace(ty.clone());
+        self.depth += 1;
+        self.push_scope();
+        let result = self.build_lambda_body(params, ty, body, value, location);
+        self.pop_scope();
+        self.depth -= 1;
+        self.return_type = outer_return_type;
+        let frame = self.lambdas.pop();
+        result?;
+        Ok(frame.map(|x| x.captures).unwrap_or_default())
+    }
+
+    fn build_lambda_body(
+        &mut self,
+        params: &[Param],
+        ty: &Type,
+        body: &mut [Statement],
+        value: Option<&mut Expr>,
+        location: Location,
+    ) -> anyhow::Result<()> {
+        self.declare_params(params, location)?;
+        for stmt in body {
+            self.build_stmt(stmt)?;
+        }
+        if let Some(value) = value {
+            self.infer_expr(value, Some(ty), location)?;
+            let expression = Expression {
+                expr: value.clone(),
+                location,
+            };
+            self.build_expr(&expression)?;
+            let value_type = self.expr_type(&expression)?;
+            anyhow::ensure!(
+                value_type == *ty,
+                "Type mismatch in lambda at {:?}: returns {}, found {}",
+                location,
+                ty,
+                value_type
+            );
+            self.fold_expr(value, location)?;
+            self.check_escape(value, location)?;
+        }
+        Ok(())
+    }
+
+    /// Records `name` as a capture of the lambdas being built that it's
+    /// declared outside of. Globals aren't captured.
+    fn capture(&mut self, name: &str) {
+        let Some(scope) = self.lookup_scope(name) else {
+            return;
+        };
+        let sym = &self.scopes[scope].symbols[name];
+        if scope == 0 || !matches!(sym.kind, SymbolKind::Variable | SymbolKind::Parameter) {
+            return;
+        }
+        let capture = Param {
+            name: name.to_string(),
+            ty: sym.ty.clone(),
+        };
+        for frame in &mut self.lambdas {
+            if scope < frame.scope && !frame.captures.contains(&capture) {
+                frame.captures.push(capture.clone());
+            }
+        }
+    }
+
+    /// Turns `receiver.method(arguments)` into a call of the method of the
+    /// receiver's type, passing the receiver the way its `self` is declared.
+    fn resolve_method(
+        &mut self,
+        receiver: &Expr,
+        method: &str,
+        arguments: Vec<Expr>,
+        location: Location,
+    ) -> anyhow::Result<Expr> {
+        let ty = self.expr_type(&Expression {
+            expr: receiver.clone(),
+            location,
+        })?;
+        let (type_name, is_pointer) = match &ty {
+            Type::Pointer(inner) => (inner.to_string(), true),
+            ty => (ty.to_string(), false),
+        };
+        let name = format!("{type_name}::{method}");
+        if !self.methods.contains(&name) {
+            anyhow::ensure!(
+                self.lookup(&name).is_none(),
+                "'{}' at {:?} doesn't take self, call it as `{}(...)`",
+                name,
+                location,
+                name
+            );
+            anyhow::bail!("Type {} has no method '{}' at {:?}", ty, method, location);
+        }
+        let takes_pointer = matches!(
+            self.function_params.get(&name).and_then(|x| x.first()),
+            Some(Type::Pointer(_))
+        );
+        let receiver = match (takes_pointer, is_pointer) {
+            (true, false) => {
+                anyhow::ensure!(
+                    matches!(receiver, Expr::Variable(_)),
+                    "'{}' at {:?} takes *self, so it has to be called on a variable",
+                    name,
+                    location
+                );
+                self.check_mutable(receiver, "assign to", location)?;
+                Expr::Unary {
+                    operator: Operator::Ampersand,
+                    operand: Box::new(receiver.clone()),
+                }
+            }
+            (false, true) => Expr::Unary {
+                operator: Operator::Asterisk,
+                operand: Box::new(receiver.clone()),
+            },
+            _ => receiver.clone(),
+        };
+        Ok(Expr::FunctionCall {
+            name,
+            type_arguments: Vec::new(),
+            arguments: std::iter::once(receiver).chain(arguments).collect(),
+        })
+    }
+
+    /// The depth of the function whose stack holds the values captured by
+    /// the closure `expr` evaluates to, if it captured any.
+    fn closure_bound(&mut self, expr: &Expr) -> Option<usize> {
+        match expr {
+            Expr::Lambda { captures, .. } if !captures.is_empty() => Some(self.depth),
+            Expr::Variable(name) => self.lookup_bound(name),
+            Expr::FunctionCall { .. } | Expr::Call { .. }
+                if !self
+                    .expr_type(&Expression {
+                        expr: expr.clone(),
+                        location: Location::default(),
+                    })
+                    .is_ok_and(|x| matches!(x, Type::Closure { .. })) =>
+            {
+                None
+            }
+            // a closure passed in may come back out
+            Expr::FunctionCall { arguments, .. } => {
+                arguments.iter().filter_map(|x| self.closure_bound(x)).max()
             }
+            Expr::Call { callee, arguments } => std::iter::once(callee.as_ref())
+                .chain(arguments)
+                .filter_map(|x| self.closure_bound(x))
+                .max(),
+            Expr::Assignment { value, .. } => self.closure_bound(value),
+            Expr::If {
+                then_value,
+                else_value,
+                ..
+            } => self
+                .closure_bound(then_value)
+                .max(self.closure_bound(else_value)),
+            Expr::Match { arms, .. } => arms
+                .iter()
+                .filter_map(|x| x.value.as_ref().and_then(|x| self.closure_bound(x)))
+                .max(),
+            _ => None,
+        }
+    }
+
+    /// Rejects a closure leaving the function that holds its captured values
+    /// on the stack.
+    fn check_escape(&mut self, expr: &Expr, location: Location) -> anyhow::Result<()> {
+        match self.closure_bound(expr) {
+            Some(bound) if bound >= self.depth => anyhow::bail!(
+                "Closure returned at {:?} captures local variables, which don't outlive the function",
+                location
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// The type of the function `name` used as a value.
+    fn function_type(&self, name: &str, location: Location) -> anyhow::Result<Type> {
+        anyhow::ensure!(
+            !self.generics.contains_key(name),
+            "Generic function '{}' at {:?} cannot be used as a value",
+            name,
+            location
+        );
+        let (Some(params), Some(sym)) = (self.function_params.get(name), self.lookup(name)) else {
+            anyhow::bail!(
+                "Builtin function '{}' at {:?} cannot be used as a value",
+                name,
+                location
+            );
+        };
+        Ok(Type::Function {
+            params: params.clone(),
+            ty: Box::new(sym.ty.clone()),
+        })
+    }
+
+    fn branch_type(
+        &mut self,
+        stmts: &[Statement],
+        value: &Expr,
+        location: Location,
+    ) -> anyhow::Result<Type> {
+        // the value may refer to variables declared in the branch itself
+        self.push_scope();
+        let result = self.scoped_branch_type(stmts, value, location);
+        self.pop_scope();
+        result
+    }
+
+    fn scoped_branch_type(
+        &mut self,
+        stmts: &[Statement],
+        value: &Expr,
+        location: Location,
+    ) -> anyhow::Result<Type> {
+        for stmt in stmts {
+            self.build_stmt(&mut stmt.clone())?;
         }
+        self.block_type(stmts)?;
+        self.expr_type(&Expression {
+            expr: value.clone(),
+            location,
+        })
     }
 
-    fn block_type(&self, stmts: &[Statement]) -> anyhow::Result<Type> {
+    fn block_type(&mut self, stmts: &[Statement]) -> anyhow::Result<Type> {
         let mut last_type = Type::Void;
         for stmt in stmts {
             last_type = self.stmt_type(stmt)?;
@@ -392,7 +2378,7 @@ impl SymbolTable {
         Ok(last_type)
     }
 
-    fn stmt_type(&self, statement: &Statement) -> anyhow::Result<Type> {
+    fn stmt_type(&mut self, statement: &Statement) -> anyhow::Result<Type> {
         match &statement.stmt {
             Stmt::Let { ty, value, .. } => {
                 let expr_type = self.expr_type(&Expression {
@@ -464,25 +2450,81 @@ impl SymbolTable {
                 self.stmt_type(body)?;
                 Ok(Type::Void)
             }
-            Stmt::Return { value: None } | Stmt::Break | Stmt::Continue | Stmt::Semicolon => {
-                Ok(Type::Void)
-            }
+            Stmt::Const { .. }
+            | Stmt::Static { .. }
+            | Stmt::Enum { .. }
+            | Stmt::Impl { .. }
+            | Stmt::Return { value: None }
+            | Stmt::Break
+            | Stmt::Continue
+            | Stmt::Semicolon => Ok(Type::Void),
         }
     }
 
-    pub fn build(&mut self, statements: &[Statement]) -> anyhow::Result<()> {
-        self.declare(Symbol {
-            name: "print".to_string(),
-            kind: SymbolKind::Function,
-            ty: Type::Void,
-            location: Location::default(),
-        })?;
-        self.declare(Symbol {
-            name: "exit".to_string(),
-            kind: SymbolKind::Function,
-            ty: Type::Void,
-            location: Location::default(),
-        })?;
+    /// Checks `statements` and resolves the types of their numeric literals.
+    pub fn build(&mut self, statements: &mut [Statement]) -> anyhow::Result<()> {
+        let string = Type::Named("String".to_string());
+        let builtins = [
+            ("print", Type::Void),
+            ("exit", Type::Void),
+            ("len", Type::Numeric(NumericType::USize)),
+            ("slice", string.clone()),
+            ("push", Type::Void),
+            ("to_string", string),
+            ("parse_int", Type::Numeric(NumericType::I64)),
+            ("parse_float", Type::Numeric(NumericType::F64)),
+            (
+                "c_str",
+                Type::Pointer(Box::new(Type::Numeric(NumericType::I8))),
+            ),
+        ];
+        // `alloc<T>()`, `alloc_array<T>(n)`, `free(p)` and the integer
+        // builtins like `wrapping_add(a, b)` are generic
+        let param = Type::Param("T".to_string());
+        let pointer = Type::Pointer(Box::new(param.clone()));
+        let mut generic_builtins = vec![
+            ("alloc", Vec::new(), pointer.clone()),
+            (
+                "alloc_array",
+                vec![Type::Numeric(NumericType::USize)],
+                pointer.clone(),
+            ),
+            ("free", vec![pointer], Type::Void),
+        ];
+        for name in INTEGER_BUILTINS {
+            generic_builtins.push((name, vec![param.clone(), param.clone()], param.clone()));
+        }
+        for (name, params, ty) in generic_builtins {
+            let requirements = if INTEGER_BUILTINS.contains(&name) {
+                vec![("T".to_string(), Requirement::Integer)]
+            } else {
+                Vec::new()
+            };
+            self.declare(Symbol {
+                name: name.to_string(),
+                kind: SymbolKind::Function,
+                mutable: false,
+                ty,
+                location: Location::default(),
+            })?;
+            self.function_params.insert(name.to_string(), params);
+            self.generics.insert(
+                name.to_string(),
+                Generic {
+                    type_params: vec!["T".to_string()],
+                    requirements,
+                },
+            );
+        }
+        for (name, ty) in builtins {
+            self.declare(Symbol {
+                name: name.to_string(),
+                kind: SymbolKind::Function,
+                mutable: false,
+                ty,
+                location: Location::default(),
+            })?;
+        }
 
         for stmt in statements {
             self.build_stmt(stmt)?;
@@ -492,3 +2534,261 @@ impl SymbolTable {
         Ok(())
     }
 }
+
+/// Integer arithmetic that doesn't panic on overflow, wrapping around or
+/// clamping to the range of the type instead.
+const INTEGER_BUILTINS: [&str; 6] = [
+    "wrapping_add",
+    "wrapping_sub",
+    "wrapping_mul",
+    "saturating_add",
+    "saturating_sub",
+    "saturating_mul",
+];
+
+/// Whether `expr` is built only from unsuffixed numeric literals, and so
+/// takes its type from the context.
+fn is_flexible(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(Literal::Numeric(literal)) => literal.suffix.is_none(),
+        Expr::Null { .. } => true,
+        Expr::Unary {
+            operator: Operator::Minus | Operator::Tilde,
+            operand,
+        } => is_flexible(operand),
+        Expr::Binary {
+            left,
+            right,
+            operator:
+                Operator::Plus
+                | Operator::Minus
+                | Operator::Asterisk
+                | Operator::Slash
+                | Operator::Percent
+                | Operator::Ampersand
+                | Operator::Pipe
+                | Operator::Caret
+                | Operator::ShiftLeft
+                | Operator::ShiftRight,
+        } => is_flexible(left) && is_flexible(right),
+        _ => false,
+    }
+}
+
+/// `literal` as it is written in the source.
+fn describe_literal(literal: &Literal) -> String {
+    match literal {
+        Literal::Numeric(literal) => match literal.value {
+            NumericValue::Integer(value) => value.to_string(),
+            NumericValue::Float(value) => value.to_string(),
+        },
+        Literal::Char(c) => format!("{c:?}"),
+        Literal::String(s) => format!("{s:?}"),
+        Literal::Boolean(b) => b.clone(),
+    }
+}
+
+/// A set of values a pattern can test for, see [`SymbolTable::useful`].
+#[derive(Debug, Clone, PartialEq)]
+enum Constructor {
+    Variant(String),
+    Boolean(bool),
+    /// An inclusive range of integers or chars.
+    Range(i128, i128),
+    /// A literal of a type with too many values to list.
+    Literal(Literal),
+}
+
+/// Whether integer `+`, `-`, `*` or negation of `operands` leaves the range
+/// of their type.
+fn overflows(operator: Operator, operands: &[&Literal]) -> bool {
+    let integers: Option<Vec<(i128, NumericType)>> = operands
+        .iter()
+        .map(|x| match x {
+            Literal::Numeric(literal) => match literal.value {
+                NumericValue::Integer(value) => Some((value, literal.ty())),
+                NumericValue::Float(_) => None,
+            },
+            _ => None,
+        })
+        .collect();
+    let (ty, result) = match (operator, integers.as_deref()) {
+        (Operator::Minus, Some([(a, ty)])) => (ty, a.checked_neg()),
+        (Operator::Plus, Some([(a, ty), (b, _)])) => (ty, a.checked_add(*b)),
+        (Operator::Minus, Some([(a, ty), (b, _)])) => (ty, a.checked_sub(*b)),
+        (Operator::Asterisk, Some([(a, ty), (b, _)])) => (ty, a.checked_mul(*b)),
+        _ => return false,
+    };
+    let Some((min, max)) = ty.integer_range() else {
+        return false;
+    };
+    result.is_none_or(|x| x < min || x > max)
+}
+
+/// The smallest and largest value of a type that range patterns work on.
+fn value_range(ty: &Type) -> Option<(i128, i128)> {
+    match ty {
+        Type::Numeric(ty) => ty.integer_range(),
+        Type::Named(name) if name == "char" => Some((0, u8::MAX as i128)),
+        _ => None,
+    }
+}
+
+/// The values an integer or char pattern matches, as an inclusive range.
+fn pattern_range(pattern: &Pattern) -> Option<(i128, i128)> {
+    let value = |literal: &Literal| match literal {
+        Literal::Numeric(literal) => match literal.value {
+            NumericValue::Integer(value) => Some(value),
+            NumericValue::Float(_) => None,
+        },
+        Literal::Char(c) => Some(*c as i128),
+        _ => None,
+    };
+    match pattern {
+        Pattern::Literal(literal) => value(literal).map(|x| (x, x)),
+        Pattern::Range {
+            start,
+            end,
+            inclusive,
+        } => Some((value(start)?, value(end)? - i128::from(!inclusive))),
+        _ => None,
+    }
+}
+
+/// Splits `start..=end` into ranges that each lie either completely inside
+/// or completely outside of every range pattern in `heads`.
+fn split_range(start: i128, end: i128, heads: &[&Pattern]) -> Vec<Constructor> {
+    let mut bounds = vec![start, end + 1];
+    for (head_start, head_end) in heads.iter().filter_map(|x| pattern_range(x)) {
+        bounds.extend(
+            [head_start, head_end + 1]
+                .into_iter()
+                .filter(|x| (start + 1..=end).contains(x)),
+        );
+    }
+    bounds.sort_unstable();
+    bounds.dedup();
+    bounds
+        .windows(2)
+        .map(|x| Constructor::Range(x[0], x[1] - 1))
+        .collect()
+}
+
+/// The rest of `row` if its first pattern matches `constructor`, with the
+/// fields of the constructor in front. Wildcards match with `arity`
+/// wildcard fields.
+fn specialize<'a>(
+    row: &[&'a Pattern],
+    constructor: &Constructor,
+    arity: usize,
+) -> Option<Vec<&'a Pattern>> {
+    let matches = match (row[0], constructor) {
+        (Pattern::Wildcard | Pattern::Binding(_), _) => {
+            let mut specialized = vec![&Pattern::Wildcard; arity];
+            specialized.extend_from_slice(&row[1..]);
+            return Some(specialized);
+        }
+        (
+            Pattern::Variant {
+                variant, fields, ..
+            },
+            Constructor::Variant(name),
+        ) => {
+            if variant != name {
+                return None;
+            }
+            let mut specialized: Vec<&Pattern> = fields.iter().collect();
+            specialized.extend_from_slice(&row[1..]);
+            return Some(specialized);
+        }
+        (Pattern::Literal(Literal::Boolean(value)), Constructor::Boolean(b)) => {
+            (value == "true") == *b
+        }
+        (pattern, Constructor::Range(start, end)) => {
+            pattern_range(pattern).is_some_and(|(pattern_start, pattern_end)| {
+                pattern_start <= *start && *end <= pattern_end
+            })
+        }
+        (Pattern::Literal(literal), Constructor::Literal(other)) => literal == other,
+        _ => false,
+    };
+    matches.then(|| row[1..].to_vec())
+}
+
+/// Binds the type parameters in `param` so that it becomes `arg`.
+fn unify(param: &Type, arg: &Type, bindings: &mut HashMap<String, Type>) -> anyhow::Result<()> {
+    match (param, arg) {
+        (Type::Param(name), _) => match bindings.get(name) {
+            Some(bound) if bound != arg => {
+                anyhow::bail!("Type parameter '{name}' is both {bound} and {arg}")
+            }
+            Some(_) => Ok(()),
+            None => {
+                bindings.insert(name.clone(), arg.clone());
+                Ok(())
+            }
+        },
+        (Type::Pointer(param), Type::Pointer(arg)) => unify(param, arg, bindings),
+        (
+            Type::Function { params, ty },
+            Type::Function {
+                params: args,
+                ty: arg,
+            },
+        )
+        | (
+            Type::Closure { params, ty },
+            Type::Closure {
+                params: args,
+                ty: arg,
+            },
+        ) if params.len() == args.len() => {
+            for (param, arg) in params.iter().zip(args) {
+                unify(param, arg, bindings)?;
+            }
+            unify(ty, arg, bindings)
+        }
+        _ => {
+            anyhow::ensure!(param == arg, "Expected {param}, found {arg}");
+            Ok(())
+        }
+    }
+}
+
+/// Checks that a value of type `ty` can be formatted with `spec`.
+fn check_format(ty: &Type, spec: &FormatSpec) -> anyhow::Result<()> {
+    let (kinds, precision, zero_pad): (&[FormatKind], _, _) = match ty {
+        Type::Numeric(n) if n.is_integer() => (
+            &[
+                FormatKind::Display,
+                FormatKind::LowerHex,
+                FormatKind::UpperHex,
+                FormatKind::Octal,
+            ],
+            false,
+            true,
+        ),
+        Type::Numeric(_) => (&[FormatKind::Display, FormatKind::Exponent], true, true),
+        Type::Named(name) if name == "String" => (&[FormatKind::Display], true, false),
+        Type::Named(name) if name == "char" => (&[FormatKind::Display], false, false),
+        Type::Boolean => (&[FormatKind::Display], false, false),
+        // checked for each call by the requirements
+        Type::Param(_) => return Ok(()),
+        _ => anyhow::bail!("Cannot format a value of type {ty:?}"),
+    };
+
+    anyhow::ensure!(
+        kinds.contains(&spec.kind),
+        "Format {:?} is not supported for {ty:?}",
+        spec.kind
+    );
+    anyhow::ensure!(
+        precision || spec.precision.is_none(),
+        "Precision is not supported for {ty:?}"
+    );
+    anyhow::ensure!(
+        zero_pad || !spec.zero_pad,
+        "Zero padding is not supported for {ty:?}"
+    );
+    Ok(())
+}
@@ -2,7 +2,11 @@ macro_rules! impl_test {
     ($name:ident, $matcher:expr) => {
         #[test]
         fn $name() -> anyhow::Result<()> {
-            $crate::run_file(concat!("tests/", stringify!($name), ".lang"))?;
+            $crate::run_file(
+                concat!("tests/", stringify!($name), ".lang"),
+                lang::heap::Memory::Manual,
+                false,
+            )?;
             let result =
                 ::std::process::Command::new(concat!("build/", stringify!($name))).output()?;
             anyhow::ensure!(
@@ -19,7 +23,11 @@ macro_rules! impl_test {
     ($name:ident, $input:expr, $matcher:expr) => {
         #[test]
         fn $name() -> anyhow::Result<()> {
-            $crate::run_file(concat!("tests/", stringify!($name), ".lang"))?;
+            $crate::run_file(
+                concat!("tests/", stringify!($name), ".lang"),
+                lang::heap::Memory::Manual,
+                false,
+            )?;
             let mut process = ::std::process::Command::new(concat!("build/", stringify!($name)))
                 .stdin(::std::process::Stdio::piped())
                 .stdout(::std::process::Stdio::piped())
@@ -50,3 +58,1416 @@ impl_test!(while_loop, |x| {
 impl_test!(calculator, "5 + 2", |x| {
     matches!(x, "Enter an expression: 5 + 2 = 7\n")
 });
+
+#[test]
+fn engine_host_functions() -> anyhow::Result<()> {
+    let mut engine = lang::Engine::new();
+    engine.capture_output();
+    engine.register_function("host_add", |a: i32, b: i32| a + b)?;
+    engine.set_global("offset", 10i32);
+
+    let program = engine.compile(
+        "func compute(i32 x) => i32 {
+            print(\"x = {}\", x);
+            return host_add(x, offset);
+        }",
+    )?;
+    engine.run(&program)?;
+
+    let result: i32 = engine.call("compute", (5i32,))?;
+    assert_eq!(result, 15);
+    assert_eq!(engine.take_output(), "x = 5\n");
+
+    engine.set_global("offset", 1i32);
+    assert_eq!(engine.call::<i32>("compute", (5i32,))?, 6);
+    assert_eq!(engine.get_global::<i32>("offset")?, 1);
+
+    // the parameter types reach the semantic analyzer, literals take them
+    // and wrong arguments don't compile
+    engine.register_function("host_byte", |a: u8| a as i32)?;
+    let program = engine.compile("func byte() => i32 { return host_byte(200); }")?;
+    engine.run(&program)?;
+    assert_eq!(engine.call::<i32>("byte", ())?, 200);
+    for (call, message) in [
+        ("host_add(1)", "takes 2 arguments but 1 were given"),
+        ("host_add(true, 2)", "expected i32, found bool"),
+        ("host_byte(offset)", "expected u8, found i32"),
+    ] {
+        let e = engine
+            .compile(&format!("func bad() => i32 {{ return {call}; }}"))
+            .unwrap_err();
+        assert!(e.to_string().contains(message), "{call}: {e}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn engine_limits() -> anyhow::Result<()> {
+    use lang::interpreter::{CallPolicy, Exit, LimitExceeded, Limits};
+
+    let mut engine = lang::Engine::new();
+    engine.set_limits(Limits {
+        max_steps: Some(1000),
+        max_call_depth: Some(64),
+        policy: CallPolicy::Deny(["system".to_string()].into()),
+        ..Default::default()
+    });
+
+    let program = engine.compile(
+        "extern system(*i8 cmd) => i32;
+        func spin() { while true {} }
+        func recurse(i32 n) => i32 { return recurse(n + 1); }
+        func quit() { exit(3); }
+        func shell() { system(\"ls\"); }
+        func hoard() { let mut s = to_string(\"grow\"); while true { s = s + \"0123456789abcdef\"; } }
+        func one() => i32 { return 1; }",
+    )?;
+    assert_eq!(engine.run(&program)?, None);
+
+    let err = engine.call::<()>("spin", ()).unwrap_err();
+    assert_eq!(err.downcast_ref(), Some(&LimitExceeded::Steps(1000)));
+    let err = engine.call::<i32>("recurse", (0,)).unwrap_err();
+    assert_eq!(err.downcast_ref(), Some(&LimitExceeded::CallDepth(64)));
+    let err = engine.call::<()>("quit", ()).unwrap_err();
+    assert_eq!(err.downcast_ref(), Some(&Exit(3)));
+    assert!(engine.call::<()>("shell", ()).is_err());
+    engine.set_limits(Limits {
+        max_steps: Some(100_000),
+        max_memory: Some(4096),
+        ..Default::default()
+    });
+    let err = engine.call::<()>("hoard", ()).unwrap_err();
+    assert_eq!(err.downcast_ref(), Some(&LimitExceeded::Memory(4096)));
+
+    // calls from the top level share the budget of the script
+    engine.set_limits(Limits {
+        max_steps: Some(1000),
+        ..Default::default()
+    });
+    let program = engine.compile("func tick() {} while true { tick(); }")?;
+    let err = engine.run(&program).unwrap_err();
+    assert_eq!(err.downcast_ref(), Some(&LimitExceeded::Steps(1000)));
+
+    // a cancel made between runs stops the next one, and only that one
+    engine.cancel_handle().cancel();
+    let err = engine.call::<i32>("one", ()).unwrap_err();
+    assert_eq!(err.downcast_ref(), Some(&LimitExceeded::Cancelled));
+    assert_eq!(engine.call::<i32>("one", ())?, 1);
+
+    Ok(())
+}
+
+#[test]
+fn runtime_backtrace() -> anyhow::Result<()> {
+    use lang::interpreter::RuntimeError;
+
+    let mut engine = lang::Engine::new();
+    let program = engine.compile_named(
+        "divide.lang",
+        "func divide(i32 a, i32 b) => i32 {
+            return a / b;
+        }
+        func compute(i32 x) => i32 {
+            let y = x - x;
+            return divide(x, y);
+        }",
+    )?;
+    engine.run(&program)?;
+
+    let err = engine.call::<i32>("compute", (5,)).unwrap_err();
+    assert_eq!(
+        format!("{err:#}"),
+        "Interpreter: Division by zero\n    at divide (divide.lang:2:18)\n    at compute (divide.lang:6:18): Division by zero"
+    );
+    let err = err.downcast_ref::<RuntimeError>().unwrap();
+    assert_eq!(err.message, "Division by zero");
+    let functions = err.backtrace.iter().map(|f| f.function.as_str());
+    assert_eq!(functions.collect::<Vec<_>>(), ["divide", "compute"]);
+    assert_eq!(err.backtrace[0].location.line, 2);
+    assert_eq!(err.backtrace[1].location.line, 6);
+    assert!(err.to_string().contains("\n    at divide (divide.lang:2:"));
+
+    Ok(())
+}
+
+#[test]
+fn debugger_session() -> anyhow::Result<()> {
+    use std::{
+        io::Write,
+        sync::{Arc, Mutex},
+    };
+
+    #[derive(Clone, Default)]
+    struct Output(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let source = "func add(i32 a, i32 b) => i32 {
+    let sum = a + b;
+    return sum;
+}
+func main() => i32 {
+    let x = add(1, 2);
+    return x;
+}";
+    let commands = "b 3\nc\np sum\nset sum = 10\nbt\nc\n";
+    let output = Output::default();
+
+    let mut engine = lang::Engine::new();
+    let program = engine.compile(source)?;
+    engine.set_debug_hook(Box::new(lang::debugger::Debugger::new(
+        commands.as_bytes(),
+        output.clone(),
+        source,
+    )));
+    engine.run(&program)?;
+    assert_eq!(engine.call::<i32>("main", ())?, 10);
+
+    let output = String::from_utf8(output.0.lock().unwrap().clone())?;
+    assert!(output.contains("3: return sum;"));
+    assert!(output.contains("sum = 3"));
+    assert!(output.contains("  at add (line 3)\n  at main (line 6)\n"));
+
+    Ok(())
+}
+
+impl_test!(short_circuit, |x| {
+    matches!(x, "check 0\ncheck 2\nor\ncheck 4\ncheck 5\nboth\n")
+});
+
+#[test]
+fn short_circuit_interpreter() -> anyhow::Result<()> {
+    let mut engine = lang::Engine::new();
+    engine.capture_output();
+    let program = engine.compile(
+        "func check(i32 x) => bool {
+            print(\"check {}\", x);
+            return x > 0;
+        }
+        func main() => bool {
+            return check(0) && check(1) || check(2);
+        }",
+    )?;
+    engine.run(&program)?;
+
+    assert!(engine.call::<bool>("main", ())?);
+    assert_eq!(engine.take_output(), "check 0\ncheck 2\n");
+
+    Ok(())
+}
+
+impl_test!(else_if, |x| {
+    matches!(x, "-5 is negative\n0 is zero\n7 is small\n42 is large\n")
+});
+
+impl_test!(bitwise, |x| matches!(
+    x,
+    "8 14 6 -13\n17 2\n-4 1073741820\n3\n"
+));
+
+impl_test!(numeric_literals, |x| {
+    matches!(x, "255 10 493 -16\n1000000 4294967295\n2500.0 1e-09 100\n")
+});
+
+#[test]
+fn numeric_literal_errors() {
+    let error = |source: &str| {
+        let mut lexer = lang::lexer::Lexer::new(source.to_string());
+        lexer.tokenize().unwrap_err().to_string()
+    };
+    assert_eq!(
+        error("let x = 0x1_00u8;"),
+        "Numeric literal '0x1_00u8' is out of range for U8"
+    );
+    assert_eq!(
+        error("let x = -0o777i8;"),
+        "Numeric literal '-0o777i8' is out of range for I8"
+    );
+    assert_eq!(
+        error(
+            "let x = 0b1_0000_0000_0000_0000_0000_0000_0000_0000_0000_0000_0000_0000_0000_0000_0000_0000;"
+        ),
+        "Numeric literal '0b1_0000_0000_0000_0000_0000_0000_0000_0000_0000_0000_0000_0000_0000_0000_0000_0000' is too large"
+    );
+    assert_eq!(
+        error("let x = 1e39f32;"),
+        "Numeric literal '1e39f32' is out of range for F32"
+    );
+}
+
+#[test]
+fn numeric_value_equality() {
+    use lang::token::NumericValue;
+
+    let nan = NumericValue::Float(f64::NAN);
+    assert_eq!(nan, nan);
+    assert_ne!(NumericValue::Float(0.0), NumericValue::Float(-0.0));
+    assert_ne!(NumericValue::Float(1.0), NumericValue::Integer(1));
+}
+
+impl_test!(escapes, |x| matches!(x, "say \"hi\"\tback\\slash\nABC'\n"));
+
+#[test]
+fn escape_errors() {
+    let error = |source: &str| {
+        let mut lexer = lang::lexer::Lexer::new(source.to_string());
+        lexer.tokenize().unwrap_err().to_string()
+    };
+    assert!(error(r#"let s = "\x80";"#).contains("'\\x80' is out of range, use '\\u{80}'"));
+    assert!(error(r#"let c = '\xff';"#).contains("out of range"));
+    assert!(error(r#"let s = "\x4";"#).contains("expected two hex digits"));
+    assert!(error("let c = 'é';").contains("a char is a single ASCII byte"));
+    assert!(error(r#"let c = '\u{e9}';"#).contains("out of range"));
+    let mut lexer = lang::lexer::Lexer::new(r#"let c = '\u{7f}';"#.to_string());
+    assert!(lexer.tokenize().is_ok());
+}
+
+impl_test!(multiline_strings, |x| {
+    matches!(
+        x,
+        "raw \\n stays\\nsay \"hi\"\ntwo\nlines\nblock:\n  indented\ndone\t!inline \"quotes\"\n"
+    )
+});
+
+impl_test!(interpolation, |x| {
+    matches!(
+        x,
+        "result = 0003.750\n42 2a    42|42   |00042 255 FF 52\nlang: la true c 1.5 1.000000e-09 100% {} {}\nnested lang!\n"
+    )
+});
+
+#[test]
+fn interpolation_interpreter() -> anyhow::Result<()> {
+    let mut engine = lang::Engine::new();
+    let program = engine.compile(
+        r#"func describe(i32 n, f64 x) => String {
+            return "{n:>4}|{n:<4}|{-n:05} {n:x} {x:.2} {x} {x:e} {n > 0} {"[{n}]"}";
+        }
+        func letter(char c) => String {
+            return "{'c'}{c:>2}" + to_string(c);
+        }"#,
+    )?;
+    engine.run(&program)?;
+
+    let output: String = engine.call("describe", (42, 0.5))?;
+    assert_eq!(output, "  42|42  |-0042 2a 0.50 0.5 5.000000e-01 true [42]");
+    let output: String = engine.call("letter", (b'x',))?;
+    assert_eq!(output, "c xx");
+    let err = engine.call::<String>("letter", (0xe9u8,)).unwrap_err();
+    assert!(format!("{err:#}").contains("isn't an ASCII char"));
+
+    // interpolations used as operands are freed once consumed
+    let ir = compile_ir(&std::fs::read_to_string("tests/interpolation.lang")?)?;
+    assert_eq!(ir.matches("call void @free(ptr").count(), 5);
+
+    Ok(())
+}
+
+impl_test!(strings, |x| {
+    matches!(
+        x,
+        "Hello, world! 13\nabcdef42\ncde\nequal\nnot equal\nabcdef42 abcdef42!\n\
+         xyz xyw xy xyz xyw xy\n2000\nhé 1 -15.000000\n3 5 true 3\n"
+    )
+});
+
+#[test]
+fn string_errors() -> anyhow::Result<()> {
+    crate::run_file(
+        "tests/string_errors.lang",
+        lang::heap::Memory::Manual,
+        false,
+    )?;
+    let stderr = |case: &str| -> anyhow::Result<String> {
+        let result = std::process::Command::new("build/string_errors")
+            .env("CASE", case)
+            .output()?;
+        assert!(!result.status.success());
+        Ok(String::from_utf8(result.stderr)?)
+    };
+    assert_eq!(
+        stderr("0")?,
+        "Runtime error: Slice 0..2 is not on a character boundary\n"
+    );
+    assert_eq!(stderr("1")?, "Runtime error: Invalid float '0x10'\n");
+    Ok(())
+}
+
+#[test]
+fn strings_interpreter() -> anyhow::Result<()> {
+    let mut engine = lang::Engine::new();
+    let program = engine.compile(
+        r#"func build(String name) => String {
+            let mut s = "Hi " + name;
+            push(&s, to_string(len(name)));
+            if slice(s, 0, 2) == "Hi" {
+                push(&s, " " + to_string(parse_float("0.25")));
+            }
+            return s;
+        }
+        func exclaim(String text) => String {
+            let mut s = text;
+            let p = &s;
+            push(p, "!");
+            return s;
+        }
+        func copies() => String {
+            let mut t = "x";
+            push(&t, "y");
+            let mut u = t;
+            let v = t;
+            push(&t, "z");
+            push(&u, "w");
+            return "{t} {u} {v} " + slice("héllo", 0, 3) + " {len(to_string('a'))} {parse_float("-1.5e1")}";
+        }
+        func bad_slice() => String { return slice("héllo", 0, 2); }
+        func bad_float() => f64 { return parse_float("0x10"); }"#,
+    )?;
+    engine.run(&program)?;
+
+    let output: String = engine.call("build", ("lang".to_string(),))?;
+    assert_eq!(output, "Hi lang4 0.25");
+    let output: String = engine.call("exclaim", ("hey".to_string(),))?;
+    assert_eq!(output, "hey!");
+    // the same results as tests/strings.lang and tests/string_errors.lang
+    let output: String = engine.call("copies", ())?;
+    assert_eq!(output, "xyz xyw xy hé 1 -15");
+    let err = engine.call::<String>("bad_slice", ()).unwrap_err();
+    assert!(format!("{err:#}").contains("Slice 0..2 is not on a character boundary"));
+    let err = engine.call::<f64>("bad_float", ()).unwrap_err();
+    assert!(format!("{err:#}").contains("Invalid float '0x10'"));
+    // the C runtime rejects surrounding whitespace as well
+    let program = engine.compile(r#"let n = parse_int(" 7");"#)?;
+    assert!(engine.run(&program).is_err());
+    assert!(
+        engine
+            .compile(r#"func f() => bool { return "a" < "b"; }"#)
+            .is_err()
+    );
+
+    Ok(())
+}
+
+impl_test!(literal_inference, |x| {
+    matches!(
+        x,
+        "255 10000000000 1 10000000001 65535 -128\n3 18446744073709551615\n1\n"
+    )
+});
+
+#[test]
+fn literal_inference_interpreter() -> anyhow::Result<()> {
+    let mut engine = lang::Engine::new();
+    let program = engine.compile(
+        "func next(u8 x) => u8 { return x + 1; }
+        func mix(u32 a) => u64 {
+            let b: u64 = 1 << 40;
+            return b + 3;
+        }",
+    )?;
+    engine.run(&program)?;
+
+    assert_eq!(engine.call::<u8>("next", (254u8,))?, 255);
+    assert_eq!(engine.call::<u64>("mix", (1u32,))?, (1 << 40) + 3);
+    assert!(engine.compile("func f() => u8 { return 256; }").is_err());
+    assert!(engine.compile("func f() { let x: u32 = -1; }").is_err());
+
+    Ok(())
+}
+
+impl_test!(constants, |x| {
+    matches!(x, "-1 60 4800000000 240 3 0\nlang window 464\n")
+});
+
+#[test]
+fn constant_errors() -> anyhow::Result<()> {
+    let engine = lang::Engine::new();
+    let error = |source: &str| match engine.compile(source) {
+        Ok(_) => String::new(),
+        Err(e) => e.to_string(),
+    };
+
+    assert!(error("const BIG: u8 = 200 + 100;").contains("Arithmetic overflow"));
+    assert!(error("const ZERO: i32 = 0; const X: i32 = 1 / ZERO;").contains("Division by zero"));
+    assert!(error("func f() => i32 { return 1 << 40; }").contains("out of range"));
+    assert!(error("func f(i32 x) { const Y: i32 = x; }").contains("not known at compile time"));
+    assert!(error("const X: i32 = 1; func f() { X = 2; }").contains("Cannot assign"));
+    assert!(error("const X: i64 = 1;").is_empty());
+    // only the operands that run are folded, overflow is left to run
+    assert!(error("func f() => bool { return false && 1 / 0 == 0; }").is_empty());
+    assert!(error("func f() => bool { return true || 1 << 40 == 0; }").is_empty());
+    assert!(error("func f() => i32 { return 2147483647 + 1; }").is_empty());
+    assert!(error("static X: i8 = -(-128i8);").contains("Arithmetic overflow"));
+
+    Ok(())
+}
+
+#[test]
+fn mutability_errors() -> anyhow::Result<()> {
+    let engine = lang::Engine::new();
+    let error = |source: &str| match engine.compile(source) {
+        Ok(_) => String::new(),
+        Err(e) => e.to_string(),
+    };
+
+    assert!(error("func f() { let x = 1; x = 2; }").contains("consider `let mut x`"));
+    assert!(error("func f() { let x = 1; x += 2; }").contains("immutable variable 'x'"));
+    assert!(error("func f(i32 n) { n = 2; }").contains("parameter 'n'"));
+    assert!(
+        error("extern scanf(*i8 s, ..vars); func f() { let n = 0; scanf(\"%d\", &n); }")
+            .contains("consider `let mut n`")
+    );
+    assert!(error("func f() { let s = \"a\"; push(&s, \"b\"); }").contains("immutable"));
+    assert!(error("func f() { let mut x = 1; x += 2; let p = &x; }").is_empty());
+    // any pointer may be written through
+    assert!(
+        error("func f() { let x = 1; let p = &x; *p = 2; }")
+            .contains("Cannot take the address of immutable variable 'x'")
+    );
+    assert!(
+        error("func set(*i32 p) { *p = 2; } func f(i32 n) { set(&n); }").contains("parameter 'n'")
+    );
+
+    Ok(())
+}
+
+impl_test!(globals, |x| matches!(x, "3 45 1 hello globals\n"));
+
+#[test]
+fn globals_interpreter() -> anyhow::Result<()> {
+    let mut engine = lang::Engine::new();
+    let program = engine.compile(
+        "static mut CALLS: i32 = 0;
+        func count() => i32 {
+            CALLS += 1;
+            return CALLS;
+        }
+        func inner() => i32 { return count() + count(); }
+        let x = 1;
+        func read() => i32 { return x; }
+        func shadowed() => i32 {
+            let x = 2;
+            return read();
+        }",
+    )?;
+    engine.run(&program)?;
+    assert_eq!(engine.call::<i32>("inner", ())?, 3);
+    assert_eq!(engine.get_global::<i32>("CALLS")?, 2);
+    // the callee sees the global, not the caller's local
+    assert_eq!(engine.call::<i32>("shadowed", ())?, 1);
+
+    assert!(
+        engine
+            .compile("func f() { static X: i32 = 1; }")
+            .is_err_and(|e| e.to_string().contains("file scope"))
+    );
+
+    Ok(())
+}
+
+impl_test!(enums, |x| {
+    matches!(
+        x,
+        "12 9 0\nzero digit small large negative\nno number, number 12\ngreeting, word there\nwrapped nothing, wrapped 2 #3\nyes\n"
+    )
+});
+
+#[test]
+fn enums_interpreter() -> anyhow::Result<()> {
+    let mut engine = lang::Engine::new();
+    engine.capture_output();
+    let program = engine.compile_file("tests/enums.lang")?;
+    engine.run(&program)?;
+    assert_eq!(engine.call::<String>("classify", (150i32,))?, "large");
+    assert_eq!(engine.call::<String>("classify", (99i32,))?, "small");
+
+    let program = engine.compile(
+        "enum Op { Add(i64, i64), Neg(i64) }
+        func eval() => i64 {
+            let op = Op::Neg(7);
+            let same = op == op;
+            return 0;
+        }",
+    );
+    assert!(program.is_err_and(|e| e.to_string().contains("enum operands")));
+
+    let program = engine.compile(
+        "enum Op { Add(i64, i64), Neg(i64) }
+        func eval(bool add) => i64 {
+            let op = if add { Op::Add(40, 2) } else { Op::Neg(7) };
+            return match op {
+                Op::Add(a, b) => a + b,
+                Op::Neg(a) => -a,
+            };
+        }",
+    )?;
+    engine.run(&program)?;
+    assert_eq!(engine.call::<i64>("eval", (true,))?, 42);
+    assert_eq!(engine.call::<i64>("eval", (false,))?, -7);
+
+    Ok(())
+}
+
+#[test]
+fn match_errors() -> anyhow::Result<()> {
+    let engine = lang::Engine::new();
+    let error = |source: &str| match engine.compile(source) {
+        Ok(_) => String::new(),
+        Err(e) => e.to_string(),
+    };
+    let shape = "enum Shape { Circle(f64), Rect(f64, f64), Empty }";
+
+    assert!(
+        error(&format!(
+            "{shape} func f(Shape s) => i32 {{ return match s {{ Shape::Circle(_) => 1, Shape::Empty => 2 }}; }}"
+        ))
+        .contains("not exhaustive")
+    );
+    assert!(
+        error(&format!(
+            "{shape} func f(Shape s) => i32 {{ return match s {{ _ => 1, Shape::Empty => 2 }}; }}"
+        ))
+        .contains("Unreachable match arm")
+    );
+    assert!(
+        error("func f(u8 x) => i32 { return match x { 0..=127 => 1, 128..=255 => 2 }; }")
+            .is_empty()
+    );
+    assert!(
+        error("func f(u8 x) => i32 { return match x { 0..=127 => 1, 129..=255 => 2 }; }")
+            .contains("not exhaustive")
+    );
+    assert!(
+        error("func f(i32 x) => i32 { return match x { 0..10 => 1, 3..=5 => 2, _ => 3 }; }")
+            .contains("Unreachable match arm")
+    );
+    assert!(
+        error("func f(bool b) => i32 { return match b { true => 1, false => 2, _ => 3 }; }")
+            .contains("Unreachable match arm")
+    );
+    assert!(
+        error(&format!(
+            "{shape} func f(Shape s) => i32 {{ return match s {{ Shape::Circle(r) => 1, _ => 2.5 }}; }}"
+        ))
+        .contains("Type mismatch in match arms")
+    );
+    assert!(
+        error(&format!("{shape} func f() {{ let s = Shape::Rect(1); }}"))
+            .contains("takes 2 values")
+    );
+    assert!(
+        error(&format!("{shape} func f() {{ let s = Shape::Square; }}")).contains("no variant")
+    );
+    assert!(
+        error("func f(String s) => i32 { return match s { \"a\" => 1 }; }")
+            .contains("not exhaustive")
+    );
+
+    Ok(())
+}
+
+impl_test!(switch, |x| {
+    matches!(
+        x,
+        "1 2 3 0\n10 20 30 42\n0 1\nUnknown operator ?\n42 -2 0\nzero other two other \n"
+    )
+});
+
+/// The LLVM IR generated for `source`.
+fn compile_ir(source: &str) -> anyhow::Result<String> {
+    let mut lexer = lang::lexer::Lexer::new(source.to_string());
+    lexer.tokenize()?;
+    let mut parser = lang::parser::Parser::new(lexer.tokens().clone());
+    parser.parse()?;
+    lang::sema::SymbolTable::new().build(&mut parser.global_scope)?;
+    let context = inkwell::context::Context::create();
+    let module = lang::codegen::CodeGen::new(&context).generate(&parser.global_scope)?;
+    Ok(module.print_to_string().to_string())
+}
+
+#[test]
+fn switch_lowering() -> anyhow::Result<()> {
+    let ir = compile_ir(&std::fs::read_to_string("tests/switch.lang")?)?;
+    assert_eq!(ir.matches("switch i8").count(), 3);
+    assert!(ir.contains("switch i64"));
+    assert!(ir.contains("switch i32"));
+
+    // too large to list the values of the range
+    let ir = compile_ir("func f(i32 x) => i32 { return match x { 0..1000 => 1, _ => 2 }; }")?;
+    assert!(!ir.contains("switch"));
+
+    let mut engine = lang::Engine::new();
+    let program = engine.compile_file("tests/switch.lang")?;
+    engine.run(&program)?;
+    assert_eq!(engine.call::<i32>("kind", (b'5',))?, 1);
+    assert_eq!(engine.call::<i64>("shift", (-1i64,))?, 10);
+    assert_eq!(engine.call::<i32>("half", (255u8,))?, 1);
+    assert_eq!(engine.call::<f64>("calculate", (6.0, b'*', 7.0))?, 42.0);
+
+    let error = |source: &str| match engine.compile(source) {
+        Ok(_) => String::new(),
+        Err(e) => e.to_string(),
+    };
+    assert!(
+        error("func f(char c) => i32 { return match c { 'a' => 1, 'b' => 2, 'a' => 3, _ => 4 }; }")
+            .contains("Duplicate case 'a'")
+    );
+    assert!(
+        error("func f(i32 x) => i32 { return match x { -5 => 1, -5 => 2, _ => 3 }; }")
+            .contains("Duplicate case -5")
+    );
+
+    Ok(())
+}
+
+impl_test!(generics, |x| {
+    matches!(
+        x,
+        "7 2.5 122\n10 0 5\n2 1 right left\n81 0.125\n(1, one) (true, 2.5)\n"
+    )
+});
+
+#[test]
+fn generics_interpreter() -> anyhow::Result<()> {
+    let mut engine = lang::Engine::new();
+    engine.capture_output();
+    let program = engine.compile_file("tests/generics.lang")?;
+    engine.run(&program)?;
+    assert_eq!(engine.call::<i32>("max", (3i32, 9i32))?, 9);
+    assert_eq!(engine.call::<f64>("max", (2.5f64, -1.0f64))?, 2.5);
+    assert_eq!(
+        engine.call::<String>("pair", (1i32, String::from("one")))?,
+        "(1, one)"
+    );
+
+    let ir = compile_ir(&std::fs::read_to_string("tests/generics.lang")?)?;
+    for instance in [
+        "max<i32>",
+        "max<f64>",
+        "max<char>",
+        "clamp<i64>",
+        "power<f64>",
+    ] {
+        assert!(ir.contains(&format!("@\"{instance}\"(")));
+    }
+    assert!(ir.contains("@\"pair<bool, f64>\"("));
+    assert!(!ir.contains("@max("));
+
+    let error = |source: &str| match engine.compile(source) {
+        Ok(_) => String::new(),
+        Err(e) => e.to_string(),
+    };
+    let max = "func max<T>(T a, T b) => T { if a > b { return a; } return b; }";
+    assert!(
+        error(&format!("{max} func f() {{ let x = max(1u8, 2.5f32); }}"))
+            .contains("'T' is both u8 and f32")
+    );
+    // literals take the type of the other arguments
+    assert!(error(&format!("{max} func f() {{ let x = max(1, 2.5f32); }}")).is_empty());
+    assert!(
+        error(&format!("{max} func f() {{ let x = max(\"a\", \"b\"); }}"))
+            .contains("Operator Greater is not supported for String")
+    );
+    assert!(
+        error(&format!(
+            "{max} func g<U>(U a) => U {{ return max(a, a); }} func f() {{ let x = g(true); }}"
+        ))
+        .contains("Operator Greater is not supported for bool")
+    );
+    assert!(error(&format!("{max} func f() {{ let x = max('a', 'b'); }}")).is_empty());
+    assert!(
+        error("func add<T>(T a, T b) => T { return a + b; } func f() { let c = add('a', 'b'); }")
+            .contains("Operator Plus is not supported for char")
+    );
+    assert!(error("func f() => bool { return true < false; }").contains("not supported for bool"));
+    assert!(
+        error(&format!(
+            "{max} func g<U>(U a) => U {{ return max(a, a); }} enum E {{ A }} func f() {{ let x = g(E::A); }}"
+        ))
+        .contains("not supported for E")
+    );
+    assert!(
+        error("func hex<T>(T x) => String { return \"{x:x}\"; } func f() { let s = hex(1.5); }")
+            .contains("in call to 'hex'")
+    );
+    assert!(
+        error("func zero<T>() => i32 { return 0; } func f() { let x = zero(); }")
+            .contains("Cannot infer type parameter 'T'")
+    );
+    // a concrete value isn't a `T`
+    assert!(
+        error("func zero<T>() => T { return 0; }")
+            .contains("Type mismatch in return at Location { line: 1, column: 28")
+    );
+
+    // nested calls bind the type parameter of each call on its own
+    let nested = "func id<T>(T x) => T { return x; }
+        func twice<T>(T x) => T { return id(id(x)); }
+        func nested() => i32 { return id(id(3)) + twice(4); }";
+    let program = engine.compile(nested)?;
+    engine.run(&program)?;
+    assert_eq!(engine.call::<i32>("nested", ())?, 7);
+    assert!(compile_ir(nested)?.contains("@\"twice<i32>\"("));
+
+    Ok(())
+}
+
+impl_test!(function_pointers, |x| {
+    matches!(x, "10 49\n25 20 81\ncfinnotu utonnifc\n")
+});
+
+#[test]
+fn function_values() -> anyhow::Result<()> {
+    let mut engine = lang::Engine::new();
+    let program = engine.compile(
+        "func double(i32 x) => i32 { return x * 2; }
+        func square(i32 x) => i32 { return x * x; }
+        func twice<T>(func(T) => T f, T x) => T { return f(f(x)); }
+        func run(bool squared) => i32 {
+            let f: func(i32) => i32 = if squared { square } else { double };
+            return f(3) + twice(f, 1);
+        }",
+    )?;
+    engine.run(&program)?;
+    assert_eq!(engine.call::<i32>("run", (true,))?, 10);
+    assert_eq!(engine.call::<i32>("run", (false,))?, 10);
+
+    let error = |source: &str| match engine.compile(source) {
+        Ok(_) => String::new(),
+        Err(e) => e.to_string(),
+    };
+    let double = "func double(i32 x) => i32 { return x * 2; }";
+    assert!(
+        error(&format!(
+            "{double} func f() {{ let g = double; let x = g(1, 2); }}"
+        ))
+        .contains("takes 1 arguments but 2 were given")
+    );
+    assert!(
+        error(&format!(
+            "{double} func f() {{ let g = double; let x = g(true); }}"
+        ))
+        .contains("expected i32, found bool")
+    );
+    assert!(
+        error("func f() { let x = 3; let y = x(1); }").contains("Cannot call a value of type i32")
+    );
+    assert!(error("func f() { let g = len; }").contains("cannot be used as a value"));
+    assert!(
+        error(&format!(
+            "{double} func f() {{ let same = double == double; }}"
+        ))
+        .contains("function operands")
+    );
+    assert!(
+        error(&format!("{double} func f() {{ double = double; }}"))
+            .contains("Cannot assign to function")
+    );
+    // the whole signature has to match, arity included
+    let add = "func add(i32 a, i32 b) => i32 { return a + b; }";
+    assert!(
+        error(&format!(
+            "{add} func f() {{ let g: func(i64, i64) => i64 = add; }}"
+        ))
+        .contains("declared as func(i64, i64) => i64, assigned func(i32, i32) => i32")
+    );
+    assert!(
+        error(&format!(
+            "{add} func f() {{ let g: func(f64) => f64 = add; }}"
+        ))
+        .contains("declared as func(f64) => f64")
+    );
+    assert!(
+        error(&format!(
+            "{add} func pick() => func(i32) => i32 {{ return add; }}"
+        ))
+        .contains("returns func(i32) => i32, found func(i32, i32) => i32")
+    );
+    assert!(
+        error(&format!(
+            "{add} {double} func f() {{ let mut g = double; g = add; }}"
+        ))
+        .contains("Type mismatch in assignment")
+    );
+    assert!(
+        error(&format!(
+            "{add} func apply<T>(func(i32) => i32 f, T x) => T {{ return x; }}
+            func f() {{ let x = apply(add, 1); }}"
+        ))
+        .contains("Expected func(i32) => i32, found func(i32, i32) => i32")
+    );
+
+    Ok(())
+}
+
+impl_test!(closures, |x| {
+    matches!(x, "11 15\n6 33\ntick 0 tick 1 tick 2 \n12 42\n")
+});
+
+#[test]
+fn closures_interpreter() -> anyhow::Result<()> {
+    let mut engine = lang::Engine::new();
+    let program = engine.compile(
+        "func apply(|i32| => i32 f, i32 x) => i32 { return f(x); }
+        func run(i32 offset) => i32 {
+            let mut total = 0;
+            let add = |i32 x| => i32 { x + offset };
+            total = apply(add, 1);
+            offset = 0;
+            let nested = |i32 x| => i32 {
+                let inner = |i32 y| => i32 { x * y + offset };
+                inner(x)
+            };
+            return total + nested(3);
+        }",
+    );
+    assert!(program.is_err_and(|e| e.to_string().contains("Cannot assign to parameter")));
+
+    let program = engine.compile(
+        "func apply(|i32| => i32 f, i32 x) => i32 { return f(x); }
+        func run(i32 offset) => i32 {
+            let add = |i32 x| => i32 { x + offset };
+            let nested = |i32 x| => i32 {
+                let inner = |i32 y| => i32 { x * y + offset };
+                inner(x)
+            };
+            return apply(add, 1) + nested(3);
+        }",
+    )?;
+    engine.run(&program)?;
+    assert_eq!(engine.call::<i32>("run", (10i32,))?, 11 + 19);
+
+    let error = |source: &str| match engine.compile(source) {
+        Ok(_) => String::new(),
+        Err(e) => e.to_string(),
+    };
+    assert!(
+        error("func f(i32 n) => |i32| => i32 { return |i32 x| => i32 { x + n }; }")
+            .contains("don't outlive the function")
+    );
+    assert!(
+        error(
+            "func f(i32 n) => |i32| => i32 { let g = |i32 x| => i32 { x + n }; let h = g; return h; }"
+        )
+        .contains("don't outlive the function")
+    );
+    assert!(error("func f(|i32| => i32 g) => |i32| => i32 { return g; }").is_empty());
+    assert!(
+        error(
+            "let mut saved = |i32 x| => i32 { x };
+            func f(i32 n) { saved = |i32 x| => i32 { x + n }; }"
+        )
+        .contains("don't live as long as the target")
+    );
+    assert!(
+        error(
+            "func f(i32 n) {
+                let mut g = |i32 x| => i32 { x };
+                { let m = n; g = |i32 x| => i32 { x + m }; }
+            }"
+        )
+        .is_empty()
+    );
+    assert!(
+        error("func f() { let mut n = 1; let g = |i32 x| { n = x; }; }")
+            .contains("Cannot assign to captured variable 'n'")
+    );
+    assert!(
+        error("func f() { let g = |i32 x| => i32 { x }; let y = g(true); }")
+            .contains("expected i32, found bool")
+    );
+    assert!(
+        error("func f() { let g = |i32 x| => i32 { true }; }").contains("Type mismatch in lambda")
+    );
+    assert!(
+        error(
+            "func apply(func(i32) => i32 f, i32 x) => i32 { return f(x); }
+            func g(i32 n) => i32 { return apply(|i32 x| => i32 { x + n }, 1); }"
+        )
+        .contains("expected func(i32) => i32, found |i32| => i32")
+    );
+    assert!(error("extern qsort(|i32| f);").contains("can't take or return closures"));
+
+    let ir = compile_ir(&std::fs::read_to_string("tests/closures.lang")?)?;
+    assert!(ir.contains("define private i32 @main.lambda("));
+    assert!(ir.contains("%captures = alloca { i32 }"));
+
+    Ok(())
+}
+
+impl_test!(methods, |x| matches!(x, "4 3 0\n9 area 9\n36 36\n3 3\n"));
+
+#[test]
+fn methods_interpreter() -> anyhow::Result<()> {
+    let mut engine = lang::Engine::new();
+    engine.capture_output();
+    let program = engine.compile(
+        "enum Counter { Count(i32) }
+        impl Counter {
+            func new(i32 n) => Counter { return Counter::Count(n); }
+            func get(self) => i32 { return match self { Counter::Count(n) => n }; }
+            func add(*self, i32 n) { *self = Counter::Count(self.get() + n); }
+            func bump(*self) => i32 { self.add(1); return self.get(); }
+        }
+        func bump_twice(*Counter counter) { counter.bump(); counter.bump(); }
+        func main() => i32 {
+            let mut counter = Counter::new(10);
+            print(\"{counter.bump()} {counter.get()}\");
+            bump_twice(&counter);
+            counter.add(5);
+            return counter.get();
+        }",
+    )?;
+    engine.run(&program)?;
+    assert_eq!(engine.call::<i32>("main", ())?, 18);
+    assert_eq!(engine.take_output(), "11 11\n");
+
+    // pointers to variables write to the variable, also outside of calls
+    let program = engine.compile(
+        "enum Shape { Square(i32), Rect(i32, i32) }
+        func area(*Shape shape) => i32 {
+            return match *shape { Shape::Square(n) => n * n, Shape::Rect(w, h) => w * h };
+        }
+        func reshape() => i32 {
+            let mut shape = Shape::Square(3);
+            let p = &shape;
+            let mut n = area(p);
+            *p = Shape::Rect(2, 5);
+            let q = &n;
+            *q = *q + area(&shape);
+            return n;
+        }
+        func release() { let mut n = 1; free(&n); }",
+    )?;
+    engine.run(&program)?;
+    assert_eq!(engine.call::<i32>("reshape", ())?, 19);
+    let err = engine.call::<()>("release", ()).unwrap_err();
+    assert!(format!("{err:#}").contains("it is a variable"));
+
+    let ir = compile_ir(&std::fs::read_to_string("tests/methods.lang")?)?;
+    assert!(ir.contains("define %Shape @\"Shape::square\"(double %0)"));
+    assert!(ir.contains("define double @\"Shape::area\"(%Shape %0)"));
+    assert!(ir.contains("define void @\"Shape::scale\"(ptr %0, double %1)"));
+
+    let error = |source: &str| match engine.compile(source) {
+        Ok(_) => String::new(),
+        Err(e) => e.to_string(),
+    };
+    let shape = "enum Shape { Circle(f64), Empty }
+        impl Shape {
+            func unit() => Shape { return Shape::Circle(1); }
+            func area(self) => f64 { return 1; }
+            func clear(*self) { *self = Shape::Empty; }
+        }";
+    assert!(
+        error(&format!(
+            "{shape} func f() {{ let s = Shape::unit(); s.clear(); }}"
+        ))
+        .contains("Cannot assign to immutable variable 's'")
+    );
+    assert!(
+        error(&format!("{shape} func f() {{ Shape::unit().clear(); }}"))
+            .contains("takes *self, so it has to be called on a variable")
+    );
+    assert!(
+        error(&format!(
+            "{shape} func f() {{ let s = Shape::unit(); s.perimeter(); }}"
+        ))
+        .contains("Type Shape has no method 'perimeter'")
+    );
+    assert!(
+        error(&format!(
+            "{shape} func f() {{ let s = Shape::unit(); s.unit(); }}"
+        ))
+        .contains("'Shape::unit' at")
+    );
+    assert!(
+        error(&format!("{shape} func f() {{ let x = 1; x.area(); }}"))
+            .contains("Type i32 has no method 'area'")
+    );
+    assert!(
+        error("enum Shape { Empty } impl Shape { func Empty() {} }")
+            .contains("Method 'Shape::Empty'")
+    );
+    assert!(error("impl Point { func origin() {} }").contains("unknown type 'Point'"));
+    assert!(
+        error("enum Shape { Empty } impl Shape { func f(i32 self) {} }")
+            .contains("'self' can only be the first parameter of a method")
+    );
+
+    Ok(())
+}
+
+impl_test!(heap, |x| matches!(x, "4 16 30\n1 1 9\n-1 1\n2.5 h\n"));
+
+#[test]
+fn heap_interpreter() -> anyhow::Result<()> {
+    use lang::interpreter::RuntimeError;
+
+    let mut engine = lang::Engine::new();
+    engine.capture_output();
+    let program = engine.compile(
+        "func leak() => *i32 {
+            let p: *i32 = alloc();
+            *p = 7;
+            return p;
+        }
+        func main() => i32 {
+            let values = alloc_array<i32>(3us);
+            values[0] = 1;
+            *(values + 1) = 2;
+            values[2] = values[0] + values[1];
+            let last = values + 2;
+            print(\"{*last} {last > values} {values != null}\");
+            free(values);
+            free(null);
+            return *leak();
+        }
+        func double_free() { let p = alloc<i32>(); free(p); free(p); }
+        func use_after_free() => i32 { let p = alloc<i32>(); *p = 1; free(p); return *p; }
+        func uninitialized() => i32 { let p = alloc_array<i32>(2us); return p[1]; }
+        func out_of_bounds() { let p = alloc_array<i32>(2us); p[2] = 1; }
+        func null_deref() => i32 { let p: *i32 = null; return *p; }
+        func null_index() => i32 { let p: *i32 = null; return p[0]; }
+        func compare_blocks() => bool {
+            let a = alloc<i32>(); let b = alloc<i32>();
+            let less = a < b; free(a); free(b);
+            return less;
+        }
+        func huge() { let p = alloc_array<i32>(1152921504606846976us); free(p); }",
+    )?;
+    engine.run(&program)?;
+    assert!(engine.leaks().is_empty());
+    assert_eq!(engine.call::<i32>("main", ())?, 7);
+    assert_eq!(engine.take_output(), "3 true true\n");
+    let leaks = engine.leaks();
+    assert_eq!(leaks.len(), 1);
+    assert_eq!(leaks[0].to_string(), "1 x i32 allocated in leak at 2:15");
+
+    let message = |engine: &mut lang::Engine, name: &str| {
+        let err = engine.call::<i32>(name, ()).unwrap_err();
+        err.downcast_ref::<RuntimeError>().unwrap().message.clone()
+    };
+    assert!(message(&mut engine, "double_free").starts_with("Double free of 1 x i32"));
+    assert!(message(&mut engine, "use_after_free").starts_with("Use after free of 1 x i32"));
+    assert!(message(&mut engine, "uninitialized").starts_with("Read of uninitialized value 1"));
+    assert!(message(&mut engine, "out_of_bounds").starts_with("Value 2 is out of bounds"));
+    assert!(message(&mut engine, "huge").starts_with("Out of memory allocating"));
+    assert_eq!(
+        message(&mut engine, "null_deref"),
+        "Null pointer dereference"
+    );
+    assert_eq!(
+        message(&mut engine, "null_index"),
+        "Null pointer dereference"
+    );
+    let err = engine.call::<bool>("compare_blocks", ()).unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<RuntimeError>().unwrap().message,
+        "Cannot order pointers into different allocations with 'Less'"
+    );
+
+    let ir = compile_ir(&std::fs::read_to_string("tests/heap.lang")?)?;
+    assert!(ir.contains("call ptr @malloc("));
+    assert!(ir.contains("call void @free(ptr"));
+    assert!(ir.contains("getelementptr i32, ptr"));
+
+    let error = |source: &str| match engine.compile(source) {
+        Ok(_) => String::new(),
+        Err(e) => e.to_string(),
+    };
+    assert!(
+        error("func f() { let p = alloc<i32>(); let q = p + 1.5; }")
+            .contains("must be an integer, found f64")
+    );
+    assert!(error("func f() { let p = null; let x = *p; }").contains("pointer to void"));
+    assert!(error("func f() { let p = alloc<i32, i32>(); }").contains("takes 1 type arguments"));
+    assert!(error("func g() {} func f() { g<i32>(); }").contains("doesn't take type arguments"));
+
+    Ok(())
+}
+
+#[test]
+fn managed() -> anyhow::Result<()> {
+    use lang::heap::Memory;
+
+    crate::run_file("tests/managed.lang", Memory::Managed, false)?;
+    let result = std::process::Command::new("build/managed").output()?;
+    anyhow::ensure!(result.status.success(), "Exited with {}", result.status);
+    assert_eq!(
+        String::from_utf8(result.stdout)?,
+        "20000 42 0 19999 is formatted into a new String! 1\n"
+    );
+
+    let ir = std::fs::read_to_string("build/managed.ll")?;
+    assert!(ir.contains("%alloc = call ptr @lang_gc_alloc(i64 mul"));
+    assert!(ir.contains("%fmtbuf = call ptr @lang_gc_alloc(i64 %fmtsize)"));
+    assert!(ir.contains("call void @lang_gc_root(ptr @kept"));
+    assert!(!ir.contains("@malloc"));
+    assert!(!ir.contains("@free"));
+
+    // the loop reuses the slot of `garbage` instead of growing the stack
+    let entry = ir.split("define i32 @main()").nth(1).unwrap();
+    let entry = &entry[..entry.find("\n\n").unwrap()];
+    assert!(entry.contains("%garbage = alloca ptr"));
+
+    Ok(())
+}
+
+#[test]
+fn managed_interpreter() -> anyhow::Result<()> {
+    use lang::heap::Memory;
+
+    let mut engine = lang::Engine::new();
+    engine.set_memory(Memory::Managed);
+    let program = engine.compile(
+        "enum Node { Link(i32, *Node), End }
+        let kept = alloc<i32>();
+        func cycle(i32 n) => i32 {
+            let a = alloc<Node>();
+            let b = alloc<Node>();
+            *a = Node::Link(n, b);
+            *b = Node::Link(n + 1, a);
+            return match *b { Node::Link(v, _) => v, Node::End => 0 };
+        }
+        func main() => i32 {
+            *kept = 5;
+            let mut total = 0;
+            let mut i = 0;
+            while i < 1000 {
+                let garbage = alloc_array<i64>(100us);
+                garbage[99] = 1i64;
+                total += cycle(i) - i;
+                i += 1;
+            }
+            return total + *kept;
+        }",
+    )?;
+    engine.run(&program)?;
+    assert_eq!(engine.call::<i32>("main", ())?, 1005);
+    // only `kept` is reachable, the cycles were collected
+    assert_eq!(engine.live_allocations(), 1);
+    assert!(engine.leaks().is_empty());
+
+    let err = engine.compile("func f() { let p = alloc<i32>(); free(p); }");
+    assert!(
+        err.unwrap_err()
+            .to_string()
+            .contains("isn't available with managed memory")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn overflow_checks() -> anyhow::Result<()> {
+    use lang::heap::Memory;
+
+    let expected = "-2147483648 2147483647 0\n\
+                    2147483647 -2147483648 0\n\
+                    -2147483648 2147483647 4294967295\n\
+                    -2 -9223372036854775808\n";
+
+    // without checks the multiplication wraps around
+    crate::run_file("tests/overflow.lang", Memory::Manual, false)?;
+    let result = std::process::Command::new("build/overflow").output()?;
+    assert!(result.status.success());
+    assert_eq!(
+        String::from_utf8(result.stdout)?,
+        format!("{expected}-2147479015\n")
+    );
+
+    crate::run_file("tests/overflow.lang", Memory::Manual, true)?;
+    let result = std::process::Command::new("build/overflow").output()?;
+    assert!(!result.status.success());
+    assert_eq!(String::from_utf8(result.stdout)?, expected);
+    assert_eq!(
+        String::from_utf8(result.stderr)?,
+        "Runtime error: Arithmetic overflow\n    at square (tests/overflow.lang:8:10)\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn division_checks() -> anyhow::Result<()> {
+    use lang::heap::Memory;
+
+    crate::run_file("tests/division.lang", Memory::Manual, true)?;
+    for (case, message, location) in [
+        (
+            "0",
+            "Arithmetic overflow",
+            "divide (tests/division.lang:6:10)",
+        ),
+        (
+            "1",
+            "Division by zero",
+            "remainder (tests/division.lang:10:10)",
+        ),
+        (
+            "2",
+            "Shift amount is out of range",
+            "shift (tests/division.lang:14:10)",
+        ),
+    ] {
+        let result = std::process::Command::new("build/division")
+            .env("CASE", case)
+            .output()?;
+        assert!(!result.status.success());
+        assert_eq!(String::from_utf8(result.stdout)?, "-3 -1 16\n");
+        assert_eq!(
+            String::from_utf8(result.stderr)?,
+            format!("Runtime error: {message}\n    at {location}\n")
+        );
+    }
+
+    // the interpreter checks the same
+    let mut engine = lang::Engine::new();
+    let program = engine.compile_file("tests/division.lang")?;
+    engine.run(&program)?;
+    let message = |engine: &mut lang::Engine, name: &str, args: (i32, i32)| {
+        format!("{:#}", engine.call::<i32>(name, args).unwrap_err())
+    };
+    assert!(message(&mut engine, "divide", (i32::MIN, -1)).contains("Arithmetic overflow"));
+    assert!(message(&mut engine, "remainder", (1, 0)).contains("Division by zero"));
+    assert!(message(&mut engine, "shift", (1, 40)).contains("Shift amount 40 is out of range"));
+    Ok(())
+}
+
+#[test]
+fn overflow_check_locations() -> anyhow::Result<()> {
+    let mut lexer = lang::lexer::Lexer::new(
+        "func double<T>(T x) => T {
+            return wrapping_add(x, x);
+        }
+        func f(i32 x) => i32 {
+            let twice = |i32 y| => i32 { y + y };
+            return double(x) * twice(x) * x / x << x;
+        }"
+        .to_string(),
+    );
+    lexer.tokenize()?;
+    let mut parser = lang::parser::Parser::new(lexer.tokens().clone());
+    parser.parse()?;
+    lang::sema::SymbolTable::new().build(&mut parser.global_scope)?;
+    let context = inkwell::context::Context::create();
+    let mut codegen = lang::codegen::CodeGen::new(&context);
+    codegen.set_overflow_checks(true);
+    let ir = codegen
+        .generate(&parser.global_scope)?
+        .print_to_string()
+        .to_string();
+
+    // the multiplications are reported where they are, not in the lambda or
+    // the instance of `double` compiled before them
+    let panics: Vec<&str> = ir
+        .lines()
+        .filter(|x| x.contains("call void @lang_panic"))
+        .collect();
+    // two multiplications, a division by zero or of MIN by -1 and a shift
+    assert_eq!(panics.len(), 6);
+    assert_eq!(
+        panics.iter().filter(|x| x.contains("i32 5, i32")).count(),
+        1
+    );
+    assert_eq!(
+        panics.iter().filter(|x| x.contains("i32 6, i32")).count(),
+        5
+    );
+    Ok(())
+}
+
+#[test]
+fn overflow_interpreter() -> anyhow::Result<()> {
+    let mut engine = lang::Engine::new();
+    let program = engine.compile(
+        "func wrapped() => i32 {
+            return wrapping_add(2147483647, 1) - saturating_sub(-2147483647, 5);
+        }
+        func saturated() => u8 {
+            return saturating_mul(100u8, 3u8) - wrapping_sub(0u8, 1u8);
+        }
+        func overflow(i64 x) => i64 {
+            return x * 4i64;
+        }",
+    )?;
+    engine.run(&program)?;
+    assert_eq!(engine.call::<i32>("wrapped", ())?, 0);
+    assert_eq!(engine.call::<u8>("saturated", ())?, 0);
+    let err = engine.call::<i64>("overflow", (i64::MAX / 2,)).unwrap_err();
+    assert!(format!("{err:#}").contains("Arithmetic overflow"));
+
+    let err = engine.compile("func f() => f64 { return wrapping_add(1.5, 2.5); }");
+    assert!(
+        err.unwrap_err()
+            .to_string()
+            .contains("takes integers, found f64")
+    );
+    // generic functions pass the requirement on to their type arguments
+    let err = engine.compile(
+        "func double<T>(T x) => T { return wrapping_add(x, x); }
+        func f() => f64 { return double(1.5); }",
+    );
+    assert!(
+        err.unwrap_err()
+            .to_string()
+            .contains("takes integers, found f64")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn if_expression_control_flow() -> anyhow::Result<()> {
+    let mut engine = lang::Engine::new();
+    let program = engine.compile(
+        "func pick(i32 a, i32 b) => i32 {
+            let found = if a < 0 { return a; 0 } else if b < 0 { b } else { 0 };
+            return found + 100;
+        }
+        func count() => i32 {
+            let mut i = 0;
+            while true {
+                i += if i == 5 { break; 0 } else { 1 };
+            }
+            return i;
+        }",
+    )?;
+    engine.run(&program)?;
+    assert_eq!(engine.call::<i32>("pick", (-1, 2))?, -1);
+    assert_eq!(engine.call::<i32>("pick", (1, -2))?, 98);
+    assert_eq!(engine.call::<i32>("count", ())?, 5);
+
+    Ok(())
+}
+
+#[test]
+fn unsigned_operations() -> anyhow::Result<()> {
+    let ir = compile_ir(
+        "func f(u32 a, u32 b) => bool {
+            let c = a >> 1u32;
+            let d = a / b + a % b;
+            return c < d;
+        }
+        func g(String s) => i64 {
+            return parse_int(s) >> 1i64;
+        }",
+    )?;
+    assert!(ir.contains("lshr i32"));
+    assert!(ir.contains("udiv i32"));
+    assert!(ir.contains("urem i32"));
+    assert!(ir.contains("icmp ult i32"));
+    assert!(ir.contains("ashr i64"));
+    assert!(!ir.contains("sdiv"));
+
+    Ok(())
+}
@@ -23,16 +23,155 @@ pub enum TokenType {
     Literal(Literal),
     Operator(Operator),
     Delimiter(Delimiter),
+    /// A string literal containing `{expr}` interpolations.
+    Interpolated(Vec<StringPart>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StringPart {
+    Text(String),
+    Expr {
+        tokens: Vec<Token>,
+        spec: FormatSpec,
+    },
+}
+
+/// The `:spec` of an interpolation, `[<|>][0][width][.precision][x|X|o|e]`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FormatSpec {
+    pub left_align: bool,
+    pub zero_pad: bool,
+    pub width: Option<usize>,
+    pub precision: Option<usize>,
+    pub kind: FormatKind,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FormatKind {
+    #[default]
+    Display,
+    LowerHex,
+    UpperHex,
+    Octal,
+    Exponent,
+}
+
+impl FormatSpec {
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        let mut result = FormatSpec::default();
+        let mut rest = spec;
+
+        if let Some(x) = rest.strip_prefix('<') {
+            result.left_align = true;
+            rest = x;
+        } else if let Some(x) = rest.strip_prefix('>') {
+            rest = x;
+        }
+        if let Some(x) = rest.strip_prefix('0') {
+            result.zero_pad = true;
+            rest = x;
+        }
+
+        let digits = rest.len() - rest.trim_start_matches(|x: char| x.is_ascii_digit()).len();
+        if digits > 0 {
+            result.width = Some(rest[..digits].parse()?);
+            rest = &rest[digits..];
+        }
+        if let Some(x) = rest.strip_prefix('.') {
+            let digits = x.len() - x.trim_start_matches(|x: char| x.is_ascii_digit()).len();
+            anyhow::ensure!(digits > 0, "Missing precision in format spec '{spec}'");
+            result.precision = Some(x[..digits].parse()?);
+            rest = &x[digits..];
+        }
+
+        result.kind = match rest {
+            "" => FormatKind::Display,
+            "x" => FormatKind::LowerHex,
+            "X" => FormatKind::UpperHex,
+            "o" => FormatKind::Octal,
+            "e" => FormatKind::Exponent,
+            _ => anyhow::bail!("Invalid format spec '{spec}'"),
+        };
+        Ok(result)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Literal {
-    Numeric(String),
+    Numeric(NumericLiteral),
     Char(char),
     String(String),
     Boolean(String),
 }
 
+/// A numeric literal, parsed and range checked by the lexer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumericLiteral {
+    pub value: NumericValue,
+    pub suffix: Option<NumericType>,
+}
+
+impl NumericLiteral {
+    /// The type of the literal, `i32` or `f64` when it has no suffix and its
+    /// type hasn't been inferred yet.
+    pub fn ty(&self) -> NumericType {
+        self.suffix.unwrap_or(match self.value {
+            NumericValue::Integer(_) => NumericType::I32,
+            NumericValue::Float(_) => NumericType::F64,
+        })
+    }
+
+    /// Gives an unsuffixed literal the type `ty`, checking that its value
+    /// fits. Integer literals can become floats but not the other way around,
+    /// such a literal keeps its default type.
+    pub fn infer(&mut self, ty: NumericType) -> anyhow::Result<()> {
+        if self.suffix.is_some() {
+            return Ok(());
+        }
+        match (self.value, ty.integer_range()) {
+            (NumericValue::Integer(value), Some((min, max))) => {
+                anyhow::ensure!(
+                    (min..=max).contains(&value),
+                    "Numeric literal '{value}' is out of range for {ty:?}"
+                );
+            }
+            (NumericValue::Integer(value), None) => {
+                self.value = NumericValue::Float(value as f64);
+            }
+            (NumericValue::Float(value), None) => {
+                anyhow::ensure!(
+                    ty != NumericType::F32 || (value as f32).is_finite(),
+                    "Numeric literal '{value}' is out of range for {ty:?}"
+                );
+            }
+            (NumericValue::Float(_), Some(_)) => return self.infer(NumericType::F64),
+        }
+        self.suffix = Some(ty);
+        Ok(())
+    }
+}
+
+/// Integer types always hold an `Integer` and float types a `Float`.
+#[derive(Debug, Clone, Copy)]
+pub enum NumericValue {
+    Integer(i128),
+    Float(f64),
+}
+
+/// Floats compare by their bits, so a NaN literal equals itself and `Eq`
+/// holds, while `0.0` and `-0.0` are different literals.
+impl PartialEq for NumericValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (NumericValue::Integer(a), NumericValue::Integer(b)) => a == b,
+            (NumericValue::Float(a), NumericValue::Float(b)) => a.to_bits() == b.to_bits(),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for NumericValue {}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum NumericType {
     I8,
@@ -50,33 +189,66 @@ pub enum NumericType {
 }
 
 impl NumericType {
-    pub fn from_literal(literal: &str) -> anyhow::Result<Option<Self>> {
-        let suffix = literal.split("_").last().unwrap();
-        if suffix.is_empty() {
-            Ok(None)
-        } else {
-            Ok(Some(match suffix {
-                "i8" => NumericType::I8,
-                "i16" => NumericType::I16,
-                "i32" => NumericType::I32,
-                "i64" => NumericType::I64,
-                "is" => NumericType::ISize,
-                "u8" => NumericType::U8,
-                "u16" => NumericType::U16,
-                "u32" => NumericType::U32,
-                "u64" => NumericType::U64,
-                "us" => NumericType::USize,
-                "f32" => NumericType::F32,
-                "f64" => NumericType::F64,
-                _ => anyhow::bail!("Unknown numeric type suffix '{}'", suffix),
-            }))
-        }
+    pub fn is_integer(&self) -> bool {
+        !matches!(self, NumericType::F32 | NumericType::F64)
+    }
+
+    pub fn is_signed(&self) -> bool {
+        matches!(
+            self,
+            NumericType::I8
+                | NumericType::I16
+                | NumericType::I32
+                | NumericType::I64
+                | NumericType::ISize
+                | NumericType::F32
+                | NumericType::F64
+        )
+    }
+
+    pub fn from_suffix(suffix: &str) -> anyhow::Result<Self> {
+        Ok(match suffix {
+            "i8" => NumericType::I8,
+            "i16" => NumericType::I16,
+            "i32" => NumericType::I32,
+            "i64" => NumericType::I64,
+            "is" => NumericType::ISize,
+            "u8" => NumericType::U8,
+            "u16" => NumericType::U16,
+            "u32" => NumericType::U32,
+            "u64" => NumericType::U64,
+            "us" => NumericType::USize,
+            "f32" => NumericType::F32,
+            "f64" => NumericType::F64,
+            _ => anyhow::bail!("Unknown numeric type suffix '{}'", suffix),
+        })
+    }
+
+    /// The smallest and largest value of an integer type.
+    pub fn integer_range(&self) -> Option<(i128, i128)> {
+        Some(match self {
+            NumericType::I8 => (i8::MIN as i128, i8::MAX as i128),
+            NumericType::I16 => (i16::MIN as i128, i16::MAX as i128),
+            NumericType::I32 => (i32::MIN as i128, i32::MAX as i128),
+            NumericType::I64 | NumericType::ISize => (i64::MIN as i128, i64::MAX as i128),
+            NumericType::U8 => (0, u8::MAX as i128),
+            NumericType::U16 => (0, u16::MAX as i128),
+            NumericType::U32 => (0, u32::MAX as i128),
+            NumericType::U64 | NumericType::USize => (0, u64::MAX as i128),
+            NumericType::F32 | NumericType::F64 => return None,
+        })
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Keyword {
     Let,
+    Mut,
+    Const,
+    Static,
+    Enum,
+    Impl,
+    Match,
     Func,
     Return,
     If,
@@ -86,6 +258,7 @@ pub enum Keyword {
     Break,
     Continue,
     Extern,
+    Null,
     // True,
     // False,
     Import,
@@ -110,6 +283,10 @@ pub enum Operator {
     Exclem,        // !
     Ampersand,     // &
     Pipe,          // |
+    Caret,         // ^
+    Tilde,         // ~
+    ShiftLeft,     // <<
+    ShiftRight,    // >>
     Assign,        // =
     AddAssign,     // +=
     SubAssign,     // -=
@@ -118,19 +295,24 @@ pub enum Operator {
     ModAssign,     // %=
     BitAndAssign,  // &=
     BitOrAssign,   // |=
+    BitXorAssign,  // ^=
+    ShlAssign,     // <<=
+    ShrAssign,     // >>=
 }
 
 impl Operator {
     pub fn rank(&self) -> u8 {
         match self {
-            Operator::Exclem => 9,
-            Operator::Asterisk | Operator::Slash | Operator::Percent => 8,
-            Operator::Plus | Operator::Minus => 7,
+            Operator::Exclem | Operator::Tilde => 11,
+            Operator::Asterisk | Operator::Slash | Operator::Percent => 10,
+            Operator::Plus | Operator::Minus => 9,
+            Operator::ShiftLeft | Operator::ShiftRight => 8,
             Operator::Greater | Operator::Less | Operator::GreaterEquals | Operator::LessEquals => {
-                6
+                7
             }
-            Operator::Equals | Operator::NotEquals => 5,
-            Operator::Ampersand => 4,
+            Operator::Equals | Operator::NotEquals => 6,
+            Operator::Ampersand => 5,
+            Operator::Caret => 4,
             Operator::Pipe => 3,
             Operator::LogicalAnd => 2,
             Operator::LogicalOr => 1,
@@ -141,16 +323,18 @@ impl Operator {
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Delimiter {
-    LBrace,    // {
-    RBrace,    // }
-    LParen,    // (
-    RParen,    // )
-    LBracket,  // [
-    RBracket,  // ]
-    Semicolon, // ;
-    Colon,     // :
-    Comma,     // ,
-    Dot,       // .
-    Arrow,     // =>
-    Variadic,  // ..
+    LBrace,         // {
+    RBrace,         // }
+    LParen,         // (
+    RParen,         // )
+    LBracket,       // [
+    RBracket,       // ]
+    Semicolon,      // ;
+    Colon,          // :
+    DoubleColon,    // ::
+    Comma,          // ,
+    Dot,            // .
+    Arrow,          // =>
+    Variadic,       // .., also an exclusive range in patterns
+    RangeInclusive, // ..=
 }